@@ -17,10 +17,11 @@ use serde::Serialize;
 use serde_json::Value;
 use std::env;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Read, Write};
+use std::io::{self, BufReader, Read, Write};
 use std::process::{Command, Stdio};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use vimscript_core::protocol::MessageReader;
 
 struct TeeReader<R: Read, W: Write> {
     reader: R,
@@ -46,76 +47,6 @@ where
     }
 }
 
-// Reads the content of the next message from given input.
-//
-// The input is expected to provide a message as described by "Base Protocol" of Language Server
-// Protocol.
-fn read_message<R: BufRead>(input: &mut R) -> Result<String, io::Error> {
-    // Read in the "Content-Length: xx" part.
-    let mut size: Option<usize> = None;
-    loop {
-        let mut buffer = String::new();
-        input.read_line(&mut buffer)?;
-
-        // End of input.
-        if buffer.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "EOF encountered in the middle of reading LSP headers",
-            ));
-        }
-
-        // Header section is finished, break from the loop.
-        if buffer == "\r\n" {
-            break;
-        }
-
-        let res: Vec<&str> = buffer.split(' ').collect();
-
-        // Make sure header is valid.
-        if res.len() != 2 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Header '{}' is malformed", buffer),
-            ));
-        }
-        let header_name = res[0].to_lowercase();
-        let header_value = res[1].trim();
-
-        match header_name.as_ref() {
-            "content-length:" => {
-                size = Some(usize::from_str_radix(header_value, 10).map_err(|_e| {
-                    io::Error::new(io::ErrorKind::InvalidData, "Couldn't read size")
-                })?);
-            }
-            "content-type:" => {
-                if header_value != "utf8" && header_value != "utf-8" {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        format!("Content type '{}' is invalid", header_value),
-                    ));
-                }
-            }
-            // Ignore unknown headers (specification doesn't say what to do in this case).
-            _ => (),
-        }
-    }
-    let size = match size {
-        Some(size) => size,
-        None => {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Message is missing 'content-length' header",
-            ));
-        }
-    };
-
-    let mut content = vec![0; size];
-    input.read_exact(&mut content)?;
-
-    String::from_utf8(content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-}
-
 #[derive(Serialize)]
 struct LspMessage {
     timestamp: String,
@@ -160,12 +91,14 @@ fn main() {
     let stdin_file = stdout_file.clone();
 
     let stdout_thread = thread::spawn(move || {
-        let mut reader = BufReader::new(TeeReader {
+        let mut reader = MessageReader::new(BufReader::new(TeeReader {
             reader: child_stdout,
             writer: io::stdout(),
-        });
+        }));
         loop {
-            let msg = read_message(&mut reader).expect("failed to read message from child stdout");
+            let msg = reader
+                .read_message()
+                .expect("failed to read message from child stdout");
             let msg: Value = serde_json::from_str(&msg).expect("failed to parse json rpc message");
             let msg = LspMessage {
                 timestamp: Utc::now().to_rfc3339(),
@@ -179,12 +112,14 @@ fn main() {
         }
     });
     let stdin_thread = thread::spawn(move || {
-        let mut reader = BufReader::new(TeeReader {
+        let mut reader = MessageReader::new(BufReader::new(TeeReader {
             reader: io::stdin(),
             writer: child_stdin,
-        });
+        }));
         loop {
-            let msg = read_message(&mut reader).expect("failed to read message from stdin");
+            let msg = reader
+                .read_message()
+                .expect("failed to read message from stdin");
             let msg: Value = serde_json::from_str(&msg).expect("failed to parse json rpc message");
             let msg = LspMessage {
                 timestamp: Utc::now().to_rfc3339(),
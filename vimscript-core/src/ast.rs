@@ -17,6 +17,7 @@ use crate::lexer::TokenType;
 use crate::span::Span;
 use serde::{Deserialize, Serialize, Serializer};
 use serde_json::json;
+use std::fmt;
 
 #[derive(PartialEq, Debug, Serialize, Deserialize, Clone, Copy)]
 pub struct NodeId(pub u32);
@@ -49,12 +50,17 @@ pub enum StmtKind {
     Set(SetStatement),
     Break(BreakStatement),
     Finish(FinishStatement),
+    Augroup(AugroupStatement),
+    Autocmd(AutocmdStatement),
+    Command(CommandStatement),
+    Map(MapStatement),
     Empty(),
 }
 
 impl StmtKind {
     pub fn dump_for_testing(&self) -> serde_json::Value {
         return match &self {
+            StmtKind::Let(x) if x.constant => json!({ "const": x.dump_for_testing() }),
             StmtKind::Let(x) => json!({ "let": x.dump_for_testing() }),
             StmtKind::If(x) => json!({ "if": x.dump_for_testing() }),
             StmtKind::Call(x) => json!({ "call": x.dump_for_testing() }),
@@ -65,6 +71,10 @@ impl StmtKind {
             StmtKind::Set(x) => json!({ "set": x.dump_for_testing() }),
             StmtKind::Break(x) => json!({ "break": x.dump_for_testing() }),
             StmtKind::Finish(x) => json!({ "finish": x.dump_for_testing() }),
+            StmtKind::Augroup(x) => json!({ "augroup": x.dump_for_testing() }),
+            StmtKind::Autocmd(x) => json!({ "autocmd": x.dump_for_testing() }),
+            StmtKind::Command(x) => json!({ "command": x.dump_for_testing() }),
+            StmtKind::Map(x) => json!({ "map": x.dump_for_testing() }),
             StmtKind::Empty() => json!("empty"),
             _ => json!({}),
         };
@@ -76,6 +86,8 @@ pub struct LetStatement {
     pub var: Box<Expr>,
     pub operator: TokenType,
     pub value: Box<Expr>,
+    // true if 'const'
+    pub constant: bool,
 }
 
 impl LetStatement {
@@ -128,27 +140,140 @@ pub struct ExecuteStatement {
 
 #[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct FunctionStatement {
-    pub name: String,
-    // TODO change to list of tokens?
-    pub arguments: Vec<String>,
+    pub name: FunctionName,
+    pub name_location: SourceLocation,
+    pub arguments: Vec<FunctionArgument>,
+    // true if the last argument is '...'
+    pub variadic: bool,
     pub body: Vec<Stmt>,
     // true if 'function!'
     pub overwrite: bool,
     pub abort: bool,
+    // true if 'dict', which is implied for functions defined on a dictionary
+    pub dict: bool,
 }
 
 impl FunctionStatement {
     pub fn dump_for_testing(&self) -> serde_json::Value {
         return json!({
             "name": self.name,
-            "arguments": self.arguments,
+            "arguments": self.arguments.iter().map(|a| a.dump_for_testing()).collect::<Vec<serde_json::Value>>(),
+            "variadic": self.variadic,
             "body": self.body.iter().map(|s| s.dump_for_testing()).collect::<Vec<serde_json::Value>>(),
             "overwrite": self.overwrite,
             "abort": self.abort,
+            "dict": self.dict,
         });
     }
 }
 
+/// Name of a defined function, e.g. `s:Format`, `plugin#util#trim` or `s:formatter.run`.
+#[derive(PartialEq, Debug, Clone, Deserialize)]
+#[serde(from = "String")]
+pub struct FunctionName {
+    /// Scope prefix, e.g. `s` for `s:Format`. `<SID>` is not a scope, see `sid`.
+    pub scope: Option<char>,
+    /// true if the name starts with `<SID>`, which is the same as the `s:` scope.
+    pub sid: bool,
+    /// Path of an autoload function, e.g. `["plugin", "util"]` for `plugin#util#trim`.
+    pub autoload_path: Vec<String>,
+    /// Dictionary that the function is defined on, e.g. `["formatter"]` for
+    /// `s:formatter.run`. The scope belongs to the dictionary in that case.
+    pub dict_path: Vec<String>,
+    /// Last component of the name, e.g. `trim` for `plugin#util#trim`.
+    pub name: String,
+}
+
+impl FunctionName {
+    pub fn parse(name: &str) -> FunctionName {
+        let (scope, sid, rest) =
+            if name.len() > 5 && name.as_bytes()[..5].eq_ignore_ascii_case(b"<SID>") {
+                (None, true, &name[5..])
+            } else if name.len() > 2 && name.as_bytes()[1] == b':' {
+                (name.chars().next(), false, &name[2..])
+            } else {
+                (None, false, name)
+            };
+        let mut dict_path: Vec<String> = rest.split('.').map(|c| c.to_string()).collect();
+        let last = dict_path.pop().unwrap_or_default();
+        let mut autoload_path = Vec::new();
+        if dict_path.is_empty() {
+            autoload_path = last.split('#').map(|c| c.to_string()).collect();
+        }
+        let name = if autoload_path.is_empty() {
+            last
+        } else {
+            autoload_path.pop().unwrap_or_default()
+        };
+        return FunctionName {
+            scope: scope,
+            sid: sid,
+            autoload_path: autoload_path,
+            dict_path: dict_path,
+            name: name,
+        };
+    }
+
+    /// Returns true for functions that can only be called from the script that defines them.
+    pub fn is_script_local(&self) -> bool {
+        return self.sid || self.scope == Some('s');
+    }
+
+    /// Returns true for functions stored in a dictionary, e.g. `s:formatter.run`.
+    pub fn is_dict_function(&self) -> bool {
+        return !self.dict_path.is_empty();
+    }
+}
+
+impl fmt::Display for FunctionName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.sid {
+            write!(f, "<SID>")?;
+        } else if let Some(scope) = self.scope {
+            write!(f, "{}:", scope)?;
+        }
+        for component in &self.dict_path {
+            write!(f, "{}.", component)?;
+        }
+        for component in &self.autoload_path {
+            write!(f, "{}#", component)?;
+        }
+        write!(f, "{}", self.name)
+    }
+}
+
+impl From<String> for FunctionName {
+    fn from(name: String) -> FunctionName {
+        return FunctionName::parse(&name);
+    }
+}
+
+impl Serialize for FunctionName {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
+pub struct FunctionArgument {
+    pub name: String,
+    pub name_location: SourceLocation,
+    // Value of optional argument, e.g. `1` in `function F(a, b = 1)`.
+    pub default_value: Option<Expr>,
+}
+
+impl FunctionArgument {
+    pub fn dump_for_testing(&self) -> serde_json::Value {
+        match &self.default_value {
+            Some(value) => return json!({"name": self.name, "default": value.dump_for_testing()}),
+            None => return json!({ "name": self.name }),
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct ForStatement {
     pub loop_variable: LoopVariable,
@@ -188,6 +313,92 @@ impl SetStatement {
     }
 }
 
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
+pub struct AugroupStatement {
+    pub name: String,
+    pub name_location: SourceLocation,
+    // true if 'augroup!', which deletes the group
+    pub delete: bool,
+    // Statements until 'augroup END'.
+    pub body: Vec<Stmt>,
+}
+
+impl AugroupStatement {
+    // Returns true for 'augroup END', which ends the group.
+    pub fn is_end(&self) -> bool {
+        return !self.delete && self.name.eq_ignore_ascii_case("END");
+    }
+
+    pub fn dump_for_testing(&self) -> serde_json::Value {
+        return json!({
+            "name": self.name,
+            "delete": self.delete,
+            "body": self.body.iter().map(|s| s.dump_for_testing()).collect::<Vec<serde_json::Value>>(),
+        });
+    }
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
+pub struct AutocmdStatement {
+    // true if 'autocmd!', which removes the matching autocommands
+    pub clear: bool,
+    pub group: Option<String>,
+    pub events: Vec<String>,
+    pub pattern: Option<String>,
+    // The rest of the line, including options like '++once'.
+    pub command: Option<String>,
+}
+
+impl AutocmdStatement {
+    pub fn dump_for_testing(&self) -> serde_json::Value {
+        return json!(self);
+    }
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
+pub struct CommandStatement {
+    pub name: String,
+    pub name_location: SourceLocation,
+    // true if 'command!'
+    pub overwrite: bool,
+    // e.g. '-nargs=1'
+    pub attributes: Vec<String>,
+    pub definition: String,
+}
+
+impl CommandStatement {
+    pub fn dump_for_testing(&self) -> serde_json::Value {
+        return json!({
+            "name": self.name,
+            "overwrite": self.overwrite,
+            "attributes": self.attributes,
+            "definition": self.definition,
+        });
+    }
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
+pub struct MapStatement {
+    // e.g. 'nnoremap' or 'map!'
+    pub command: String,
+    // e.g. '<silent>'
+    pub arguments: Vec<String>,
+    pub lhs: String,
+    pub lhs_location: SourceLocation,
+    pub rhs: String,
+}
+
+impl MapStatement {
+    pub fn dump_for_testing(&self) -> serde_json::Value {
+        return json!({
+            "command": self.command,
+            "arguments": self.arguments,
+            "lhs": self.lhs,
+            "rhs": self.rhs,
+        });
+    }
+}
+
 #[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub enum ElseCond {
     None,
@@ -297,6 +508,7 @@ pub enum ExprKind {
     Paren(ParenExpression),
     Choose(ChooseExpression),
     Dictionary(DictionaryExpression),
+    Member(MemberExpression),
 }
 
 impl ExprKind {
@@ -319,6 +531,7 @@ impl ExprKind {
             ExprKind::Paren(e) => json!({"paren":  e.dump_for_testing()}),
             ExprKind::Choose(e) => json!({"choose":  e.dump_for_testing()}),
             ExprKind::Dictionary(e) => json!({"dictionary":  e.dump_for_testing()}),
+            ExprKind::Member(e) => json!({"member":  e.dump_for_testing()}),
         };
     }
 }
@@ -514,6 +727,23 @@ impl ArraySubscriptExpression {
     }
 }
 
+// Represents `base.name`, e.g. `self.run`
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
+pub struct MemberExpression {
+    pub base: Box<Expr>,
+    pub name: String,
+    pub name_location: SourceLocation,
+}
+
+impl MemberExpression {
+    pub fn dump_for_testing(&self) -> serde_json::Value {
+        return json!({
+            "base": self.base.dump_for_testing(),
+            "name": self.name,
+        });
+    }
+}
+
 #[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct ArrayExpression {
     pub elements: Vec<Expr>,
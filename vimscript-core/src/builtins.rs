@@ -0,0 +1,1307 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Database of built-in functions and options, based on Vim's `:help functions` and
+// `:help option-list`.
+
+/// Built-in Vim function.
+pub struct BuiltinFunction {
+    pub name: &'static str,
+    /// Arguments in the same format as in `:help`, e.g. `{expr} [, {start}]`.
+    pub args: &'static str,
+    pub return_type: &'static str,
+    pub help: &'static str,
+    /// True for obsolete names that are kept for backwards compatibility.
+    pub deprecated: bool,
+}
+
+impl BuiltinFunction {
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.args)
+    }
+
+    /// Returns names of the parameters in the order of their appearance, e.g. `{list}` and
+    /// `{idx}` for `get`.
+    pub fn parameters(&self) -> Vec<&'static str> {
+        let mut parameters = Vec::new();
+        let mut rest = self.args;
+        while let Some(start) = rest.find('{') {
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end + 1,
+                None => break,
+            };
+            parameters.push(&rest[start..end]);
+            rest = &rest[end..];
+        }
+        parameters
+    }
+
+    /// Returns true if the last parameter can be repeated, e.g. in `printf`.
+    pub fn variadic(&self) -> bool {
+        self.args.ends_with("...")
+    }
+}
+
+/// Built-in Vim option.
+pub struct VimOption {
+    pub name: &'static str,
+    pub short_name: &'static str,
+    pub option_type: &'static str,
+    pub default: &'static str,
+    pub help: &'static str,
+}
+
+pub fn find_function(name: &str) -> Option<&'static BuiltinFunction> {
+    FUNCTIONS.iter().find(|f| f.name == name)
+}
+
+/// Finds an option by its full or short name.
+pub fn find_option(name: &str) -> Option<&'static VimOption> {
+    OPTIONS
+        .iter()
+        .find(|o| o.name == name || (!o.short_name.is_empty() && o.short_name == name))
+}
+
+/// Function or option that is missing in Neovim or in older versions of Vim.
+pub struct Availability {
+    /// Name of the function, or of the option with `&`, e.g. `&shada`.
+    pub name: &'static str,
+    /// Version of Vim that added it, e.g. `8.0.1630`, None if Vim doesn't have it.
+    pub vim: Option<&'static str>,
+    /// True if Neovim has it.
+    pub neovim: bool,
+}
+
+// Functions of the Neovim API, e.g. `nvim_buf_get_lines`.
+const NVIM_API: Availability = Availability {
+    name: "nvim_*",
+    vim: None,
+    neovim: true,
+};
+
+/// Returns the availability of the function or option (with `&`), None if it is available in all
+/// supported editors.
+pub fn find_availability(name: &str) -> Option<&'static Availability> {
+    if name.starts_with("nvim_") {
+        return Some(&NVIM_API);
+    }
+    AVAILABILITY.iter().find(|a| a.name == name)
+}
+
+/// Returns true if the name is an autocommand event, e.g. `BufWritePre`.
+pub fn is_autocmd_event(name: &str) -> bool {
+    name == "*" || AUTOCMD_EVENTS.iter().any(|e| e.eq_ignore_ascii_case(name))
+}
+
+pub const FUNCTIONS: &[BuiltinFunction] = &[
+    BuiltinFunction {
+        name: "abs",
+        args: "{expr}",
+        return_type: "Float or Number",
+        help: "Return the absolute value of {expr}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "add",
+        args: "{object}, {expr}",
+        return_type: "List/Blob",
+        help: "Append the item {expr} to List or Blob {object}. Returns the resulting List or Blob.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "append",
+        args: "{lnum}, {text}",
+        return_type: "Number",
+        help: "Append line(s) {text} below line {lnum} in the current buffer. Returns 1 for failure.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "argc",
+        args: "[{winid}]",
+        return_type: "Number",
+        help: "The number of files in the argument list.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "bufexists",
+        args: "{buf}",
+        return_type: "Number",
+        help: "The result is a Number, which is TRUE if a buffer called {buf} exists.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "buffer_exists",
+        args: "{buf}",
+        return_type: "Number",
+        help: "Obsolete name for bufexists().",
+        deprecated: true,
+    },
+    BuiltinFunction {
+        name: "buffer_name",
+        args: "[{buf}]",
+        return_type: "String",
+        help: "Obsolete name for bufname().",
+        deprecated: true,
+    },
+    BuiltinFunction {
+        name: "buffer_number",
+        args: "[{buf} [, {create}]]",
+        return_type: "Number",
+        help: "Obsolete name for bufnr().",
+        deprecated: true,
+    },
+    BuiltinFunction {
+        name: "bufname",
+        args: "[{buf}]",
+        return_type: "String",
+        help: "The result is the name of a buffer, as it is displayed by the `:ls` command.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "bufnr",
+        args: "[{buf} [, {create}]]",
+        return_type: "Number",
+        help: "The result is the number of a buffer, as it is displayed by the `:ls` command.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "call",
+        args: "{func}, {arglist} [, {dict}]",
+        return_type: "any",
+        help: "Call function {func} with the items in List {arglist} as arguments.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "char2nr",
+        args: "{string} [, {utf8}]",
+        return_type: "Number",
+        help: "Return number value of the first char in {string}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "col",
+        args: "{expr}",
+        return_type: "Number",
+        help: "The result is a Number, which is the byte index of the column position given with {expr}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "copy",
+        args: "{expr}",
+        return_type: "any",
+        help: "Make a copy of {expr}. For a List or Dictionary a shallow copy is created.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "count",
+        args: "{comp}, {expr} [, {ic} [, {start}]]",
+        return_type: "Number",
+        help: "Return the number of times an item with value {expr} appears in String, List or Dictionary {comp}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "deepcopy",
+        args: "{expr} [, {noref}]",
+        return_type: "any",
+        help: "Make a copy of {expr}. For a List or Dictionary a full copy is created, recursively.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "delete",
+        args: "{fname} [, {flags}]",
+        return_type: "Number",
+        help: "Delete the file or directory {fname}. The result is 0 if the delete operation was successful.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "empty",
+        args: "{expr}",
+        return_type: "Number",
+        help: "Return the Number 1 if {expr} is empty, zero otherwise.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "escape",
+        args: "{string}, {chars}",
+        return_type: "String",
+        help: "Escape the characters in {chars} that occur in {string} with a backslash.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "eval",
+        args: "{string}",
+        return_type: "any",
+        help: "Evaluate {string} and return the result.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "executable",
+        args: "{expr}",
+        return_type: "Number",
+        help: "This function checks if an executable with the name {expr} exists.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "execute",
+        args: "{command} [, {silent}]",
+        return_type: "String",
+        help: "Execute an Ex command or commands and return the output as a string.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "exists",
+        args: "{expr}",
+        return_type: "Number",
+        help: "The result is a Number, which is TRUE if {expr} is defined, zero otherwise.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "expand",
+        args: "{string} [, {nosuf} [, {list}]]",
+        return_type: "String or List",
+        help: "Expand wildcards and the following special keywords in {string}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "extend",
+        args: "{expr1}, {expr2} [, {expr3}]",
+        return_type: "List/Dict",
+        help: "{expr1} and {expr2} must be both Lists or both Dictionaries. Adds all items from {expr2} to {expr1}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "feedkeys",
+        args: "{string} [, {mode}]",
+        return_type: "Number",
+        help: "Characters in {string} are queued for processing as if they come from a mapping or were typed by the user.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "file_readable",
+        args: "{file}",
+        return_type: "Number",
+        help: "Obsolete name for filereadable().",
+        deprecated: true,
+    },
+    BuiltinFunction {
+        name: "filereadable",
+        args: "{file}",
+        return_type: "Number",
+        help: "The result is a Number, which is TRUE when a file with the name {file} exists, and can be read.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "filter",
+        args: "{expr1}, {expr2}",
+        return_type: "List/Dict",
+        help: "{expr1} must be a List, Blob or Dictionary. For each item in {expr1} evaluate {expr2} and when the result is zero remove the item.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "fnamemodify",
+        args: "{fname}, {mods}",
+        return_type: "String",
+        help: "Modify file name {fname} according to {mods}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "function",
+        args: "{name} [, {arglist}] [, {dict}]",
+        return_type: "Funcref",
+        help: "Return a Funcref variable that refers to function {name}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "funcref",
+        args: "{name} [, {arglist}] [, {dict}]",
+        return_type: "Funcref",
+        help: "Just like `function()`, but the returned Funcref will lookup the function by reference, not by name.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "get",
+        args: "{list}, {idx} [, {default}]",
+        return_type: "any",
+        help: "Get item {idx} from List {list}. When this item is not available return {default}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "getbufline",
+        args: "{buf}, {lnum} [, {end}]",
+        return_type: "List",
+        help: "Return a List with the lines starting from {lnum} to {end} (inclusive) in the buffer {buf}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "getcwd",
+        args: "[{winnr} [, {tabnr}]]",
+        return_type: "String",
+        help: "The result is a String, which is the name of the current working directory.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "getline",
+        args: "{lnum} [, {end}]",
+        return_type: "String or List",
+        help: "Without {end} the result is a String, which is line {lnum} from the current buffer.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "getpos",
+        args: "{expr}",
+        return_type: "List",
+        help: "Get the position for String {expr}. The result is a List with four numbers: [bufnum, lnum, col, off].",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "glob",
+        args: "{expr} [, {nosuf} [, {list} [, {alllinks}]]]",
+        return_type: "any",
+        help: "Expand the file wildcards in {expr}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "globpath",
+        args: "{path}, {expr} [, {nosuf} [, {list} [, {alllinks}]]]",
+        return_type: "String",
+        help: "Perform glob() for String {expr} on all directories in {path} and concatenate the results.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "has",
+        args: "{feature} [, {check}]",
+        return_type: "Number",
+        help: "Returns 1 if {feature} is supported, 0 otherwise.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "has_key",
+        args: "{dict}, {key}",
+        return_type: "Number",
+        help: "The result is a Number, which is TRUE if Dictionary {dict} has an entry with key {key}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "highlight_exists",
+        args: "{name}",
+        return_type: "Number",
+        help: "Obsolete name for hlexists().",
+        deprecated: true,
+    },
+    BuiltinFunction {
+        name: "index",
+        args: "{object}, {expr} [, {start} [, {ic}]]",
+        return_type: "Number",
+        help: "Return the lowest index in List {object} where the item has a value equal to {expr}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "input",
+        args: "{prompt} [, {text} [, {completion}]]",
+        return_type: "String",
+        help: "The result is a String, which is whatever the user typed on the command-line.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "insert",
+        args: "{object}, {item} [, {idx}]",
+        return_type: "List/Blob",
+        help: "When {object} is a List or a Blob insert {item} at the start of it.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "isdirectory",
+        args: "{directory}",
+        return_type: "Number",
+        help: "The result is a Number, which is TRUE when a directory with the name {directory} exists.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "items",
+        args: "{dict}",
+        return_type: "List",
+        help: "Return a List with all the key-value pairs of {dict}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "join",
+        args: "{list} [, {sep}]",
+        return_type: "String",
+        help: "Join the items in {list} together into one String. When {sep} is specified it is put in between the items.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "json_decode",
+        args: "{string}",
+        return_type: "any",
+        help: "This parses a JSON formatted string and returns the equivalent in Vim values.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "json_encode",
+        args: "{expr}",
+        return_type: "String",
+        help: "Encode {expr} as JSON and return this as a String.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "keys",
+        args: "{dict}",
+        return_type: "List",
+        help: "Return a List with all the keys of {dict}. The List is in arbitrary order.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "len",
+        args: "{expr}",
+        return_type: "Number",
+        help: "The result is a Number, which is the length of the argument.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "line",
+        args: "{expr} [, {winid}]",
+        return_type: "Number",
+        help: "The result is a Number, which is the line number of the file position given with {expr}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "map",
+        args: "{expr1}, {expr2}",
+        return_type: "List/Dict",
+        help: "{expr1} must be a List, Blob or Dictionary. Replace each item in {expr1} with the result of evaluating {expr2}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "match",
+        args: "{expr}, {pat} [, {start} [, {count}]]",
+        return_type: "Number",
+        help: "When {expr} is a String this returns the index where {pat} matches in {expr}, -1 if there is no match.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "matchstr",
+        args: "{expr}, {pat} [, {start} [, {count}]]",
+        return_type: "String",
+        help: "Same as `match()`, but return the matched string.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "max",
+        args: "{expr}",
+        return_type: "Number",
+        help: "Return the maximum value of all items in {expr}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "min",
+        args: "{expr}",
+        return_type: "Number",
+        help: "Return the minimum value of all items in {expr}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "mkdir",
+        args: "{name} [, {path} [, {prot}]]",
+        return_type: "Number",
+        help: "Create directory {name}. If {path} is \"p\" then intermediate directories are created as necessary.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "mode",
+        args: "[{expr}]",
+        return_type: "String",
+        help: "Return a string that indicates the current mode.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "nr2char",
+        args: "{expr} [, {utf8}]",
+        return_type: "String",
+        help: "Return a string with a single character, which has the number value {expr}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "printf",
+        args: "{fmt}, {expr1} ...",
+        return_type: "String",
+        help: "Return a String with {fmt}, where \"%\" items are replaced by the formatted form of their respective arguments.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "range",
+        args: "{expr} [, {max} [, {stride}]]",
+        return_type: "List",
+        help: "Returns a List with Numbers.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "readfile",
+        args: "{fname} [, {type} [, {max}]]",
+        return_type: "List",
+        help: "Read file {fname} and return a List, each line of the file as an item.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "remove",
+        args: "{list}, {idx} [, {end}]",
+        return_type: "any",
+        help: "Without {end}: Remove the item at {idx} from List {list} and return the item.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "repeat",
+        args: "{expr}, {count}",
+        return_type: "String",
+        help: "Repeat {expr} {count} times and return the concatenated result.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "reverse",
+        args: "{object}",
+        return_type: "List",
+        help: "Reverse the order of items in {object} in-place. Returns {object}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "search",
+        args: "{pattern} [, {flags} [, {stopline} [, {timeout}]]]",
+        return_type: "Number",
+        help: "Search for regexp pattern {pattern}. The search starts at the cursor position.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "setline",
+        args: "{lnum}, {text}",
+        return_type: "Number",
+        help: "Set line {lnum} of the current buffer to {text}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "setpos",
+        args: "{expr}, {list}",
+        return_type: "Number",
+        help: "Set the position for String {expr}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "shellescape",
+        args: "{string} [, {special}]",
+        return_type: "String",
+        help: "Escape {string} for use as a shell command argument.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "sort",
+        args: "{list} [, {func} [, {dict}]]",
+        return_type: "List",
+        help: "Sort the items in {list} in-place. Returns {list}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "split",
+        args: "{string} [, {pattern} [, {keepempty}]]",
+        return_type: "List",
+        help: "Make a List out of {string}. When {pattern} is omitted or empty each white-separated sequence of characters becomes an item.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "string",
+        args: "{expr}",
+        return_type: "String",
+        help: "Return {expr} converted to a String.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "strftime",
+        args: "{format} [, {time}]",
+        return_type: "String",
+        help: "The result is a String, which is a formatted date and time, as specified by the {format} string.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "strlen",
+        args: "{string}",
+        return_type: "Number",
+        help: "The result is a Number, which is the length of the String {string} in bytes.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "strpart",
+        args: "{src}, {start} [, {len} [, {chars}]]",
+        return_type: "String",
+        help: "The result is a String, which is part of {src}, starting from byte {start}, with the byte length {len}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "substitute",
+        args: "{string}, {pat}, {sub}, {flags}",
+        return_type: "String",
+        help: "The result is a String, which is a copy of {string}, in which the first match of {pat} is replaced with {sub}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "system",
+        args: "{expr} [, {input}]",
+        return_type: "String",
+        help: "Get the output of the shell command {expr} as a String.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "systemlist",
+        args: "{expr} [, {input}]",
+        return_type: "List",
+        help: "Same as `system()`, but returns a List with lines.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "timer_start",
+        args: "{time}, {callback} [, {options}]",
+        return_type: "Number",
+        help: "Create a timer and return the timer ID. {callback} is invoked after {time} milliseconds.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "tolower",
+        args: "{expr}",
+        return_type: "String",
+        help: "The result is a copy of the String given, with all uppercase characters turned into lowercase.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "toupper",
+        args: "{expr}",
+        return_type: "String",
+        help: "The result is a copy of the String given, with all lowercase characters turned into uppercase.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "tr",
+        args: "{src}, {fromstr}, {tostr}",
+        return_type: "String",
+        help: "The result is a copy of the {src} string with all characters which appear in {fromstr} replaced by the character in that position in the {tostr} string.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "trim",
+        args: "{text} [, {mask} [, {dir}]]",
+        return_type: "String",
+        help: "Return {text} as a String where any character in {mask} is removed from the beginning and/or end of {text}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "type",
+        args: "{expr}",
+        return_type: "Number",
+        help: "The result is a Number representing the type of {expr}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "uniq",
+        args: "{list} [, {func} [, {dict}]]",
+        return_type: "List",
+        help: "Remove second and succeeding copies of repeated adjacent {list} items in-place. Returns {list}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "values",
+        args: "{dict}",
+        return_type: "List",
+        help: "Return a List with all the values of {dict}. The List is in arbitrary order.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "virtcol",
+        args: "{expr}",
+        return_type: "Number",
+        help: "The result is a Number, which is the screen column of the file position given with {expr}.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "winnr",
+        args: "[{arg}]",
+        return_type: "Number",
+        help: "The result is a Number, which is the number of the current window.",
+        deprecated: false,
+    },
+    BuiltinFunction {
+        name: "writefile",
+        args: "{object}, {fname} [, {flags}]",
+        return_type: "Number",
+        help: "When {object} is a List write it to file {fname}. Each list item is separated with a NL.",
+        deprecated: false,
+    },
+];
+
+pub const OPTIONS: &[VimOption] = &[
+    VimOption {
+        name: "autoindent",
+        short_name: "ai",
+        option_type: "boolean",
+        default: "off",
+        help: "Copy indent from current line when starting a new line.",
+    },
+    VimOption {
+        name: "background",
+        short_name: "bg",
+        option_type: "string",
+        default: "\"light\"",
+        help: "When set to \"dark\", Vim will try to use colors that look good on a dark background.",
+    },
+    VimOption {
+        name: "clipboard",
+        short_name: "cb",
+        option_type: "string",
+        default: "\"\"",
+        help: "This option is a list of comma separated names that control the clipboard.",
+    },
+    VimOption {
+        name: "cursorline",
+        short_name: "cul",
+        option_type: "boolean",
+        default: "off",
+        help: "Highlight the text line of the cursor with CursorLine.",
+    },
+    VimOption {
+        name: "encoding",
+        short_name: "enc",
+        option_type: "string",
+        default: "\"latin1\" or value from $LANG",
+        help: "Sets the character encoding used inside Vim.",
+    },
+    VimOption {
+        name: "expandtab",
+        short_name: "et",
+        option_type: "boolean",
+        default: "off",
+        help: "In Insert mode: Use the appropriate number of spaces to insert a <Tab>.",
+    },
+    VimOption {
+        name: "fileformat",
+        short_name: "ff",
+        option_type: "string",
+        default: "\"unix\"",
+        help: "This gives the <EOL> of the current buffer.",
+    },
+    VimOption {
+        name: "filetype",
+        short_name: "ft",
+        option_type: "string",
+        default: "\"\"",
+        help: "When this option is set, the FileType autocommand event is triggered.",
+    },
+    VimOption {
+        name: "foldenable",
+        short_name: "fen",
+        option_type: "boolean",
+        default: "on",
+        help: "When off, all folds are open.",
+    },
+    VimOption {
+        name: "foldmethod",
+        short_name: "fdm",
+        option_type: "string",
+        default: "\"manual\"",
+        help: "The kind of folding used for the current window.",
+    },
+    VimOption {
+        name: "hidden",
+        short_name: "hid",
+        option_type: "boolean",
+        default: "off",
+        help: "When off a buffer is unloaded when it is abandoned. When on a buffer becomes hidden when it is abandoned.",
+    },
+    VimOption {
+        name: "hlsearch",
+        short_name: "hls",
+        option_type: "boolean",
+        default: "off",
+        help: "When there is a previous search pattern, highlight all its matches.",
+    },
+    VimOption {
+        name: "ignorecase",
+        short_name: "ic",
+        option_type: "boolean",
+        default: "off",
+        help: "Ignore case in search patterns.",
+    },
+    VimOption {
+        name: "incsearch",
+        short_name: "is",
+        option_type: "boolean",
+        default: "off",
+        help: "While typing a search command, show where the pattern, as it was typed so far, matches.",
+    },
+    VimOption {
+        name: "iskeyword",
+        short_name: "isk",
+        option_type: "string",
+        default: "\"@,48-57,_,192-255\"",
+        help: "Keywords are used in searching and recognizing with many commands.",
+    },
+    VimOption {
+        name: "laststatus",
+        short_name: "ls",
+        option_type: "number",
+        default: "1",
+        help: "The value of this option influences when the last window will have a status line.",
+    },
+    VimOption {
+        name: "list",
+        short_name: "",
+        option_type: "boolean",
+        default: "off",
+        help: "List mode: Show tabs as CTRL-I is displayed, display $ after end of line.",
+    },
+    VimOption {
+        name: "modeline",
+        short_name: "ml",
+        option_type: "boolean",
+        default: "on",
+        help: "If 'modeline' is on 'modelines' gives the number of lines that is checked for set commands.",
+    },
+    VimOption {
+        name: "mouse",
+        short_name: "",
+        option_type: "string",
+        default: "\"\"",
+        help: "Enable the use of the mouse.",
+    },
+    VimOption {
+        name: "number",
+        short_name: "nu",
+        option_type: "boolean",
+        default: "off",
+        help: "Print the line number in front of each line.",
+    },
+    VimOption {
+        name: "paste",
+        short_name: "",
+        option_type: "boolean",
+        default: "off",
+        help: "Put Vim in Paste mode. This is useful if you want to cut or copy some text from one window and paste it in Vim.",
+    },
+    VimOption {
+        name: "relativenumber",
+        short_name: "rnu",
+        option_type: "boolean",
+        default: "off",
+        help: "Show the line number relative to the line with the cursor in front of each line.",
+    },
+    VimOption {
+        name: "ruler",
+        short_name: "ru",
+        option_type: "boolean",
+        default: "off",
+        help: "Show the line and column number of the cursor position, separated by a comma.",
+    },
+    VimOption {
+        name: "runtimepath",
+        short_name: "rtp",
+        option_type: "string",
+        default: "\"$HOME/.vim,$VIM/vimfiles,$VIMRUNTIME,$VIM/vimfiles/after,$HOME/.vim/after\"",
+        help: "This is a list of directories which will be searched for runtime files.",
+    },
+    VimOption {
+        name: "scrolloff",
+        short_name: "so",
+        option_type: "number",
+        default: "0",
+        help: "Minimal number of screen lines to keep above and below the cursor.",
+    },
+    VimOption {
+        name: "selection",
+        short_name: "sel",
+        option_type: "string",
+        default: "\"inclusive\"",
+        help: "This option defines the behavior of the selection.",
+    },
+    VimOption {
+        name: "shiftwidth",
+        short_name: "sw",
+        option_type: "number",
+        default: "8",
+        help: "Number of spaces to use for each step of (auto)indent.",
+    },
+    VimOption {
+        name: "showcmd",
+        short_name: "sc",
+        option_type: "boolean",
+        default: "Vim default: on, off for Unix",
+        help: "Show (partial) command in the last line of the screen.",
+    },
+    VimOption {
+        name: "smartcase",
+        short_name: "scs",
+        option_type: "boolean",
+        default: "off",
+        help: "Override the 'ignorecase' option if the search pattern contains upper case characters.",
+    },
+    VimOption {
+        name: "smartindent",
+        short_name: "si",
+        option_type: "boolean",
+        default: "off",
+        help: "Do smart autoindenting when starting a new line.",
+    },
+    VimOption {
+        name: "softtabstop",
+        short_name: "sts",
+        option_type: "number",
+        default: "0",
+        help: "Number of spaces that a <Tab> counts for while performing editing operations.",
+    },
+    VimOption {
+        name: "spell",
+        short_name: "",
+        option_type: "boolean",
+        default: "off",
+        help: "When on spell checking will be done.",
+    },
+    VimOption {
+        name: "splitbelow",
+        short_name: "sb",
+        option_type: "boolean",
+        default: "off",
+        help: "When on, splitting a window will put the new window below the current one.",
+    },
+    VimOption {
+        name: "splitright",
+        short_name: "spr",
+        option_type: "boolean",
+        default: "off",
+        help: "When on, splitting a window will put the new window right of the current one.",
+    },
+    VimOption {
+        name: "statusline",
+        short_name: "stl",
+        option_type: "string",
+        default: "\"\"",
+        help: "When nonempty, this option determines the content of the status line.",
+    },
+    VimOption {
+        name: "tabstop",
+        short_name: "ts",
+        option_type: "number",
+        default: "8",
+        help: "Number of spaces that a <Tab> in the file counts for.",
+    },
+    VimOption {
+        name: "textwidth",
+        short_name: "tw",
+        option_type: "number",
+        default: "0",
+        help: "Maximum width of text that is being inserted. A longer line will be broken after white space to get this width.",
+    },
+    VimOption {
+        name: "timeoutlen",
+        short_name: "tm",
+        option_type: "number",
+        default: "1000",
+        help: "The time in milliseconds that is waited for a mapped sequence to complete.",
+    },
+    VimOption {
+        name: "undofile",
+        short_name: "udf",
+        option_type: "boolean",
+        default: "off",
+        help: "When on, Vim automatically saves undo history to an undo file when writing a buffer to a file.",
+    },
+    VimOption {
+        name: "updatetime",
+        short_name: "ut",
+        option_type: "number",
+        default: "4000",
+        help: "If this many milliseconds nothing is typed the swap file will be written to disk.",
+    },
+    VimOption {
+        name: "wildmenu",
+        short_name: "wmnu",
+        option_type: "boolean",
+        default: "off",
+        help: "When 'wildmenu' is on, command-line completion operates in an enhanced mode.",
+    },
+    VimOption {
+        name: "wrap",
+        short_name: "",
+        option_type: "boolean",
+        default: "on",
+        help: "When on, lines longer than the width of the window will wrap and displaying continues on the next line.",
+    },
+    VimOption {
+        name: "wrapscan",
+        short_name: "ws",
+        option_type: "boolean",
+        default: "on",
+        help: "Searches wrap around the end of the file.",
+    },
+];
+
+pub const AUTOCMD_EVENTS: &[&str] = &[
+    "BufAdd",
+    "BufDelete",
+    "BufEnter",
+    "BufFilePost",
+    "BufFilePre",
+    "BufHidden",
+    "BufLeave",
+    "BufNew",
+    "BufNewFile",
+    "BufRead",
+    "BufReadCmd",
+    "BufReadPost",
+    "BufReadPre",
+    "BufUnload",
+    "BufWinEnter",
+    "BufWinLeave",
+    "BufWipeout",
+    "BufWrite",
+    "BufWriteCmd",
+    "BufWritePost",
+    "BufWritePre",
+    "CmdlineChanged",
+    "CmdlineEnter",
+    "CmdlineLeave",
+    "CmdwinEnter",
+    "CmdwinLeave",
+    "ColorScheme",
+    "ColorSchemePre",
+    "CompleteChanged",
+    "CompleteDone",
+    "CompleteDonePre",
+    "CursorHold",
+    "CursorHoldI",
+    "CursorMoved",
+    "CursorMovedI",
+    "DiffUpdated",
+    "DirChanged",
+    "EncodingChanged",
+    "ExitPre",
+    "FileAppendCmd",
+    "FileAppendPost",
+    "FileAppendPre",
+    "FileChangedRO",
+    "FileChangedShell",
+    "FileChangedShellPost",
+    "FileReadCmd",
+    "FileReadPost",
+    "FileReadPre",
+    "FileType",
+    "FileWriteCmd",
+    "FileWritePost",
+    "FileWritePre",
+    "FilterReadPost",
+    "FilterReadPre",
+    "FilterWritePost",
+    "FilterWritePre",
+    "FocusGained",
+    "FocusLost",
+    "FuncUndefined",
+    "GUIEnter",
+    "GUIFailed",
+    "InsertChange",
+    "InsertCharPre",
+    "InsertEnter",
+    "InsertLeave",
+    "InsertLeavePre",
+    "MenuPopup",
+    "OptionSet",
+    "QuickFixCmdPost",
+    "QuickFixCmdPre",
+    "QuitPre",
+    "RemoteReply",
+    "SafeState",
+    "SafeStateAgain",
+    "SessionLoadPost",
+    "ShellCmdPost",
+    "ShellFilterPost",
+    "SourceCmd",
+    "SourcePost",
+    "SourcePre",
+    "SpellFileMissing",
+    "StdinReadPost",
+    "StdinReadPre",
+    "SwapExists",
+    "Syntax",
+    "TabClosed",
+    "TabEnter",
+    "TabLeave",
+    "TabNew",
+    "TermChanged",
+    "TermResponse",
+    "TerminalOpen",
+    "TerminalWinOpen",
+    "TextChanged",
+    "TextChangedI",
+    "TextChangedP",
+    "TextYankPost",
+    "User",
+    "VimEnter",
+    "VimLeave",
+    "VimLeavePre",
+    "VimResized",
+    "WinEnter",
+    "WinLeave",
+    "WinNew",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn finds_option_by_short_name() {
+        assert_eq!(find_option("ts").unwrap().name, "tabstop");
+        assert_eq!(find_option("tabstop").unwrap().name, "tabstop");
+        assert!(find_option("").is_none());
+    }
+
+    #[test]
+    fn returns_function_signature() {
+        let function = find_function("get").unwrap();
+        assert_eq!(function.signature(), "get({list}, {idx} [, {default}])");
+    }
+
+    #[test]
+    fn returns_function_parameters() {
+        let function = find_function("get").unwrap();
+        assert_eq!(function.parameters(), vec!["{list}", "{idx}", "{default}"]);
+        assert!(!function.variadic());
+        assert!(find_function("printf").unwrap().variadic());
+    }
+}
+
+pub const AVAILABILITY: &[Availability] = &[
+    Availability {
+        name: "appendbufline",
+        vim: Some("8.1.0037"),
+        neovim: true,
+    },
+    Availability {
+        name: "ch_open",
+        vim: Some("8.0"),
+        neovim: false,
+    },
+    Availability {
+        name: "chansend",
+        vim: None,
+        neovim: true,
+    },
+    Availability {
+        name: "deletebufline",
+        vim: Some("8.1.0039"),
+        neovim: true,
+    },
+    Availability {
+        name: "funcref",
+        vim: Some("8.0"),
+        neovim: true,
+    },
+    Availability {
+        name: "getenv",
+        vim: Some("8.1.1305"),
+        neovim: true,
+    },
+    Availability {
+        name: "job_start",
+        vim: Some("8.0"),
+        neovim: false,
+    },
+    Availability {
+        name: "jobstart",
+        vim: None,
+        neovim: true,
+    },
+    Availability {
+        name: "jobstop",
+        vim: None,
+        neovim: true,
+    },
+    Availability {
+        name: "json_decode",
+        vim: Some("8.0"),
+        neovim: true,
+    },
+    Availability {
+        name: "json_encode",
+        vim: Some("8.0"),
+        neovim: true,
+    },
+    Availability {
+        name: "popup_create",
+        vim: Some("8.1.1391"),
+        neovim: false,
+    },
+    Availability {
+        name: "rpcnotify",
+        vim: None,
+        neovim: true,
+    },
+    Availability {
+        name: "rpcrequest",
+        vim: None,
+        neovim: true,
+    },
+    Availability {
+        name: "setenv",
+        vim: Some("8.1.1305"),
+        neovim: true,
+    },
+    Availability {
+        name: "stdpath",
+        vim: None,
+        neovim: true,
+    },
+    Availability {
+        name: "termopen",
+        vim: None,
+        neovim: true,
+    },
+    Availability {
+        name: "timer_start",
+        vim: Some("8.0"),
+        neovim: true,
+    },
+    Availability {
+        name: "trim",
+        vim: Some("8.0.1630"),
+        neovim: true,
+    },
+    Availability {
+        name: "&cryptmethod",
+        vim: Some("7.3"),
+        neovim: false,
+    },
+    Availability {
+        name: "&inccommand",
+        vim: None,
+        neovim: true,
+    },
+    Availability {
+        name: "&shada",
+        vim: None,
+        neovim: true,
+    },
+    Availability {
+        name: "&signcolumn",
+        vim: Some("8.0"),
+        neovim: true,
+    },
+    Availability {
+        name: "&termguicolors",
+        vim: Some("8.0"),
+        neovim: true,
+    },
+];
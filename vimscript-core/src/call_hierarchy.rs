@@ -0,0 +1,448 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::conversion::token_position_to_range;
+use crate::index::Index;
+use crate::index::IndexedSymbol;
+use crate::lexer::Lexer;
+use crate::lexer::SourceLocation;
+use crate::parser::Parser;
+use crate::references::find_references;
+use crate::references::function_symbol;
+use crate::references::Symbol;
+use crate::visitor::walk_expr;
+use crate::visitor::walk_program;
+use crate::visitor::walk_stmt;
+use crate::visitor::Visitor;
+use lsp_types::CallHierarchyIncomingCall;
+use lsp_types::CallHierarchyItem;
+use lsp_types::CallHierarchyOutgoingCall;
+use lsp_types::Position;
+use lsp_types::Range;
+use lsp_types::SymbolKind;
+use lsp_types::Url;
+use std::ops;
+
+/// Call of a function. Calls of builtin functions are included too, they are not defined in any
+/// script.
+#[derive(PartialEq, Debug, Clone)]
+pub struct FunctionCall {
+    // Name of the function that contains the call, None for calls at the script level.
+    pub caller: Option<String>,
+    // Name of the called function, script-local functions always use the `s:` prefix.
+    pub callee: String,
+    pub range: Range,
+}
+
+/// Returns calls in the script: `call` statements, calls in expressions, funcrefs created with
+/// `function('name')` and calls in the commands of mappings, user commands and autocommands.
+pub fn function_calls(source: &str) -> Vec<FunctionCall> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    let mut collector = Calls {
+        source: source,
+        functions: Vec::new(),
+        calls: Vec::new(),
+    };
+    walk_program(&mut collector, &program);
+    let mut calls = collector.calls;
+    for reference in find_references(&program, &parser) {
+        if let (Symbol::Function(name), false) = (&reference.symbol, reference.write) {
+            calls.push((reference.location.range, name.clone()));
+        }
+    }
+    calls.sort_by_key(|(range, _)| range.start);
+
+    let functions = collector.functions;
+    calls
+        .into_iter()
+        .map(|(range, callee)| FunctionCall {
+            // Functions can't be nested, so at most one function contains the call.
+            caller: functions
+                .iter()
+                .find(|(_, span)| span.start <= range.start && range.end <= span.end)
+                .map(|(name, _)| name.clone()),
+            callee: callee,
+            range: token_position_to_range(
+                &parser.resolve_location(SourceLocation { range: range }),
+            ),
+        })
+        .collect()
+}
+
+/// Returns the user function at the position, which can be its definition or a call.
+pub fn prepare_call_hierarchy(
+    index: &Index,
+    uri: &Url,
+    source: &str,
+    pos: Position,
+) -> Option<Vec<CallHierarchyItem>> {
+    let name = function_calls(source)
+        .into_iter()
+        .find(|call| contains(&call.range, pos))
+        .map(|call| call.callee)
+        .or_else(|| {
+            index
+                .symbols(uri)
+                .iter()
+                .find(|s| s.kind == SymbolKind::FUNCTION && contains(&s.location.range, pos))
+                .map(|s| function_name(&s.name))
+        })?;
+    let function = index.find_function(uri, &name)?;
+    Some(vec![function_item(function)])
+}
+
+/// Returns the functions and scripts that call the function, with the ranges of the calls.
+pub fn incoming_calls(index: &Index, item: &CallHierarchyItem) -> Vec<CallHierarchyIncomingCall> {
+    let name = function_name(&item.name);
+    let mut result: Vec<CallHierarchyIncomingCall> = Vec::new();
+    for (uri, call) in index.calls_of(&item.uri, &name) {
+        let from = match &call.caller {
+            Some(caller) => match index.find_function(uri, caller) {
+                Some(function) => function_item(function),
+                None => continue,
+            },
+            None => script_item(index, uri),
+        };
+        match result
+            .iter_mut()
+            .find(|c| c.from.uri == from.uri && c.from.name == from.name)
+        {
+            Some(incoming) => incoming.from_ranges.push(call.range),
+            None => result.push(CallHierarchyIncomingCall {
+                from: from,
+                from_ranges: vec![call.range],
+            }),
+        }
+    }
+    result.sort_by(|a, b| {
+        (a.from.uri.as_str(), &a.from.name).cmp(&(b.from.uri.as_str(), &b.from.name))
+    });
+    result
+}
+
+/// Returns the user functions called by the function, with the ranges of the calls.
+pub fn outgoing_calls(index: &Index, item: &CallHierarchyItem) -> Vec<CallHierarchyOutgoingCall> {
+    let name = function_name(&item.name);
+    let mut result: Vec<CallHierarchyOutgoingCall> = Vec::new();
+    for call in index
+        .calls_in(&item.uri)
+        .iter()
+        .filter(|c| c.caller.as_ref() == Some(&name))
+    {
+        // Builtin functions are not defined anywhere.
+        let to = match index.find_function(&item.uri, &call.callee) {
+            Some(function) => function_item(function),
+            None => continue,
+        };
+        match result
+            .iter_mut()
+            .find(|c| c.to.uri == to.uri && c.to.name == to.name)
+        {
+            Some(outgoing) => outgoing.from_ranges.push(call.range),
+            None => result.push(CallHierarchyOutgoingCall {
+                to: to,
+                from_ranges: vec![call.range],
+            }),
+        }
+    }
+    result
+}
+
+// Returns the name of the function as it is used in calls.
+pub(crate) fn function_name(name: &str) -> String {
+    match function_symbol(name) {
+        Symbol::Function(name) => name,
+        _ => name.to_string(),
+    }
+}
+
+fn contains(range: &Range, pos: Position) -> bool {
+    range.start <= pos && pos <= range.end
+}
+
+fn function_item(function: &IndexedSymbol) -> CallHierarchyItem {
+    CallHierarchyItem {
+        name: function.name.clone(),
+        kind: SymbolKind::FUNCTION,
+        tags: None,
+        detail: function.container_name.clone(),
+        uri: function.location.uri.clone(),
+        range: function.location.range,
+        selection_range: function.location.range,
+        data: None,
+    }
+}
+
+// Calls at the script level are reported as calls from the script file.
+fn script_item(index: &Index, uri: &Url) -> CallHierarchyItem {
+    let name = uri
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or("")
+        .to_string();
+    CallHierarchyItem {
+        name: name,
+        kind: SymbolKind::FILE,
+        tags: None,
+        detail: None,
+        uri: uri.clone(),
+        range: index.script_range(uri),
+        selection_range: index.script_range(uri),
+        data: None,
+    }
+}
+
+// Collects function definitions and calls that are not references to functions in the AST.
+struct Calls<'a> {
+    source: &'a str,
+    // Names and byte ranges of function definitions.
+    functions: Vec<(String, ops::Range<usize>)>,
+    calls: Vec<(ops::Range<usize>, String)>,
+}
+
+impl<'a> Calls<'a> {
+    // Finds calls in the Ex command at the end of the statement, e.g. the right hand side of a
+    // mapping. Only names that can't be builtin functions are considered.
+    fn add_command_calls(&mut self, stmt: &Stmt, command: &str) {
+        let mut end = stmt.span.end.0 as usize;
+        while end > 0 && self.source.as_bytes()[end - 1].is_ascii_whitespace() {
+            end -= 1;
+        }
+        if end < command.len() || &self.source[end - command.len()..end] != command {
+            return;
+        }
+        let start = end - command.len();
+        let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == ':' || c == '#';
+        let mut i = 0;
+        while i < command.len() {
+            let rest = &command[i..];
+            let prefix = if rest.len() >= 5 && rest.as_bytes()[..5].eq_ignore_ascii_case(b"<SID>") {
+                5
+            } else {
+                0
+            };
+            let length = prefix
+                + rest[prefix..]
+                    .find(|c| !is_name_char(c))
+                    .unwrap_or(rest.len() - prefix);
+            if length == 0 {
+                i += rest.chars().next().map_or(1, |c| c.len_utf8());
+                continue;
+            }
+            let name = &rest[..length];
+            let is_user_function = prefix > 0
+                || name.starts_with("s:")
+                || name.starts_with("g:")
+                || name.contains('#')
+                || name.starts_with(|c: char| c.is_ascii_uppercase());
+            if is_user_function && rest[length..].starts_with('(') {
+                let name = format!("<SID>{}", &name[prefix..]);
+                let name = if prefix > 0 {
+                    name
+                } else {
+                    name[5..].to_string()
+                };
+                self.calls
+                    .push((start + i..start + i + length, function_name(&name)));
+            }
+            i += length;
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Calls<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Function(f) => self.functions.push((
+                function_name(&f.name.to_string()),
+                stmt.span.start.0 as usize..stmt.span.end.0 as usize,
+            )),
+            StmtKind::Map(m) => self.add_command_calls(stmt, &m.rhs),
+            StmtKind::Command(c) => self.add_command_calls(stmt, &c.definition),
+            StmtKind::Autocmd(a) => {
+                if let Some(command) = &a.command {
+                    self.add_command_calls(stmt, command);
+                }
+            }
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        // `function('name')` and `funcref('name')` reference the function by its name.
+        if let ExprKind::Function(e) = &expr.kind {
+            if let (ExprKind::Identifier(callee), Some(argument)) =
+                (&e.callee.kind, e.arguments.first())
+            {
+                if let ExprKind::StringLiteral(name) = &argument.kind {
+                    if callee.name == "function" || callee.name == "funcref" {
+                        let start = argument.span.start.0 as usize + 1;
+                        self.calls
+                            .push((start..start + name.value.len(), function_name(&name.value)));
+                    }
+                }
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn calls(source: &str) -> Vec<(Option<String>, String, String)> {
+        function_calls(source)
+            .into_iter()
+            .map(|call| {
+                (
+                    call.caller,
+                    call.callee,
+                    format!(
+                        "{}:{}-{}:{}",
+                        call.range.start.line,
+                        call.range.start.character,
+                        call.range.end.line,
+                        call.range.end.character
+                    ),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_calls_in_statements_expressions_and_funcrefs() {
+        let source = "function! s:Run() abort
+  call s:Format(len(l:x))
+  let l:F = function('plugin#util#trim')
+endfunction
+call s:Run()";
+        assert_eq!(
+            calls(source),
+            vec![
+                (
+                    Some("s:Run".to_string()),
+                    "s:Format".to_string(),
+                    "1:7-1:15".to_string()
+                ),
+                (
+                    Some("s:Run".to_string()),
+                    "len".to_string(),
+                    "1:16-1:19".to_string()
+                ),
+                (
+                    Some("s:Run".to_string()),
+                    "function".to_string(),
+                    "2:12-2:20".to_string()
+                ),
+                (
+                    Some("s:Run".to_string()),
+                    "plugin#util#trim".to_string(),
+                    "2:22-2:38".to_string()
+                ),
+                (None, "s:Run".to_string(), "4:5-4:10".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_calls_in_mappings_and_commands() {
+        let source = "nnoremap <leader>f :call <SID>Format()<CR>
+command! -nargs=0 Fmt call plugin#Format(<q-args>)";
+        assert_eq!(
+            calls(source),
+            vec![
+                (None, "s:Format".to_string(), "0:25-0:36".to_string()),
+                (None, "plugin#Format".to_string(), "1:27-1:40".to_string()),
+            ]
+        );
+    }
+
+    fn index() -> Index {
+        let mut index = Index::new();
+        index.update(
+            &Url::parse("file:///autoload/plugin.vim").unwrap(),
+            "function! plugin#trim(text) abort
+  return s:Strip(a:text)
+endfunction
+function! s:Strip(text) abort
+endfunction
+",
+        );
+        index.update(
+            &Url::parse("file:///plugin/plugin.vim").unwrap(),
+            "function! s:Run() abort
+  call plugin#trim('a')
+  call plugin#trim('b')
+endfunction
+command! Trim call plugin#trim(getline('.'))
+",
+        );
+        index
+    }
+
+    #[test]
+    fn returns_incoming_calls_from_other_files() {
+        let index = index();
+        let uri = Url::parse("file:///autoload/plugin.vim").unwrap();
+        let items = prepare_call_hierarchy(
+            &index,
+            &uri,
+            "function! plugin#trim(text) abort\n",
+            Position {
+                line: 0,
+                character: 12,
+            },
+        )
+        .unwrap();
+        assert_eq!(items[0].name, "plugin#trim");
+        let incoming = incoming_calls(&index, &items[0]);
+        assert_eq!(
+            incoming
+                .iter()
+                .map(|c| (c.from.name.as_str(), c.from_ranges.len()))
+                .collect::<Vec<_>>(),
+            vec![("plugin.vim", 1), ("s:Run", 2)]
+        );
+        assert_eq!(
+            incoming[0].from.range,
+            Range::new(Position::new(0, 0), Position::new(5, 0))
+        );
+    }
+
+    #[test]
+    fn returns_outgoing_calls_to_user_functions() {
+        let index = index();
+        let uri = Url::parse("file:///autoload/plugin.vim").unwrap();
+        let item = &prepare_call_hierarchy(
+            &index,
+            &uri,
+            "function! plugin#trim(text) abort\n",
+            Position {
+                line: 0,
+                character: 12,
+            },
+        )
+        .unwrap()[0];
+        let outgoing: Vec<String> = outgoing_calls(&index, item)
+            .into_iter()
+            .map(|c| c.to.name)
+            .collect();
+        assert_eq!(outgoing, vec!["s:Strip"]);
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::code_lens::RUN_TEST_COMMAND;
+use crate::semantic_tokens;
+use lsp_types::CallHierarchyServerCapability;
+use lsp_types::ClientCapabilities;
+use lsp_types::CodeActionKind;
+use lsp_types::CodeActionOptions;
+use lsp_types::CodeActionProviderCapability;
+use lsp_types::CodeLensOptions;
+use lsp_types::DiagnosticOptions;
+use lsp_types::DiagnosticServerCapabilities;
+use lsp_types::DocumentLinkOptions;
+use lsp_types::ExecuteCommandOptions;
+use lsp_types::FoldingRangeProviderCapability;
+use lsp_types::HoverProviderCapability;
+use lsp_types::ImplementationProviderCapability;
+use lsp_types::MarkupKind;
+use lsp_types::OneOf;
+use lsp_types::PositionEncodingKind;
+use lsp_types::SelectionRangeProviderCapability;
+use lsp_types::SemanticTokensFullOptions;
+use lsp_types::SemanticTokensOptions;
+use lsp_types::SemanticTokensServerCapabilities;
+use lsp_types::ServerCapabilities;
+use lsp_types::SignatureHelpOptions;
+use lsp_types::TextDocumentSyncCapability;
+use lsp_types::TextDocumentSyncKind;
+use lsp_types::TextDocumentSyncOptions;
+use lsp_types::TextDocumentSyncSaveOptions;
+use lsp_types::TypeDefinitionProviderCapability;
+
+/// Returns the capabilities of the server, limited to the features the client supports.
+pub fn server_capabilities(client: &ClientCapabilities) -> ServerCapabilities {
+    let text_document = client.text_document.as_ref();
+    let code_action_literals = text_document
+        .and_then(|t| t.code_action.as_ref())
+        .map_or(false, |c| c.code_action_literal_support.is_some());
+    let semantic_tokens = text_document.and_then(|t| t.semantic_tokens.as_ref());
+    ServerCapabilities {
+        position_encoding: Some(position_encoding(client)),
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                // Partial content changes are not supported yet.
+                change: Some(TextDocumentSyncKind::FULL),
+                will_save: None,
+                will_save_wait_until: None,
+                save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+            },
+        )),
+        rename_provider: Some(OneOf::Left(true)),
+        document_highlight_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        workspace_symbol_provider: Some(OneOf::Left(true)),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+        implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
+        type_definition_provider: Some(TypeDefinitionProviderCapability::Simple(true)),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
+        document_link_provider: Some(DocumentLinkOptions {
+            resolve_provider: Some(false),
+            work_done_progress_options: Default::default(),
+        }),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![RUN_TEST_COMMAND.to_string()],
+            work_done_progress_options: Default::default(),
+        }),
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+            retrigger_characters: None,
+            work_done_progress_options: Default::default(),
+        }),
+        // Code actions are returned as literals, which older clients can't handle.
+        code_action_provider: if code_action_literals {
+            Some(CodeActionProviderCapability::Options(CodeActionOptions {
+                code_action_kinds: Some(vec![
+                    CodeActionKind::QUICKFIX,
+                    CodeActionKind::REFACTOR_EXTRACT,
+                    CodeActionKind::REFACTOR_INLINE,
+                ]),
+                work_done_progress_options: Default::default(),
+                resolve_provider: None,
+            }))
+        } else {
+            None
+        },
+        call_hierarchy_provider: text_document
+            .and_then(|t| t.call_hierarchy.as_ref())
+            .map(|_| CallHierarchyServerCapability::Simple(true)),
+        inlay_hint_provider: text_document
+            .and_then(|t| t.inlay_hint.as_ref())
+            .map(|_| OneOf::Left(true)),
+        diagnostic_provider: if supports_pull_diagnostics(client) {
+            Some(DiagnosticServerCapabilities::Options(DiagnosticOptions {
+                identifier: Some("vimscript".to_string()),
+                // Calls are checked against functions defined in other files.
+                inter_file_dependencies: true,
+                workspace_diagnostics: true,
+                work_done_progress_options: Default::default(),
+            }))
+        } else {
+            None
+        },
+        semantic_tokens_provider: semantic_tokens.map(|semantic_tokens| {
+            let delta = match semantic_tokens.requests.full {
+                Some(SemanticTokensFullOptions::Delta { delta }) => delta.unwrap_or(false),
+                _ => false,
+            };
+            SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
+                work_done_progress_options: Default::default(),
+                legend: semantic_tokens::legend(),
+                range: Some(semantic_tokens.requests.range.unwrap_or(false)),
+                full: Some(SemanticTokensFullOptions::Delta { delta: Some(delta) }),
+            })
+        }),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// Returns the encoding of positions used with the client.
+///
+/// Positions are computed in characters, so UTF-32 is preferred. Clients that don't support it
+/// get UTF-16, which only differs for characters outside of the Basic Multilingual Plane.
+pub fn position_encoding(client: &ClientCapabilities) -> PositionEncodingKind {
+    let supported = client
+        .general
+        .as_ref()
+        .and_then(|g| g.position_encodings.as_ref())
+        .map_or(false, |encodings| {
+            encodings.contains(&PositionEncodingKind::UTF32)
+        });
+    if supported {
+        PositionEncodingKind::UTF32
+    } else {
+        PositionEncodingKind::UTF16
+    }
+}
+
+/// Returns the preferred format of hover contents.
+pub fn hover_format(client: &ClientCapabilities) -> MarkupKind {
+    let formats = client
+        .text_document
+        .as_ref()
+        .and_then(|t| t.hover.as_ref())
+        .and_then(|h| h.content_format.as_ref());
+    match formats {
+        // Formats are ordered by preference of the client.
+        Some(formats) => formats
+            .iter()
+            .find(|f| **f == MarkupKind::Markdown || **f == MarkupKind::PlainText)
+            .cloned()
+            .unwrap_or(MarkupKind::PlainText),
+        None => MarkupKind::PlainText,
+    }
+}
+
+/// Returns true if the client requests diagnostics, instead of waiting for them to be published.
+pub fn supports_pull_diagnostics(client: &ClientCapabilities) -> bool {
+    client
+        .text_document
+        .as_ref()
+        .map_or(false, |t| t.diagnostic.is_some())
+}
+
+/// Returns true if the client shows progress of work started by the server, with a token created
+/// by `window/workDoneProgress/create`.
+pub fn supports_work_done_progress(client: &ClientCapabilities) -> bool {
+    client
+        .window
+        .as_ref()
+        .and_then(|w| w.work_done_progress)
+        .unwrap_or(false)
+}
+
+/// Returns true if the server can request settings with `workspace/configuration`.
+pub fn supports_configuration_requests(client: &ClientCapabilities) -> bool {
+    client
+        .workspace
+        .as_ref()
+        .and_then(|w| w.configuration)
+        .unwrap_or(false)
+}
+
+/// Returns true if the server can ask the client to pull all diagnostics again with
+/// `workspace/diagnostic/refresh`.
+pub fn supports_diagnostic_refresh(client: &ClientCapabilities) -> bool {
+    client
+        .workspace
+        .as_ref()
+        .and_then(|w| w.diagnostic.as_ref())
+        .and_then(|d| d.refresh_support)
+        .unwrap_or(false)
+}
+
+/// Returns true if the server can ask the client to watch files with `client/registerCapability`.
+pub fn supports_watched_files_registration(client: &ClientCapabilities) -> bool {
+    client
+        .workspace
+        .as_ref()
+        .and_then(|w| w.did_change_watched_files.as_ref())
+        .and_then(|d| d.dynamic_registration)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn client_capabilities(value: serde_json::Value) -> ClientCapabilities {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn does_not_advertise_features_unknown_to_client() {
+        let capabilities = server_capabilities(&client_capabilities(json!({})));
+        assert_eq!(
+            capabilities.position_encoding,
+            Some(PositionEncodingKind::UTF16)
+        );
+        assert_eq!(
+            capabilities.hover_provider,
+            Some(HoverProviderCapability::Simple(true))
+        );
+        assert_eq!(capabilities.code_action_provider, None);
+        assert_eq!(capabilities.call_hierarchy_provider, None);
+        assert_eq!(capabilities.inlay_hint_provider, None);
+        assert_eq!(capabilities.semantic_tokens_provider, None);
+        assert_eq!(capabilities.diagnostic_provider, None);
+    }
+
+    #[test]
+    fn advertises_features_supported_by_client() {
+        let capabilities = server_capabilities(&client_capabilities(json!({
+            "general": {"positionEncodings": ["utf-8", "utf-32", "utf-16"]},
+            "textDocument": {
+                "codeAction": {"codeActionLiteralSupport": {"codeActionKind": {"valueSet": []}}},
+                "callHierarchy": {},
+                "inlayHint": {},
+                "diagnostic": {},
+                "semanticTokens": {
+                    "requests": {"full": {"delta": true}},
+                    "tokenTypes": [],
+                    "tokenModifiers": [],
+                    "formats": ["relative"],
+                },
+            },
+        })));
+        assert_eq!(
+            capabilities.position_encoding,
+            Some(PositionEncodingKind::UTF32)
+        );
+        assert!(capabilities.code_action_provider.is_some());
+        assert_eq!(
+            capabilities.call_hierarchy_provider,
+            Some(CallHierarchyServerCapability::Simple(true))
+        );
+        assert_eq!(capabilities.inlay_hint_provider, Some(OneOf::Left(true)));
+        assert!(capabilities.diagnostic_provider.is_some());
+        match capabilities.semantic_tokens_provider {
+            Some(SemanticTokensServerCapabilities::SemanticTokensOptions(options)) => {
+                assert_eq!(options.range, Some(false));
+                assert_eq!(
+                    options.full,
+                    Some(SemanticTokensFullOptions::Delta { delta: Some(true) })
+                );
+            }
+            provider => panic!("unexpected semantic tokens provider: {:?}", provider),
+        }
+    }
+
+    #[test]
+    fn prefers_hover_format_of_client() {
+        assert_eq!(
+            hover_format(&client_capabilities(json!({}))),
+            MarkupKind::PlainText
+        );
+        assert_eq!(
+            hover_format(&client_capabilities(json!({
+                "textDocument": {"hover": {"contentFormat": ["markdown", "plaintext"]}},
+            }))),
+            MarkupKind::Markdown
+        );
+    }
+}
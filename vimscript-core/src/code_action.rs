@@ -0,0 +1,438 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::conversion::position_to_source_position;
+use crate::conversion::token_position_to_range;
+use crate::diagnostics::DiagnosticCode;
+use crate::lexer::Lexer;
+use crate::lexer::SourceLocation;
+use crate::lexer::TokenType;
+use crate::parser::Parser;
+use crate::parser::Program;
+use crate::references::find_references;
+use crate::references::reference_at;
+use crate::references::Symbol;
+use crate::visitor::walk_program;
+use crate::visitor::walk_stmt;
+use crate::visitor::Visitor;
+use lsp_types::CodeAction;
+use lsp_types::CodeActionKind;
+use lsp_types::Diagnostic;
+use lsp_types::NumberOrString;
+use lsp_types::TextEdit;
+use lsp_types::Url;
+use lsp_types::WorkspaceEdit;
+use std::collections::HashMap;
+use std::ops;
+
+/// Document and diagnostic that a fix is computed for.
+pub struct FixContext<'a> {
+    pub source: &'a str,
+    pub parser: &'a Parser<'a>,
+    pub program: &'a Program,
+    pub code: DiagnosticCode,
+    // Byte range of the diagnostic.
+    pub range: ops::Range<usize>,
+}
+
+/// Byte ranges to replace, with their new text.
+pub type Edits = Vec<(ops::Range<usize>, String)>;
+
+/// Machine-applicable fix for diagnostics with given code.
+pub struct Fix {
+    pub code: DiagnosticCode,
+    pub title: &'static str,
+    // Returns byte ranges to replace with the new text, or None if the fix does not apply, e.g.
+    // because the diagnostic is stale.
+    pub edits: fn(&FixContext) -> Option<Edits>,
+}
+
+/// All known fixes, new fixes only have to be registered here.
+pub const FIXES: &[Fix] = &[
+    Fix {
+        code: DiagnosticCode::MissingAbort,
+        title: "Add `abort`",
+        edits: add_abort,
+    },
+    Fix {
+        code: DiagnosticCode::ImplicitScope,
+        title: "Add `s:` prefix",
+        edits: add_script_scope,
+    },
+    Fix {
+        code: DiagnosticCode::CaseInsensitiveComparison,
+        title: "Use case-sensitive comparison",
+        edits: match_case,
+    },
+    Fix {
+        code: DiagnosticCode::MissingEndif,
+        title: "Insert `endif`",
+        edits: insert_end,
+    },
+    Fix {
+        code: DiagnosticCode::MissingEndfor,
+        title: "Insert `endfor`",
+        edits: insert_end,
+    },
+    Fix {
+        code: DiagnosticCode::MissingEndwhile,
+        title: "Insert `endwhile`",
+        edits: insert_end,
+    },
+    Fix {
+        code: DiagnosticCode::MissingEndtry,
+        title: "Insert `endtry`",
+        edits: insert_end,
+    },
+    Fix {
+        code: DiagnosticCode::MissingEndfunction,
+        title: "Insert `endfunction`",
+        edits: insert_end,
+    },
+    Fix {
+        code: DiagnosticCode::FunctionWithoutBang,
+        title: "Use `function!`",
+        edits: add_bang,
+    },
+];
+
+/// Returns quick fixes for the diagnostics of the document.
+pub fn code_actions(source: &str, uri: &Url, diagnostics: &[Diagnostic]) -> Vec<CodeAction> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    let mut actions = Vec::new();
+    for diagnostic in diagnostics {
+        let code = match &diagnostic.code {
+            Some(NumberOrString::String(code)) => DiagnosticCode::from_code(code),
+            _ => None,
+        };
+        let code = match code {
+            Some(code) => code,
+            None => continue,
+        };
+        let context = FixContext {
+            source: source,
+            parser: &parser,
+            program: &program,
+            code: code,
+            range: parser
+                .l
+                .source_offset(&position_to_source_position(&diagnostic.range.start))
+                ..parser
+                    .l
+                    .source_offset(&position_to_source_position(&diagnostic.range.end)),
+        };
+        for fix in FIXES.iter().filter(|fix| fix.code == code) {
+            let edits = match (fix.edits)(&context) {
+                Some(edits) => edits,
+                None => continue,
+            };
+            let mut changes = HashMap::new();
+            changes.insert(
+                uri.clone(),
+                edits
+                    .into_iter()
+                    .map(|(range, new_text)| TextEdit {
+                        range: token_position_to_range(
+                            &parser.resolve_location(SourceLocation { range: range }),
+                        ),
+                        new_text: new_text,
+                    })
+                    .collect(),
+            );
+            actions.push(CodeAction {
+                title: fix.title.to_string(),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(changes),
+                    document_changes: None,
+                    change_annotations: None,
+                }),
+                command: None,
+                is_preferred: Some(true),
+                disabled: None,
+                data: None,
+            });
+        }
+    }
+    actions
+}
+
+// Inserts `abort` after the argument list of the function whose name is the diagnostic range.
+fn add_abort(context: &FixContext) -> Option<Edits> {
+    let mut finder = FunctionName {
+        start: context.range.start,
+        end: None,
+    };
+    walk_program(&mut finder, context.program);
+    let name_end = finder.end?;
+    let mut depth = 0;
+    for token in context
+        .parser
+        .tokens()
+        .iter()
+        .filter(|t| t.location.range.start >= name_end)
+    {
+        match token.token_type {
+            TokenType::LeftParenthesis => depth += 1,
+            TokenType::RightParenthesis if depth == 1 => {
+                let end = token.location.range.end;
+                return Some(vec![(end..end, " abort".to_string())]);
+            }
+            TokenType::RightParenthesis => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+// Finds the end of the name of the function, possibly nested in other blocks, whose name starts
+// at `start`.
+struct FunctionName {
+    start: usize,
+    end: Option<usize>,
+}
+
+impl<'a> Visitor<'a> for FunctionName {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::Function(f) = &stmt.kind {
+            if f.name_location.range.start == self.start {
+                self.end = Some(f.name_location.range.end);
+                return;
+            }
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+// Renames all occurrences of the symbol in the document to its script-local name.
+fn add_script_scope(context: &FixContext) -> Option<Edits> {
+    let references = find_references(context.program, context.parser);
+    let symbol = &reference_at(&references, context.range.start)?.symbol;
+    let name = match symbol {
+        Symbol::Variable {
+            scope: 'g', name, ..
+        } => name.as_str(),
+        Symbol::Function(name) if !name.contains(':') && !name.contains('#') => name.as_str(),
+        _ => return None,
+    };
+    Some(
+        references
+            .iter()
+            .filter(|r| &r.symbol == symbol)
+            .map(|r| (r.location.range.clone(), format!("s:{}", name)))
+            .collect(),
+    )
+}
+
+// Makes `==` and `!=` match case, independent of 'ignorecase'.
+fn match_case(context: &FixContext) -> Option<Edits> {
+    match &context.source[context.range.clone()] {
+        "==" | "!=" => Some(vec![(
+            context.range.end..context.range.end,
+            "#".to_string(),
+        )]),
+        _ => None,
+    }
+}
+
+// Inserts the missing end keyword where the parser expected it, which is the end of the document
+// or the end keyword of the enclosing block. The keyword is indented like the start of its block.
+fn insert_end(context: &FixContext) -> Option<Edits> {
+    let keyword = context.code.as_str().strip_prefix("missing-")?;
+    let source = context.source;
+    let offset = context.range.start;
+    let indent = block_indent(context, offset).unwrap_or("");
+    if offset >= source.len() {
+        let new_text = if source.is_empty() || source.ends_with('\n') {
+            format!("{}{}\n", indent, keyword)
+        } else {
+            format!("\n{}{}\n", indent, keyword)
+        };
+        return Some(vec![(offset..offset, new_text)]);
+    }
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    if source[line_start..offset].trim().is_empty() {
+        Some(vec![(
+            line_start..line_start,
+            format!("{}{}\n", indent, keyword),
+        )])
+    } else {
+        // The enclosing block ends in the middle of a line, e.g. `call F() | endfunction`.
+        Some(vec![(offset..offset, format!("{} | ", keyword))])
+    }
+}
+
+// Returns the indentation of the line where the block that is not terminated before given offset
+// starts, e.g. of the `if` without `endif`.
+fn block_indent<'a>(context: &FixContext<'a>, offset: usize) -> Option<&'a str> {
+    let (start, end) = match context.code {
+        DiagnosticCode::MissingEndif => (TokenType::If, TokenType::EndIf),
+        DiagnosticCode::MissingEndfor => (TokenType::For, TokenType::EndFor),
+        DiagnosticCode::MissingEndwhile => (TokenType::While, TokenType::EndWhile),
+        DiagnosticCode::MissingEndtry => (TokenType::Try, TokenType::EndTry),
+        DiagnosticCode::MissingEndfunction => (TokenType::Function, TokenType::EndFunction),
+        _ => return None,
+    };
+    let tokens = context.parser.tokens();
+    let count = tokens.partition_point(|t| t.location.range.start < offset);
+    // Blocks of the same kind that are nested in the block are skipped.
+    let mut depth = 0;
+    for (i, token) in tokens[..count].iter().enumerate().rev() {
+        // Keywords only start blocks at the beginning of a statement.
+        let starts_statement = i == 0 || Parser::end_of_statement_token(tokens[i - 1].token_type);
+        if token.token_type == end && starts_statement {
+            depth += 1;
+        } else if token.token_type == start && starts_statement {
+            if depth == 0 {
+                let token_start = token.location.range.start;
+                let line_start = context.source[..token_start]
+                    .rfind('\n')
+                    .map_or(0, |i| i + 1);
+                let line = &context.source[line_start..token_start];
+                return Some(&line[..line.len() - line.trim_start().len()]);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+// Replaces `function` with `function!`, the diagnostic range is the keyword.
+fn add_bang(context: &FixContext) -> Option<Edits> {
+    let keyword = &context.source[context.range.clone()];
+    if keyword.len() < 2 || !"function".starts_with(keyword) {
+        return None;
+    }
+    Some(vec![(
+        context.range.end..context.range.end,
+        "!".to_string(),
+    )])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::diagnostics::diagnostics;
+    use pretty_assertions::assert_eq;
+
+    // Applies the edits of the first action with given title.
+    fn apply_fix(source: &str, title: &str) -> String {
+        let uri = Url::parse("file:///test.vim").unwrap();
+        let actions = code_actions(source, &uri, &diagnostics(source, &Config::default()));
+        let action = actions
+            .iter()
+            .find(|a| a.title == title)
+            .unwrap_or_else(|| panic!("no action `{}` in {:?}", title, actions));
+        let mut edits = action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri].clone();
+        edits.sort_by_key(|e| (e.range.start.line, e.range.start.character));
+        let mut lines: Vec<String> = source.split('\n').map(|l| l.to_string()).collect();
+        for edit in edits.iter().rev() {
+            assert_eq!(edit.range.start.line, edit.range.end.line);
+            let line = &mut lines[edit.range.start.line as usize];
+            line.replace_range(
+                edit.range.start.character as usize..edit.range.end.character as usize,
+                &edit.new_text,
+            );
+        }
+        lines.join("\n")
+    }
+
+    #[test]
+    fn adds_abort_after_arguments() {
+        assert_eq!(
+            apply_fix("function! s:Run(a = len([1]))\nendfunction", "Add `abort`"),
+            "function! s:Run(a = len([1])) abort\nendfunction"
+        );
+    }
+
+    #[test]
+    fn adds_abort_to_nested_function() {
+        assert_eq!(
+            apply_fix(
+                "if 1\n  function! s:G()\n  endfunction\nendif",
+                "Add `abort`"
+            ),
+            "if 1\n  function! s:G() abort\n  endfunction\nendif"
+        );
+    }
+
+    #[test]
+    fn adds_script_scope_to_all_occurrences() {
+        assert_eq!(
+            apply_fix(
+                "let count = 1\nfunction! s:Inc() abort\n  let g:count += count\nendfunction",
+                "Add `s:` prefix"
+            ),
+            "let s:count = 1\nfunction! s:Inc() abort\n  let s:count += count\nendfunction"
+        );
+    }
+
+    #[test]
+    fn makes_comparison_case_sensitive() {
+        assert_eq!(
+            apply_fix("let s:x = s:a != 'b'", "Use case-sensitive comparison"),
+            "let s:x = s:a !=# 'b'"
+        );
+    }
+
+    #[test]
+    fn inserts_missing_end_keywords() {
+        assert_eq!(
+            apply_fix(
+                "function! s:Run() abort\n  return 1",
+                "Insert `endfunction`"
+            ),
+            "function! s:Run() abort\n  return 1\nendfunction\n"
+        );
+        assert_eq!(apply_fix("if 1\n", "Insert `endif`"), "if 1\nendif\n");
+    }
+
+    #[test]
+    fn inserts_end_keyword_of_nested_block_before_enclosing_end() {
+        assert_eq!(
+            apply_fix(
+                "function! s:Run() abort\n  if 1\n    return 1\n  endif\n  if 2\n    return 2\nendfunction",
+                "Insert `endif`"
+            ),
+            "function! s:Run() abort\n  if 1\n    return 1\n  endif\n  if 2\n    return 2\n  endif\nendfunction"
+        );
+        assert_eq!(
+            apply_fix(
+                "while 1\n  for x in []\n    break\nendwhile",
+                "Insert `endfor`"
+            ),
+            "while 1\n  for x in []\n    break\n  endfor\nendwhile"
+        );
+    }
+
+    #[test]
+    fn adds_bang_to_function() {
+        assert_eq!(
+            apply_fix("function s:Run() abort\nendfunction", "Use `function!`"),
+            "function! s:Run() abort\nendfunction"
+        );
+    }
+
+    #[test]
+    fn ignores_diagnostics_without_known_code() {
+        let uri = Url::parse("file:///test.vim").unwrap();
+        let mut diagnostic = diagnostics("let s:x = s:a == 'b'", &Config::default()).remove(0);
+        diagnostic.code = Some(NumberOrString::String("unknown".to_string()));
+        assert_eq!(code_actions("", &uri, &[diagnostic]), vec![]);
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::index::Index;
+use lsp_types::CodeLens;
+use lsp_types::Command;
+use lsp_types::SymbolKind;
+use lsp_types::Url;
+use serde_json::json;
+
+/// Command of the lens that runs a test function, with the URI of the file and the name of the
+/// function as arguments.
+pub const RUN_TEST_COMMAND: &str = "vimscript.runTest";
+
+/// Returns lenses for functions defined in the file: the number of references to each function
+/// and a command that runs test functions.
+pub fn code_lenses(index: &Index, uri: &Url) -> Vec<CodeLens> {
+    let mut lenses = Vec::new();
+    for function in index
+        .symbols(uri)
+        .iter()
+        .filter(|s| s.kind == SymbolKind::FUNCTION)
+    {
+        let count = index.calls_of(uri, &function.name).count();
+        lenses.push(CodeLens {
+            range: function.location.range,
+            // The lens is only informative, so it has no command to execute.
+            command: Some(Command {
+                title: match count {
+                    1 => "1 reference".to_string(),
+                    count => format!("{} references", count),
+                },
+                command: "".to_string(),
+                arguments: None,
+            }),
+            data: None,
+        });
+        if is_test_function(&function.name) {
+            lenses.push(CodeLens {
+                range: function.location.range,
+                command: Some(Command {
+                    title: "Run test".to_string(),
+                    command: RUN_TEST_COMMAND.to_string(),
+                    arguments: Some(vec![json!(uri), json!(function.name)]),
+                }),
+                data: None,
+            });
+        }
+    }
+    lenses
+}
+
+// Returns true for test functions of Vim's test framework (`Test_` prefix) and methods of themis
+// test suites, except for their hooks.
+fn is_test_function(name: &str) -> bool {
+    let unscoped = match name.find(':') {
+        Some(1) => &name[2..],
+        _ => name.strip_prefix("<SID>").unwrap_or(name),
+    };
+    match unscoped.strip_prefix("suite.") {
+        Some(method) => !["before", "after", "before_each", "after_each"].contains(&method),
+        None => unscoped.starts_with("Test_"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn titles(index: &Index, uri: &Url) -> Vec<(u32, String)> {
+        code_lenses(index, uri)
+            .into_iter()
+            .map(|lens| (lens.range.start.line, lens.command.unwrap().title))
+            .collect()
+    }
+
+    #[test]
+    fn counts_references_in_workspace() {
+        let mut index = Index::new();
+        let uri = Url::parse("file:///autoload/plugin.vim").unwrap();
+        index.update(
+            &uri,
+            "function! plugin#trim(text) abort
+  return s:Strip(a:text)
+endfunction
+function! s:Strip(text) abort
+endfunction
+function! s:Unused() abort
+endfunction
+",
+        );
+        index.update(
+            &Url::parse("file:///plugin/plugin.vim").unwrap(),
+            "call plugin#trim('a')
+call s:Strip('b')
+nnoremap <leader>t :call plugin#trim(getline('.'))<CR>
+",
+        );
+        assert_eq!(
+            titles(&index, &uri),
+            vec![
+                (0, "2 references".to_string()),
+                (3, "1 reference".to_string()),
+                (5, "0 references".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn adds_run_command_to_tests() {
+        let mut index = Index::new();
+        let uri = Url::parse("file:///test/test_trim.vim").unwrap();
+        index.update(
+            &uri,
+            "function Test_trim()
+endfunction
+",
+        );
+        let lenses = code_lenses(&index, &uri);
+        assert_eq!(
+            lenses[1].command,
+            Some(Command {
+                title: "Run test".to_string(),
+                command: RUN_TEST_COMMAND.to_string(),
+                arguments: Some(vec![
+                    json!("file:///test/test_trim.vim"),
+                    json!("Test_trim")
+                ]),
+            })
+        );
+    }
+    #[test]
+    fn adds_run_command_to_themis_tests_but_not_hooks() {
+        let mut index = Index::new();
+        let uri = Url::parse("file:///test/trim.vim").unwrap();
+        index.update(
+            &uri,
+            "let s:suite = themis#suite('trim')
+function! s:suite.before_each() abort
+endfunction
+function! s:suite.trims_spaces() abort
+endfunction
+function! s:suite.after() abort
+endfunction
+",
+        );
+        let run_lenses: Vec<u32> = code_lenses(&index, &uri)
+            .into_iter()
+            .filter(|lens| lens.command.as_ref().unwrap().command == RUN_TEST_COMMAND)
+            .map(|lens| lens.range.start.line)
+            .collect();
+        assert_eq!(run_lenses, vec![3]);
+    }
+}
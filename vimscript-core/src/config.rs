@@ -0,0 +1,297 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::diagnostics::LintOptions;
+use crate::format::FormatOptions;
+use crate::glob;
+use crate::inlay_hint::InlayHintOptions;
+use serde::Deserialize;
+use serde::Serialize;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Names of project configuration files. If a directory has both, the first one is used.
+pub const CONFIG_FILE_NAMES: &[&str] = &[".vimlintrc.toml", "vimscript.toml"];
+
+/// Settings of the server, read from `initializationOptions` and the `vimscript` section of the
+/// client configuration. Missing settings have their default values.
+#[derive(PartialEq, Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct Config {
+    pub lint: LintOptions,
+    pub format: FormatOptions,
+    pub inlay_hints: InlayHintOptions,
+    /// Editor the scripts are written for. Functions and options missing in it are reported.
+    pub dialect: Dialect,
+    /// Oldest version of Vim the scripts have to support, e.g. `8.2` or `8.1.1305`. Functions and
+    /// options added later are reported. Only used for the `vim` dialect.
+    pub min_version: Option<String>,
+    /// Directories indexed in addition to the workspace, e.g. plugins the scripts depend on.
+    pub runtimepath: Vec<PathBuf>,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Dialect {
+    #[default]
+    Vim,
+    Neovim,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            ConfigError::Invalid(path, e) => write!(f, "invalid {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Returns the settings of the file, which are `base` with the settings of project configuration
+/// files applied.
+///
+/// Configuration files are looked up in the directory of the file and its parents, until a file
+/// with `root = true` is found. Files closer to the file take precedence. Settings in a
+/// `[[overrides]]` section of a configuration file only apply to files matching one of its `files`
+/// globs, relative to the directory of the configuration file:
+///
+/// ```toml
+/// root = true
+/// dialect = "neovim"
+///
+/// [lint.rules]
+/// missing-abort = "off"
+///
+/// [[overrides]]
+/// files = ["test/**/*.vim"]
+/// lint.rules.implicit-scope = "off"
+/// ```
+pub fn config_for_file(path: &Path, base: &Config) -> Result<Config, ConfigError> {
+    let path = &env::current_dir()
+        .map_err(|e| ConfigError::Io(path.to_path_buf(), e))?
+        .join(path);
+    let mut config_files = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        let config_path = match CONFIG_FILE_NAMES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|config_path| config_path.is_file())
+        {
+            Some(config_path) => config_path,
+            None => continue,
+        };
+        let content = fs::read_to_string(&config_path)
+            .map_err(|e| ConfigError::Io(config_path.clone(), e))?;
+        let settings: serde_json::Value = toml::from_str(&content)
+            .map_err(|e| ConfigError::Invalid(config_path.clone(), e.to_string()))?;
+        let root = settings.get("root") == Some(&serde_json::Value::Bool(true));
+        config_files.push((dir, config_path, settings));
+        if root {
+            break;
+        }
+    }
+
+    let mut merged = serde_json::to_value(base).unwrap();
+    for (dir, config_path, mut settings) in config_files.into_iter().rev() {
+        let overrides = settings
+            .as_object_mut()
+            .and_then(|settings| settings.remove("overrides"));
+        merge(&mut merged, settings);
+        // The path is relative to `dir`, as `dir` is one of its ancestors.
+        let relative_path = path
+            .strip_prefix(dir)
+            .unwrap()
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let overrides: Vec<Override> = match overrides {
+            Some(overrides) => serde_json::from_value(overrides)
+                .map_err(|e| ConfigError::Invalid(config_path.clone(), e.to_string()))?,
+            None => Vec::new(),
+        };
+        for o in overrides {
+            if o.files
+                .iter()
+                .any(|files| glob::matches(files, &relative_path))
+            {
+                merge(&mut merged, o.settings);
+            }
+        }
+    }
+    if let Some(merged) = merged.as_object_mut() {
+        merged.remove("root");
+    }
+    serde_json::from_value(merged)
+        .map_err(|e| ConfigError::Invalid(path.to_path_buf(), e.to_string()))
+}
+
+#[derive(Deserialize)]
+struct Override {
+    files: Vec<String>,
+    #[serde(flatten)]
+    settings: serde_json::Value,
+}
+
+// Recursively replaces values in `base` with the values in `other`, keeping values of keys that
+// are missing in `other`.
+fn merge(base: &mut serde_json::Value, other: serde_json::Value) {
+    match (base, other) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(other)) => {
+            for (key, value) in other {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, other) => *base = other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::RuleLevel;
+    use crate::format::IndentStyle;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn reads_partial_config() {
+        let config: Config = serde_json::from_value(json!({
+            "lint": {"rules": {"missing-abort": "off"}},
+            "format": {"indentStyle": "tabs"},
+            "dialect": "neovim",
+            "runtimepath": ["/plugins/vim-foo"],
+        }))
+        .unwrap();
+        assert_eq!(
+            config.lint.rules.get("missing-abort"),
+            Some(&RuleLevel::Off)
+        );
+        assert_eq!(config.format.indent_style, IndentStyle::Tabs);
+        assert_eq!(config.format.indent_width, 2);
+        assert_eq!(config.inlay_hints, InlayHintOptions::default());
+        assert_eq!(config.dialect, Dialect::Neovim);
+        assert_eq!(config.min_version, None);
+        assert_eq!(config.runtimepath, vec![PathBuf::from("/plugins/vim-foo")]);
+    }
+
+    #[test]
+    fn applies_config_files_of_parent_directories_and_overrides() {
+        let root = std::env::temp_dir().join(format!("vimscript-config-{}", std::process::id()));
+        let plugin = root.join("plugin");
+        fs::create_dir_all(plugin.join("test")).unwrap();
+        fs::write(root.join("vimscript.toml"), "dialect = \"neovim\"\n").unwrap();
+        fs::write(
+            plugin.join(".vimlintrc.toml"),
+            r#"
+root = true
+
+[lint.rules]
+missing-abort = "off"
+
+[format]
+indentWidth = 4
+
+[[overrides]]
+files = ["test/**"]
+format.indentStyle = "tabs"
+lint.rules.missing-abort = "error"
+"#,
+        )
+        .unwrap();
+
+        let base = Config {
+            min_version: Some("8.2".to_string()),
+            ..Config::default()
+        };
+        let config = config_for_file(&plugin.join("autoload/util.vim"), &base).unwrap();
+        let test_config = config_for_file(&plugin.join("test/util.vim"), &base).unwrap();
+        let _ = fs::remove_dir_all(&root);
+
+        // The config file in `root` is not used, as the one in `plugin` is marked as the root.
+        assert_eq!(config.dialect, Dialect::Vim);
+        assert_eq!(config.min_version, Some("8.2".to_string()));
+        assert_eq!(
+            config.lint.rules.get("missing-abort"),
+            Some(&RuleLevel::Off)
+        );
+        assert_eq!(config.format.indent_width, 4);
+        assert_eq!(config.format.indent_style, IndentStyle::Spaces);
+        assert_eq!(
+            test_config.lint.rules.get("missing-abort"),
+            Some(&RuleLevel::Error)
+        );
+        assert_eq!(test_config.format.indent_width, 4);
+        assert_eq!(test_config.format.indent_style, IndentStyle::Tabs);
+    }
+
+    #[test]
+    fn reads_dialect_from_config_files_and_overrides() {
+        let root = std::env::temp_dir().join(format!("vimscript-dialect-{}", std::process::id()));
+        let plugin = root.join("plugin");
+        fs::create_dir_all(plugin.join("compat")).unwrap();
+        fs::write(root.join("vimscript.toml"), "dialect = \"neovim\"\n").unwrap();
+        fs::write(
+            plugin.join(".vimlintrc.toml"),
+            r#"
+[[overrides]]
+files = ["compat/*.vim"]
+dialect = "vim"
+minVersion = "8.1"
+"#,
+        )
+        .unwrap();
+
+        let config = config_for_file(&plugin.join("plugin.vim"), &Config::default()).unwrap();
+        let compat_config =
+            config_for_file(&plugin.join("compat/job.vim"), &Config::default()).unwrap();
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(config.dialect, Dialect::Neovim);
+        assert_eq!(config.min_version, None);
+        assert_eq!(compat_config.dialect, Dialect::Vim);
+        assert_eq!(compat_config.min_version, Some("8.1".to_string()));
+    }
+
+    #[test]
+    fn reports_invalid_config_file() {
+        let root = std::env::temp_dir().join(format!("vimscript-invalid-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("vimscript.toml"), "dialect = \"emacs\"\n").unwrap();
+        let result = config_for_file(&root.join("plugin.vim"), &Config::default());
+        let _ = fs::remove_dir_all(&root);
+        assert!(matches!(result, Err(ConfigError::Invalid(_, _))));
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        assert!(serde_json::from_value::<Config>(json!({"dialekt": "vim"})).is_err());
+        assert!(serde_json::from_value::<Config>(json!({"lint": {"rule": {}}})).is_err());
+    }
+}
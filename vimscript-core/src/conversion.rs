@@ -0,0 +1,40 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Conversions between positions used by the lexer and the ones used by LSP.
+use crate::lexer::SourcePosition;
+use crate::lexer::TokenPosition;
+use lsp_types::Position;
+use lsp_types::Range;
+
+pub fn token_position_to_range(position: &TokenPosition) -> Range {
+    Range {
+        start: source_position_to_position(&position.start),
+        end: source_position_to_position(&position.end),
+    }
+}
+
+pub fn source_position_to_position(position: &SourcePosition) -> Position {
+    Position {
+        line: position.line as u32,
+        character: position.character as u32,
+    }
+}
+
+pub fn position_to_source_position(position: &Position) -> SourcePosition {
+    SourcePosition {
+        line: position.line as i32,
+        character: position.character as i32,
+    }
+}
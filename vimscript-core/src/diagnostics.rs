@@ -0,0 +1,317 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::config::Config;
+use crate::conversion::token_position_to_range;
+use crate::index::Index;
+use crate::lexer::Lexer;
+use crate::lint::lint;
+use crate::lint::unavailable_features;
+use crate::parser::Parser;
+use lsp_types::Diagnostic;
+use lsp_types::DiagnosticSeverity;
+use lsp_types::NumberOrString;
+use lsp_types::Url;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+/// Stable identifier of a diagnostic, sent as the `code` of LSP diagnostics so that code actions
+/// can be matched to them. Codes must not change once released.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum DiagnosticCode {
+    SyntaxError,
+    MissingEndif,
+    MissingEndfor,
+    MissingEndwhile,
+    MissingEndtry,
+    MissingEndfunction,
+    MissingAbort,
+    ImplicitScope,
+    CaseInsensitiveComparison,
+    FunctionWithoutBang,
+    UnknownFunction,
+    UnavailableFeature,
+}
+
+const CODES: &[DiagnosticCode] = &[
+    DiagnosticCode::SyntaxError,
+    DiagnosticCode::MissingEndif,
+    DiagnosticCode::MissingEndfor,
+    DiagnosticCode::MissingEndwhile,
+    DiagnosticCode::MissingEndtry,
+    DiagnosticCode::MissingEndfunction,
+    DiagnosticCode::MissingAbort,
+    DiagnosticCode::ImplicitScope,
+    DiagnosticCode::CaseInsensitiveComparison,
+    DiagnosticCode::FunctionWithoutBang,
+    DiagnosticCode::UnknownFunction,
+    DiagnosticCode::UnavailableFeature,
+];
+
+impl DiagnosticCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiagnosticCode::SyntaxError => "syntax-error",
+            DiagnosticCode::MissingEndif => "missing-endif",
+            DiagnosticCode::MissingEndfor => "missing-endfor",
+            DiagnosticCode::MissingEndwhile => "missing-endwhile",
+            DiagnosticCode::MissingEndtry => "missing-endtry",
+            DiagnosticCode::MissingEndfunction => "missing-endfunction",
+            DiagnosticCode::MissingAbort => "missing-abort",
+            DiagnosticCode::ImplicitScope => "implicit-scope",
+            DiagnosticCode::CaseInsensitiveComparison => "case-insensitive-comparison",
+            DiagnosticCode::FunctionWithoutBang => "function-without-bang",
+            DiagnosticCode::UnknownFunction => "unknown-function",
+            DiagnosticCode::UnavailableFeature => "unavailable-feature",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<DiagnosticCode> {
+        CODES.iter().copied().find(|c| c.as_str() == code)
+    }
+}
+
+/// Lint rules, configured by `lint` in the configuration.
+#[derive(PartialEq, Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintOptions {
+    /// Level of diagnostics by their code, e.g. `{"implicit-scope": "off"}`. Diagnostics with
+    /// other codes keep their default severity.
+    pub rules: HashMap<String, RuleLevel>,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RuleLevel {
+    Off,
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl LintOptions {
+    /// Drops diagnostics of disabled rules and sets the configured severity of the others.
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter_map(|mut diagnostic| {
+                let level = match &diagnostic.code {
+                    Some(NumberOrString::String(code)) => self.rules.get(code),
+                    _ => None,
+                };
+                diagnostic.severity = match level {
+                    Some(RuleLevel::Off) => return None,
+                    Some(RuleLevel::Error) => Some(DiagnosticSeverity::ERROR),
+                    Some(RuleLevel::Warning) => Some(DiagnosticSeverity::WARNING),
+                    Some(RuleLevel::Information) => Some(DiagnosticSeverity::INFORMATION),
+                    Some(RuleLevel::Hint) => Some(DiagnosticSeverity::HINT),
+                    None => diagnostic.severity,
+                };
+                Some(diagnostic)
+            })
+            .collect()
+    }
+}
+
+/// Returns parse errors and lint warnings of the script. Uses of functions and options that are
+/// missing in the editor described by the config are reported too.
+pub fn diagnostics(source: &str, config: &Config) -> Vec<Diagnostic> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    let mut diagnostics = Vec::new();
+    for error in &parser.errors {
+        diagnostics.push(diagnostic(
+            error.code,
+            DiagnosticSeverity::ERROR,
+            error.message.clone(),
+            token_position_to_range(&error.position),
+        ));
+    }
+    let mut warnings = lint(&program, &parser);
+    warnings.extend(unavailable_features(
+        &program,
+        &parser,
+        config.dialect,
+        config.min_version.as_deref(),
+    ));
+    warnings.sort_by_key(|w| w.location.range.start);
+    for warning in warnings {
+        diagnostics.push(diagnostic(
+            warning.code,
+            DiagnosticSeverity::WARNING,
+            warning.message,
+            token_position_to_range(&parser.resolve_location(warning.location)),
+        ));
+    }
+    diagnostics
+}
+
+/// Returns warnings that depend on other files of the workspace, which are more expensive to
+/// compute than `diagnostics`.
+///
+/// Calls of script-local functions that are not defined in the script and of autoload functions
+/// that are not defined in their autoload script are reported. Global functions may be defined
+/// outside of the workspace, so they are not checked.
+pub fn workspace_diagnostics(index: &Index, uri: &Url) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for call in index.calls_in(uri) {
+        let components: Vec<&str> = call.callee.split('#').collect();
+        let checked = if call.callee.starts_with("s:") {
+            true
+        } else if components.len() > 1 {
+            let path = format!(
+                "/autoload/{}.vim",
+                components[..components.len() - 1].join("/")
+            );
+            index.files().any(|file| file.path().ends_with(&path))
+        } else {
+            false
+        };
+        if checked && index.find_function(uri, &call.callee).is_none() {
+            diagnostics.push(diagnostic(
+                DiagnosticCode::UnknownFunction,
+                DiagnosticSeverity::WARNING,
+                format!("Unknown function: {}", call.callee),
+                call.range,
+            ));
+        }
+    }
+    diagnostics
+}
+
+/// Returns an identifier of the diagnostics, which is the same for equal diagnostics, so that a
+/// client pulling diagnostics can be told that they didn't change.
+pub fn result_id(diagnostics: &[Diagnostic]) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(diagnostics)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
+fn diagnostic(
+    code: DiagnosticCode,
+    severity: DiagnosticSeverity,
+    message: String,
+    range: lsp_types::Range,
+) -> Diagnostic {
+    Diagnostic {
+        range: range,
+        message: message,
+        code: Some(NumberOrString::String(code.as_str().to_string())),
+        code_description: None,
+        related_information: None,
+        severity: Some(severity),
+        source: Some("vimscript".to_string()),
+        tags: None,
+        data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn codes_round_trip() {
+        for code in CODES {
+            assert_eq!(DiagnosticCode::from_code(code.as_str()), Some(*code));
+        }
+        assert_eq!(DiagnosticCode::from_code("unknown"), None);
+    }
+
+    #[test]
+    fn reports_errors_and_warnings_with_codes() {
+        let diagnostics = diagnostics("function! s:Run()\n", &Config::default());
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| (d.code.clone().unwrap(), d.severity.unwrap()))
+                .collect::<Vec<_>>(),
+            vec![(
+                NumberOrString::String("missing-endfunction".to_string()),
+                DiagnosticSeverity::ERROR
+            )]
+        );
+    }
+
+    #[test]
+    fn applies_configured_rule_levels() {
+        let options: LintOptions = serde_json::from_value(serde_json::json!({
+            "rules": {"missing-abort": "off", "implicit-scope": "error"},
+        }))
+        .unwrap();
+        let diagnostics = options.apply(diagnostics(
+            "function! s:Run()\nendfunction\nlet count = s:x == 'a'\n",
+            &Config::default(),
+        ));
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| (d.code.clone().unwrap(), d.severity.unwrap()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    NumberOrString::String("implicit-scope".to_string()),
+                    DiagnosticSeverity::ERROR
+                ),
+                (
+                    NumberOrString::String("case-insensitive-comparison".to_string()),
+                    DiagnosticSeverity::WARNING
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn result_id_changes_with_diagnostics() {
+        let first = diagnostics("function! s:Run()\n", &Config::default());
+        let second = diagnostics("function! s:Run()\n", &Config::default());
+        let fixed = diagnostics("function! s:Run()\nendfunction\n", &Config::default());
+        assert_eq!(result_id(&first), result_id(&second));
+        assert_ne!(result_id(&first), result_id(&fixed));
+    }
+
+    #[test]
+    fn reports_unknown_script_local_and_autoload_functions() {
+        let mut index = Index::new();
+        let uri = Url::parse("file:///plugin/format.vim").unwrap();
+        index.update(
+            &Url::parse("file:///autoload/format.vim").unwrap(),
+            "function! format#Run() abort\nendfunction\n",
+        );
+        index.update(
+            &uri,
+            "function! s:Run() abort\nendfunction\n\
+             call s:Run()\ncall s:Missing()\ncall format#Run()\ncall format#Missing()\n\
+             call other#Run()\ncall Global()\n",
+        );
+        assert_eq!(
+            workspace_diagnostics(&index, &uri)
+                .into_iter()
+                .map(|d| d.message)
+                .collect::<Vec<_>>(),
+            vec![
+                "Unknown function: s:Missing".to_string(),
+                "Unknown function: format#Missing".to_string(),
+            ]
+        );
+    }
+}
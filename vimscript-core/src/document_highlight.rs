@@ -0,0 +1,132 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::conversion::position_to_source_position;
+use crate::conversion::token_position_to_range;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::references::find_references;
+use crate::references::reference_at;
+use lsp_types::DocumentHighlight;
+use lsp_types::DocumentHighlightKind;
+use lsp_types::Position;
+
+/// Returns all occurrences of the symbol at given position.
+///
+/// Definitions and assignments are reported as writes, all other occurrences as reads. Returns an
+/// empty list if there is no symbol at the position.
+pub fn document_highlights(source: &str, pos: Position) -> Vec<DocumentHighlight> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    let offset = parser.l.source_offset(&position_to_source_position(&pos));
+    let references = find_references(&program, &parser);
+    let symbol = match reference_at(&references, offset) {
+        Some(reference) => reference.symbol.clone(),
+        None => return Vec::new(),
+    };
+    references
+        .iter()
+        .filter(|r| r.symbol == symbol)
+        .map(|r| DocumentHighlight {
+            range: token_position_to_range(&parser.resolve_location(r.location.clone())),
+            kind: Some(if r.write {
+                DocumentHighlightKind::WRITE
+            } else {
+                DocumentHighlightKind::READ
+            }),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn highlights(source: &str, line: u32, character: u32) -> Vec<String> {
+        document_highlights(
+            source,
+            Position {
+                line: line,
+                character: character,
+            },
+        )
+        .into_iter()
+        .map(|h| {
+            format!(
+                "{}:{}-{}:{} {}",
+                h.range.start.line,
+                h.range.start.character,
+                h.range.end.line,
+                h.range.end.character,
+                if h.kind == Some(DocumentHighlightKind::WRITE) {
+                    "write"
+                } else {
+                    "read"
+                }
+            )
+        })
+        .collect()
+    }
+
+    #[test]
+    fn highlights_arguments_and_their_uses() {
+        let source = "function! s:Add(count) abort
+  let l:count = 1
+  return a:count + l:count
+endfunction";
+        assert_eq!(
+            highlights(source, 2, 11),
+            vec!["0:16-0:21 write", "2:9-2:16 read"]
+        );
+        assert_eq!(
+            highlights(source, 1, 8),
+            vec!["1:6-1:13 write", "2:19-2:26 read"]
+        );
+    }
+
+    #[test]
+    fn separates_locals_of_different_functions() {
+        let source = "function! A()
+  let x = 1
+endfunction
+function! B()
+  let x = 2
+  return x
+endfunction";
+        assert_eq!(
+            highlights(source, 4, 6),
+            vec!["4:6-4:7 write", "5:9-5:10 read"]
+        );
+    }
+
+    #[test]
+    fn highlights_loop_variables_and_functions() {
+        let source = "for item in s:Items()\n  call add(s:list, item)\nendfor\nfunction! s:Items()\nendfunction";
+        assert_eq!(
+            highlights(source, 1, 20),
+            vec!["0:4-0:8 write", "1:19-1:23 read"]
+        );
+        assert_eq!(
+            highlights(source, 0, 13),
+            vec!["0:12-0:19 read", "3:10-3:17 write"]
+        );
+    }
+
+    #[test]
+    fn returns_nothing_on_whitespace() {
+        assert_eq!(highlights("let x = 1\n\n", 1, 0), Vec::<String>::new());
+        assert_eq!(highlights("let  x = 1", 0, 3), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,323 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::call_hierarchy::function_calls;
+use crate::glob;
+use crate::index::Index;
+use lsp_types::DocumentLink;
+use lsp_types::Position;
+use lsp_types::Range;
+use lsp_types::Url;
+use std::env;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Returns links to files loaded by `source` and `runtime`, to help tags referenced in comments
+/// and to the files that define called autoload functions.
+///
+/// Only targets that exist are linked. `runtime` patterns and autoload functions are resolved
+/// within the indexed workspace files.
+pub fn document_links(index: &Index, uri: &Url, source: &str) -> Vec<DocumentLink> {
+    let mut links = Vec::new();
+    for (line_number, line) in source.lines().enumerate() {
+        let line_number = line_number as u32;
+        let command_start = line.len()
+            - line
+                .trim_start_matches(|c: char| c.is_whitespace() || c == ':')
+                .len();
+        let command = &line[command_start..];
+        if command.starts_with('"') {
+            for (start, tag) in help_references(line) {
+                if let Some(location) = index.find_help_tag(tag) {
+                    let mut target = location.uri.clone();
+                    target.set_fragment(Some(&format!("L{}", location.range.start.line + 1)));
+                    links.push(link(line, line_number, start, tag, target));
+                }
+            }
+            continue;
+        }
+        let name_length = command
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(command.len());
+        let name = &command[..name_length];
+        let arguments_start = command_start + name_length;
+        let arguments = line[arguments_start..].trim_start_matches('!');
+        let arguments_start = line.len() - arguments.len();
+        if is_abbreviation(name, "source", 2) {
+            if let Some((start, argument)) = words(arguments).into_iter().next() {
+                if let Some(target) = resolve_source(index, uri, argument) {
+                    links.push(link(
+                        line,
+                        line_number,
+                        arguments_start + start,
+                        argument,
+                        target,
+                    ));
+                }
+            }
+        } else if is_abbreviation(name, "runtime", 2) {
+            for (start, argument) in words(arguments) {
+                // `:runtime START plugin/*.vim` only changes where the files are searched.
+                if ["START", "OPT", "PACK", "ALL"].contains(&argument) {
+                    continue;
+                }
+                if let Some(target) = resolve_runtime(index, argument) {
+                    links.push(link(
+                        line,
+                        line_number,
+                        arguments_start + start,
+                        argument,
+                        target,
+                    ));
+                }
+            }
+        }
+    }
+
+    for call in function_calls(source) {
+        let components: Vec<&str> = call.callee.split('#').collect();
+        if components.len() < 2 {
+            continue;
+        }
+        let path = format!(
+            "autoload/{}.vim",
+            components[..components.len() - 1].join("/")
+        );
+        if let Some(target) = resolve_runtime(index, &path) {
+            links.push(DocumentLink {
+                range: call.range,
+                target: Some(target),
+                tooltip: None,
+                data: None,
+            });
+        }
+    }
+    links.sort_by_key(|link| link.range.start);
+    links
+}
+
+fn link(line: &str, line_number: u32, start: usize, text: &str, target: Url) -> DocumentLink {
+    let start_character = line[..start].chars().count() as u32;
+    DocumentLink {
+        range: Range {
+            start: Position::new(line_number, start_character),
+            end: Position::new(line_number, start_character + text.chars().count() as u32),
+        },
+        target: Some(target),
+        tooltip: None,
+        data: None,
+    }
+}
+
+// Returns true if the name is the command or its abbreviation, e.g. `so` for `source`.
+fn is_abbreviation(name: &str, command: &str, min_length: usize) -> bool {
+    name.len() >= min_length && command.starts_with(name)
+}
+
+// Returns whitespace separated words with their byte offsets.
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                words.push((s, &text[s..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    words
+}
+
+// Returns byte offsets and names of help tags referenced in the comment line, either as
+// `:help tag` or `|tag|`. Tags between bars can be followed by punctuation, e.g. `see |tag|.`
+fn help_references(line: &str) -> Vec<(usize, &str)> {
+    let mut tags = Vec::new();
+    let parts = words(line);
+    for (i, (_, word)) in parts.iter().enumerate() {
+        if *word == ":h" || *word == ":help" {
+            if let Some((tag_start, tag)) = parts.get(i + 1) {
+                tags.push((*tag_start, *tag));
+            }
+        }
+    }
+    let mut rest = 0;
+    while let Some(open) = line[rest..].find('|').map(|i| rest + i) {
+        let close = match line[open + 1..].find('|') {
+            Some(i) => open + 1 + i,
+            None => break,
+        };
+        let tag = &line[open + 1..close];
+        if !tag.is_empty() && !tag.contains(char::is_whitespace) {
+            tags.push((open + 1, tag));
+            rest = close + 1;
+        } else {
+            // The closing bar may open the next tag, e.g. in `a | b |tag|`.
+            rest = close;
+        }
+    }
+    tags.sort_by_key(|(start, _)| *start);
+    tags
+}
+
+// Resolves the path of a `source` command. Relative paths are resolved against the directory of
+// the script, which is where plugins usually keep the sourced files.
+fn resolve_source(index: &Index, uri: &Url, argument: &str) -> Option<Url> {
+    let file = uri.to_file_path().ok()?;
+    let dir = file.parent()?;
+    let path = if let Some(rest) = argument
+        .strip_prefix("<sfile>:p:h")
+        .or_else(|| argument.strip_prefix("<sfile>:h"))
+    {
+        dir.join(rest.trim_start_matches('/'))
+    } else if let Some(rest) = argument.strip_prefix("~/") {
+        PathBuf::from(env::var("HOME").ok()?).join(rest)
+    } else {
+        dir.join(argument)
+    };
+    let path = normalize(&path);
+    let target = Url::from_file_path(&path).ok()?;
+    if index.files().any(|f| f == &target) || path.is_file() {
+        Some(target)
+    } else {
+        None
+    }
+}
+
+// Removes `.` and `..` from the path without accessing the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// Resolves the pattern of a `runtime` command to the indexed file that it would load first.
+//
+// The workspace root is not known, so the pattern is matched against every trailing part of the
+// path and files closer to the root are preferred.
+fn resolve_runtime(index: &Index, pattern: &str) -> Option<Url> {
+    let mut matches: Vec<(usize, &Url)> = index
+        .files()
+        .filter_map(|uri| {
+            let path = uri.path();
+            let depth = path
+                .match_indices('/')
+                .map(|(i, _)| &path[i + 1..])
+                .position(|suffix| glob::matches(pattern, suffix))?;
+            Some((depth, uri))
+        })
+        .collect();
+    matches.sort_by(|(a_depth, a), (b_depth, b)| {
+        a_depth
+            .cmp(b_depth)
+            .then_with(|| a.as_str().cmp(b.as_str()))
+    });
+    matches.first().map(|(_, uri)| (*uri).clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn create_index() -> Index {
+        let mut index = Index::new();
+        for path in &[
+            "/plugin/format.vim",
+            "/plugin/format/settings.vim",
+            "/autoload/format/util.vim",
+        ] {
+            index.update(&Url::parse(&format!("file://{}", path)).unwrap(), "");
+        }
+        index.update_help(
+            &Url::parse("file:///doc/format.txt").unwrap(),
+            "*format.txt*\n\n*format-options*\n",
+        );
+        index
+    }
+
+    fn links(source: &str) -> Vec<String> {
+        let uri = Url::parse("file:///plugin/format.vim").unwrap();
+        document_links(&create_index(), &uri, source)
+            .into_iter()
+            .map(|link| {
+                format!(
+                    "{}:{}-{}:{} {}",
+                    link.range.start.line,
+                    link.range.start.character,
+                    link.range.end.line,
+                    link.range.end.character,
+                    link.target.unwrap()
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn links_sourced_files() {
+        assert_eq!(
+            links("source <sfile>:h/format/settings.vim\nso format/missing.vim\n"),
+            vec!["0:7-0:36 file:///plugin/format/settings.vim"]
+        );
+    }
+
+    #[test]
+    fn links_runtime_files() {
+        assert_eq!(
+            links("runtime! plugin/*.vim autoload/format/util.vim\n"),
+            vec![
+                "0:9-0:21 file:///plugin/format.vim",
+                "0:22-0:46 file:///autoload/format/util.vim",
+            ]
+        );
+    }
+
+    #[test]
+    fn links_help_tags_in_comments() {
+        assert_eq!(
+            links(concat!(
+                "\" See :help format-options and |format.txt|.\n",
+                "\" (|format-options|, a | b)\n",
+                "let x = 1 \" |format.txt|\n",
+            )),
+            vec![
+                "0:12-0:26 file:///doc/format.txt#L3",
+                "0:32-0:42 file:///doc/format.txt#L1",
+                "1:4-1:18 file:///doc/format.txt#L3",
+            ]
+        );
+    }
+
+    #[test]
+    fn links_autoload_functions() {
+        assert_eq!(
+            links("call format#util#trim('a')\ncall missing#trim('b')\n"),
+            vec!["0:5-0:21 file:///autoload/format/util.vim"]
+        );
+    }
+}
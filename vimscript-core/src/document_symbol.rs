@@ -0,0 +1,337 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::conversion::token_position_to_range;
+use crate::lexer::Lexer;
+use crate::lexer::SourceLocation;
+use crate::parser::Parser;
+use lsp_types::DocumentSymbol;
+use lsp_types::Range;
+use lsp_types::SymbolKind;
+use std::collections::HashSet;
+
+/// Returns the outline of the script.
+///
+/// Functions, commands, mappings and script variables are reported at the top level, autocommands
+/// are nested in their augroups.
+pub fn document_symbols(source: &str) -> Vec<DocumentSymbol> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    let mut collector = Collector {
+        parser: &parser,
+        source: source,
+        variables: HashSet::new(),
+    };
+    let mut symbols = Vec::new();
+    collector.collect(&program.statements, &mut symbols);
+    symbols
+}
+
+struct Collector<'a> {
+    parser: &'a Parser<'a>,
+    source: &'a str,
+    // Variables that were already reported, only the first assignment is part of the outline.
+    variables: HashSet<String>,
+}
+
+impl<'a> Collector<'a> {
+    fn collect<'s>(
+        &mut self,
+        stmts: impl IntoIterator<Item = &'s Stmt>,
+        symbols: &mut Vec<DocumentSymbol>,
+    ) {
+        for stmt in stmts {
+            match &stmt.kind {
+                StmtKind::Function(f) => symbols.push(self.symbol(
+                    f.name.to_string(),
+                    Some(function_detail(&f.name).to_string()),
+                    SymbolKind::FUNCTION,
+                    stmt,
+                    self.location_range(&f.name_location),
+                    None,
+                )),
+                StmtKind::Let(l) => {
+                    if let ExprKind::Identifier(var) = &l.var.kind {
+                        if self.variables.insert(var.name.clone()) {
+                            symbols.push(self.symbol(
+                                var.name.clone(),
+                                None,
+                                SymbolKind::VARIABLE,
+                                stmt,
+                                self.location_range(var.name_location()),
+                                None,
+                            ));
+                        }
+                    }
+                }
+                StmtKind::Augroup(g) => {
+                    if g.delete || g.is_end() {
+                        continue;
+                    }
+                    // Only autocommands belong to the group, other definitions in its body are
+                    // reported at the top level.
+                    let (autocmds, others): (Vec<&Stmt>, Vec<&Stmt>) = g
+                        .body
+                        .iter()
+                        .partition(|s| matches!(s.kind, StmtKind::Autocmd(_)));
+                    let mut children = Vec::new();
+                    self.collect(autocmds, &mut children);
+                    symbols.push(self.symbol(
+                        g.name.clone(),
+                        None,
+                        SymbolKind::NAMESPACE,
+                        stmt,
+                        self.location_range(&g.name_location),
+                        Some(children),
+                    ));
+                    self.collect(others, symbols);
+                }
+                StmtKind::Autocmd(a) => {
+                    if a.events.is_empty() {
+                        continue;
+                    }
+                    let name = match &a.pattern {
+                        Some(pattern) => format!("{} {}", a.events.join(","), pattern),
+                        None => a.events.join(","),
+                    };
+                    symbols.push(self.symbol(
+                        name,
+                        a.command.clone(),
+                        SymbolKind::EVENT,
+                        stmt,
+                        self.range(stmt),
+                        None,
+                    ));
+                }
+                StmtKind::Command(c) => symbols.push(self.symbol(
+                    c.name.clone(),
+                    Some(c.definition.clone()),
+                    SymbolKind::OPERATOR,
+                    stmt,
+                    self.location_range(&c.name_location),
+                    None,
+                )),
+                StmtKind::Map(m) => symbols.push(self.symbol(
+                    format!("{} {}", m.command, m.lhs),
+                    Some(m.rhs.clone()),
+                    SymbolKind::KEY,
+                    stmt,
+                    self.location_range(&m.lhs_location),
+                    None,
+                )),
+                // Variables defined in blocks outside of functions are script variables too,
+                // e.g. `if !exists('g:loaded') | let g:loaded = 1 | endif`.
+                StmtKind::If(s) => self.collect_if(s, symbols),
+                StmtKind::While(s) => self.collect(&s.body, symbols),
+                StmtKind::For(s) => self.collect(&s.body, symbols),
+                StmtKind::Try(s) => {
+                    self.collect(&s.body, symbols);
+                    if let Some(finally) = &s.finally {
+                        self.collect(finally, symbols);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn collect_if(&mut self, stmt: &IfStatement, symbols: &mut Vec<DocumentSymbol>) {
+        self.collect(&stmt.then, symbols);
+        match &stmt.else_cond {
+            ElseCond::None => {}
+            ElseCond::Else(stmts) => self.collect(stmts, symbols),
+            ElseCond::ElseIf(stmt) => self.collect_if(stmt, symbols),
+        }
+    }
+
+    // `deprecated` is superseded by `tags`, but it still has to be set.
+    #[allow(deprecated)]
+    fn symbol(
+        &self,
+        name: String,
+        detail: Option<String>,
+        kind: SymbolKind,
+        stmt: &Stmt,
+        selection_range: Range,
+        children: Option<Vec<DocumentSymbol>>,
+    ) -> DocumentSymbol {
+        DocumentSymbol {
+            name: name,
+            detail: detail,
+            kind: kind,
+            tags: None,
+            deprecated: None,
+            range: self.range(stmt),
+            selection_range: selection_range,
+            children: children,
+        }
+    }
+
+    fn location_range(&self, location: &SourceLocation) -> Range {
+        token_position_to_range(&self.parser.resolve_location(location.clone()))
+    }
+
+    // Returns the range of the statement, without the new line at its end.
+    fn range(&self, stmt: &Stmt) -> Range {
+        let start = stmt.span.start.0 as usize;
+        let mut end = stmt.span.end.0 as usize;
+        if end > start && self.source[..end].ends_with('\n') {
+            end -= 1;
+        }
+        token_position_to_range(
+            &self
+                .parser
+                .resolve_location(SourceLocation { range: start..end }),
+        )
+    }
+}
+
+fn function_detail(name: &FunctionName) -> &'static str {
+    if name.is_dict_function() {
+        "dictionary function"
+    } else if !name.autoload_path.is_empty() {
+        "autoload function"
+    } else if name.is_script_local() {
+        "script-local function"
+    } else {
+        "global function"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::Position;
+    use pretty_assertions::assert_eq;
+
+    // Returns names and details of symbols and their children, indented by depth.
+    fn outline(symbols: &[DocumentSymbol], depth: usize) -> Vec<String> {
+        let mut result = Vec::new();
+        for symbol in symbols {
+            result.push(format!(
+                "{}{} [{}]",
+                "  ".repeat(depth),
+                symbol.name,
+                symbol.detail.as_ref().map_or("", |d| d.as_str())
+            ));
+            if let Some(children) = &symbol.children {
+                result.extend(outline(children, depth + 1));
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn returns_outline_of_script() {
+        let source = "if !exists('g:loaded_plugin')
+  let g:loaded_plugin = 1
+endif
+let s:count = 0
+let s:count = 1
+function! s:Format() abort
+  let l:local = 1
+endfunction
+function! plugin#run() abort
+endfunction
+augroup plugin
+  autocmd!
+  autocmd BufWritePre *.vim call s:Format()
+augroup END
+command! -nargs=0 Format call s:Format()
+nnoremap <silent> <leader>f :Format<CR>
+";
+        let symbols = document_symbols(source);
+        assert_eq!(
+            outline(&symbols, 0),
+            vec![
+                "g:loaded_plugin []",
+                "s:count []",
+                "s:Format [script-local function]",
+                "plugin#run [autoload function]",
+                "plugin []",
+                "  BufWritePre *.vim [call s:Format()]",
+                "Format [call s:Format()]",
+                "nnoremap <leader>f [:Format<CR>]",
+            ]
+        );
+        assert_eq!(
+            symbols.iter().map(|s| s.kind).collect::<Vec<SymbolKind>>(),
+            vec![
+                SymbolKind::VARIABLE,
+                SymbolKind::VARIABLE,
+                SymbolKind::FUNCTION,
+                SymbolKind::FUNCTION,
+                SymbolKind::NAMESPACE,
+                SymbolKind::OPERATOR,
+                SymbolKind::KEY,
+            ]
+        );
+        assert_eq!(
+            symbols[4].children.as_ref().unwrap()[0].kind,
+            SymbolKind::EVENT
+        );
+    }
+
+    #[test]
+    fn reports_definitions_in_augroup_at_top_level() {
+        let source = "augroup plugin
+  autocmd BufWritePre *.vim call s:Format()
+function! s:Format() abort
+endfunction
+let g:x = 1
+";
+        assert_eq!(
+            outline(&document_symbols(source), 0),
+            vec![
+                "plugin []",
+                "  BufWritePre *.vim [call s:Format()]",
+                "s:Format [script-local function]",
+                "g:x []",
+            ]
+        );
+    }
+
+    #[test]
+    fn uses_name_as_selection_range() {
+        let source = "function! s:Format() abort\nendfunction\n";
+        let symbols = document_symbols(source);
+        assert_eq!(
+            symbols[0].range,
+            Range {
+                start: Position {
+                    line: 0,
+                    character: 0
+                },
+                end: Position {
+                    line: 1,
+                    character: 11
+                },
+            }
+        );
+        assert_eq!(
+            symbols[0].selection_range,
+            Range {
+                start: Position {
+                    line: 0,
+                    character: 10
+                },
+                end: Position {
+                    line: 0,
+                    character: 18
+                },
+            }
+        );
+    }
+}
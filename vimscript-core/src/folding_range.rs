@@ -0,0 +1,277 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::lexer::Lexer;
+use crate::lexer::SourceLocation;
+use crate::parser::Parser;
+use crate::visitor::walk_program;
+use crate::visitor::walk_stmt;
+use crate::visitor::Visitor;
+use lsp_types::FoldingRange;
+use lsp_types::FoldingRangeKind;
+
+/// Returns folding ranges of the script, ordered by their first line.
+///
+/// Blocks and augroups are folded based on the spans of their statements, heredocs, comment blocks
+/// and `{{{`/`}}}` markers are found by scanning the lines of the source, as they are not part of
+/// the AST.
+pub fn folding_ranges(source: &str) -> Vec<FoldingRange> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut ranges = Vec::new();
+
+    let heredocs = heredocs(&lines);
+    for (start, end) in &heredocs {
+        ranges.push(folding_range(*start, *end, None));
+    }
+    let in_heredoc = |line: usize| {
+        heredocs
+            .iter()
+            .any(|(start, end)| *start < line && line <= *end)
+    };
+
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    let mut blocks = Blocks {
+        parser: &parser,
+        source: source,
+        ranges: Vec::new(),
+    };
+    walk_program(&mut blocks, &program);
+    ranges.extend(
+        blocks
+            .ranges
+            .into_iter()
+            .filter(|range| !in_heredoc(range.start_line as usize)),
+    );
+
+    let mut comment_start: Option<usize> = None;
+    let mut markers = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if in_heredoc(i) || heredocs.iter().any(|(start, _)| *start == i) {
+            continue;
+        }
+        let comment = line.find('"').map(|i| &line[i..]).unwrap_or("");
+        let is_marker = comment.contains("{{{") || comment.contains("}}}");
+        // Marker lines are folded on their own, they don't extend comment blocks.
+        let is_comment = line.trim_start().starts_with('"') && !is_marker;
+        match comment_start {
+            Some(start) if !is_comment => {
+                ranges.push(folding_range(start, i - 1, Some(FoldingRangeKind::Comment)));
+                comment_start = None;
+            }
+            None if is_comment => comment_start = Some(i),
+            _ => {}
+        }
+        if comment.contains("{{{") {
+            markers.push(i);
+        } else if comment.contains("}}}") {
+            if let Some(start) = markers.pop() {
+                ranges.push(folding_range(start, i, Some(FoldingRangeKind::Region)));
+            }
+        }
+    }
+    if let Some(start) = comment_start {
+        ranges.push(folding_range(
+            start,
+            lines.len() - 1,
+            Some(FoldingRangeKind::Comment),
+        ));
+    }
+
+    ranges.retain(|range| range.start_line < range.end_line);
+    ranges.sort_by_key(|range| (range.start_line, range.end_line));
+    ranges
+}
+
+struct Blocks<'a> {
+    parser: &'a Parser<'a>,
+    source: &'a str,
+    ranges: Vec<FoldingRange>,
+}
+
+impl<'a> Visitor<'a> for Blocks<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        let kind = match &stmt.kind {
+            StmtKind::Function(_)
+            | StmtKind::If(_)
+            | StmtKind::For(_)
+            | StmtKind::While(_)
+            | StmtKind::Try(_) => Some(None),
+            StmtKind::Augroup(_) => Some(Some(FoldingRangeKind::Region)),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            // The span includes the new line after the end of the block.
+            let start = stmt.span.start.0 as usize;
+            let mut end = stmt.span.end.0 as usize;
+            if end > start && self.source[..end].ends_with('\n') {
+                end -= 1;
+            }
+            let position = self
+                .parser
+                .resolve_location(SourceLocation { range: start..end });
+            self.ranges.push(folding_range(
+                position.start.line as usize,
+                position.end.line as usize,
+                kind,
+            ));
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+// Returns the first and the last line of all heredocs, e.g. `let lines =<< trim END`.
+fn heredocs(lines: &[&str]) -> Vec<(usize, usize)> {
+    let mut heredocs = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if let Some((marker, trim)) = heredoc_marker(lines[i]) {
+            let start = i;
+            i += 1;
+            while i < lines.len() {
+                let line = if trim {
+                    lines[i].trim_start()
+                } else {
+                    lines[i]
+                };
+                if line == marker {
+                    heredocs.push((start, i));
+                    break;
+                }
+                i += 1;
+            }
+        }
+        i += 1;
+    }
+    heredocs
+}
+
+// Returns the end marker of the heredoc started on the line, and whether its lines are trimmed.
+fn heredoc_marker(line: &str) -> Option<(&str, bool)> {
+    let line = line.trim_start();
+    if !(line.starts_with("let ") || line.starts_with("const ")) {
+        return None;
+    }
+    let (_, rest) = line.split_at(line.find("=<<")? + 3);
+    let mut trim = false;
+    for word in rest.split_whitespace() {
+        match word {
+            "trim" => trim = true,
+            "eval" => {}
+            marker => return Some((marker, trim)),
+        }
+    }
+    None
+}
+
+fn folding_range(start: usize, end: usize, kind: Option<FoldingRangeKind>) -> FoldingRange {
+    FoldingRange {
+        start_line: start as u32,
+        start_character: None,
+        end_line: end as u32,
+        end_character: None,
+        kind: kind,
+        collapsed_text: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn folds(source: &str) -> Vec<(u32, u32, Option<FoldingRangeKind>)> {
+        folding_ranges(source)
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect()
+    }
+
+    #[test]
+    fn folds_blocks() {
+        let source = "function! s:Run() abort
+  for item in [1, 2]
+    if item
+      return 1
+    endif
+  endfor
+endfunction
+while 1
+endwhile
+try
+  call s:Run()
+endtry
+";
+        assert_eq!(
+            folds(source),
+            vec![
+                (0, 6, None),
+                (1, 5, None),
+                (2, 4, None),
+                (7, 8, None),
+                (9, 11, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn folds_augroups_and_comments() {
+        let source = "\" Plugin settings.
+\" Loaded once.
+augroup plugin
+  autocmd!
+  \" Formats on save.
+  autocmd BufWritePre *.vim call s:Format()
+augroup END
+\" Single comment.
+";
+        assert_eq!(
+            folds(source),
+            vec![
+                (0, 1, Some(FoldingRangeKind::Comment)),
+                (2, 6, Some(FoldingRangeKind::Region)),
+            ]
+        );
+    }
+
+    #[test]
+    fn folds_markers() {
+        let source = "\" Options {{{
+set nocompatible
+\" Nested {{{1
+set hidden
+\" }}}
+\" }}}
+";
+        assert_eq!(
+            folds(source),
+            vec![
+                (0, 5, Some(FoldingRangeKind::Region)),
+                (2, 4, Some(FoldingRangeKind::Region)),
+            ]
+        );
+    }
+
+    #[test]
+    fn folds_heredocs() {
+        let source = "let lines =<< trim END
+  function! NotAFunction()
+  endfunction
+  END
+let x = 1
+";
+        assert_eq!(folds(source), vec![(0, 3, None)]);
+    }
+}
@@ -14,12 +14,18 @@
 
 use crate::ast::*;
 use crate::parser::Program;
+use serde::Deserialize;
+use serde::Serialize;
 use std::io::Write;
 
 pub fn format(program: &Program) -> String {
+    format_with_options(program, &FormatOptions::default())
+}
+
+pub fn format_with_options(program: &Program, options: &FormatOptions) -> String {
     let mut w = Vec::new();
     let mut state = State {
-        options: Options { indent: 2 },
+        options: options.clone(),
         out: &mut w,
         indent: 0,
     };
@@ -27,15 +33,46 @@ pub fn format(program: &Program) -> String {
     return String::from_utf8(w).unwrap();
 }
 
-// TODO: Make this struct public.
-struct Options {
-    // Number of spaces to use for indentation.
-    // TODO: Support spaces and tabs.
-    indent: usize,
+/// Formats a single statement without indentation, e.g. to print code generated by refactorings.
+pub fn format_stmt(stmt: &Stmt) -> String {
+    let mut w = Vec::new();
+    let mut state = State {
+        options: FormatOptions::default(),
+        out: &mut w,
+        indent: 0,
+    };
+    state.format_stmt(stmt);
+    return String::from_utf8(w).unwrap();
+}
+
+/// Style of the formatted code, configured by `format` in the configuration.
+#[derive(PartialEq, Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct FormatOptions {
+    /// Number of spaces used for one level of indentation.
+    pub indent_width: usize,
+    pub indent_style: IndentStyle,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent_width: 2,
+            indent_style: IndentStyle::Spaces,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IndentStyle {
+    Spaces,
+    /// One tab for each level of indentation, `indent_width` is ignored.
+    Tabs,
 }
 
 struct State<'a, W: Write> {
-    options: Options,
+    options: FormatOptions,
     out: &'a mut W,
     // Current identation level
     indent: usize,
@@ -57,6 +94,7 @@ impl<'a, W: Write> State<'a, W> {
             StmtKind::Function(s) => self.format_statement_function(&s),
             StmtKind::If(s) => self.format_if_statement(&s),
             StmtKind::Let(s) => self.format_let_statement(&s),
+            StmtKind::Call(s) => self.format_call_statement(&s),
             StmtKind::Return(s) => self.format_return_statement(&s),
             StmtKind::Finish(s) => self.format_finish_statement(&s),
             StmtKind::Empty() => self.write("\n"),
@@ -66,9 +104,35 @@ impl<'a, W: Write> State<'a, W> {
 
     fn format_statement_function(&mut self, stmt: &FunctionStatement) {
         self.write_indent();
-        self.write("function ");
-        self.write(&stmt.name);
-        self.write("()\n");
+        self.write(if stmt.overwrite {
+            "function! "
+        } else {
+            "function "
+        });
+        self.write(&stmt.name.to_string());
+        self.write("(");
+        for (i, argument) in stmt.arguments.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.write(&argument.name);
+            if let Some(value) = &argument.default_value {
+                self.write(" = ");
+                self.format_expression(&value.kind);
+            }
+        }
+        if stmt.variadic {
+            self.write(if stmt.arguments.is_empty() {
+                "..."
+            } else {
+                ", ..."
+            });
+        }
+        self.write(")");
+        if stmt.abort {
+            self.write(" abort");
+        }
+        self.write("\n");
 
         self.indent += 1;
         for s in &stmt.body {
@@ -80,12 +144,35 @@ impl<'a, W: Write> State<'a, W> {
         self.write("endfunction\n");
     }
 
-    fn format_return_statement(&mut self, _stmt: &ReturnStatement) {
+    fn format_return_statement(&mut self, stmt: &ReturnStatement) {
         self.write_indent();
         self.write("return");
+        if let Some(value) = &stmt.value {
+            self.write(" ");
+            self.format_expression(&value.kind);
+        }
+        self.write("\n");
+    }
+
+    fn format_call_statement(&mut self, stmt: &CallStatement) {
+        self.write_indent();
+        self.write("call ");
+        self.write(&stmt.name);
+        self.format_arguments(&stmt.arguments);
         self.write("\n");
     }
 
+    fn format_arguments(&mut self, arguments: &[Expr]) {
+        self.write("(");
+        for (i, argument) in arguments.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.format_expression(&argument.kind);
+        }
+        self.write(")");
+    }
+
     fn format_finish_statement(&mut self, _stmt: &FinishStatement) {
         self.write_indent();
         self.write("finish");
@@ -93,16 +180,19 @@ impl<'a, W: Write> State<'a, W> {
     }
 
     fn write_indent(&mut self) {
-        self.write(&" ".repeat(self.options.indent * self.indent));
+        let indent = match self.options.indent_style {
+            IndentStyle::Spaces => " ".repeat(self.options.indent_width * self.indent),
+            IndentStyle::Tabs => "\t".repeat(self.indent),
+        };
+        self.write(&indent);
     }
 
     fn format_let_statement(&mut self, stmt: &LetStatement) {
         self.write_indent();
-        self.write("let ");
+        self.write(if stmt.constant { "const " } else { "let " });
         self.format_expression(&stmt.var.kind);
         self.write(" ");
-        // TODO: Fix it for other operatrs
-        self.write("=");
+        self.write(stmt.operator.as_str().trim_matches('`'));
         self.write(" ");
         self.format_expression(&stmt.value.kind);
         self.write("\n");
@@ -112,6 +202,25 @@ impl<'a, W: Write> State<'a, W> {
         match expr {
             ExprKind::Identifier(e) => self.write(&e.name().to_string()),
             ExprKind::Number(e) => self.write(&e.value().to_string()),
+            ExprKind::Function(e) => {
+                self.format_expression(&e.callee.kind);
+                self.format_arguments(&e.arguments);
+            }
+            ExprKind::Array(e) => {
+                self.write("[");
+                for (i, element) in e.elements.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.format_expression(&element.kind);
+                }
+                self.write("]");
+            }
+            ExprKind::Member(e) => {
+                self.format_expression(&e.base.kind);
+                self.write(".");
+                self.write(&e.name);
+            }
             _ => panic!("unknown expression"),
         };
     }
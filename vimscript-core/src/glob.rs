@@ -0,0 +1,71 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Returns true if the path matches the pattern.
+///
+/// Supports `*` and `?`, which match any characters except `/`, and `**`, which matches any
+/// number of directories. Paths and patterns always use `/` as the separator.
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    matches_chars(&pattern, &path)
+}
+
+fn matches_chars(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        ['*', '*', '/', rest @ ..] => {
+            // `**/` also matches no directories at all.
+            matches_chars(rest, path)
+                || path
+                    .iter()
+                    .enumerate()
+                    .any(|(i, c)| *c == '/' && matches_chars(rest, &path[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=path.len()).any(|i| matches_chars(rest, &path[i..])),
+        ['*', rest @ ..] => {
+            let segment = path.iter().position(|c| *c == '/').unwrap_or(path.len());
+            (0..=segment).any(|i| matches_chars(rest, &path[i..]))
+        }
+        ['?', rest @ ..] => match path {
+            [c, path_rest @ ..] if *c != '/' => matches_chars(rest, path_rest),
+            _ => false,
+        },
+        [p, rest @ ..] => match path {
+            [c, path_rest @ ..] if c == p => matches_chars(rest, path_rest),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards_within_directory() {
+        assert!(matches("plugin/*.vim", "plugin/format.vim"));
+        assert!(matches("plugin/?.vim", "plugin/a.vim"));
+        assert!(!matches("plugin/*.vim", "plugin/format/util.vim"));
+        assert!(!matches("plugin/*.vim", "autoload/format.vim"));
+    }
+
+    #[test]
+    fn matches_any_directories() {
+        assert!(matches("**/*.vim", "format.vim"));
+        assert!(matches("**/*.vim", "autoload/format/util.vim"));
+        assert!(matches("autoload/**", "autoload/format/util.vim"));
+        assert!(!matches("test/**/*.vim", "autoload/format.vim"));
+    }
+}
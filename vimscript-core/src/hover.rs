@@ -0,0 +1,397 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::builtins;
+use crate::conversion::position_to_source_position;
+use crate::conversion::token_position_to_range;
+use crate::lexer::Lexer;
+use crate::lexer::TokenType;
+use crate::parser::Parser;
+use crate::visitor::walk_program;
+use crate::visitor::walk_stmt;
+use crate::visitor::Visitor;
+use lsp_types::Hover;
+use lsp_types::HoverContents;
+use lsp_types::MarkupContent;
+use lsp_types::MarkupKind;
+use lsp_types::Position;
+
+/// Returns information about the symbol at the position, formatted as Markdown or plain text.
+pub fn hover(source: &str, pos: Position, format: MarkupKind) -> Option<Hover> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    let mut definitions = Definitions {
+        functions: Vec::new(),
+        lets: Vec::new(),
+    };
+    walk_program(&mut definitions, &program);
+
+    let token = parser.find_token(position_to_source_position(&pos)).ok()?;
+    if token.token_type != TokenType::Ident {
+        return None;
+    }
+    let tokens = parser.tokens();
+    let index = tokens.iter().position(|t| *t == token)?;
+    let previous = if index > 0 {
+        tokens[index - 1].token_type
+    } else {
+        TokenType::NewLine
+    };
+    let next = tokens.get(index + 1).map(|t| t.token_type);
+    let name = parser.identifier_name(&token);
+
+    let value = if name.starts_with('&') {
+        option_hover(strip_option_scope(&name[1..]))?
+    } else if previous == TokenType::Set {
+        option_hover(&name)?
+    } else if next == Some(TokenType::LeftParenthesis) {
+        function_hover(source, &definitions, &name)?
+    } else {
+        variable_hover(&definitions, &name, token.location.range.start)
+    };
+
+    let value = match format {
+        MarkupKind::Markdown => value,
+        MarkupKind::PlainText => markdown_to_plain_text(&value),
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: format,
+            value: value,
+        }),
+        range: Some(token_position_to_range(
+            &parser.resolve_location(token.location),
+        )),
+    })
+}
+
+// Drops code fences and backticks, which is all the Markdown used in hovers.
+fn markdown_to_plain_text(markdown: &str) -> String {
+    markdown
+        .lines()
+        .filter(|line| !line.starts_with("```"))
+        .map(|line| line.replace('`', ""))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// All functions and let statements in the program, in the order of their appearance.
+struct Definitions<'a> {
+    functions: Vec<(&'a Stmt, &'a FunctionStatement)>,
+    lets: Vec<(&'a Stmt, &'a LetStatement)>,
+}
+
+impl<'a> Visitor<'a> for Definitions<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Function(f) => self.functions.push((stmt, f)),
+            StmtKind::Let(l) => self.lets.push((stmt, l)),
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+impl<'a> Definitions<'a> {
+    // Returns the innermost function that contains given byte offset.
+    fn enclosing_function(&self, offset: usize) -> Option<&(&'a Stmt, &'a FunctionStatement)> {
+        self.functions
+            .iter()
+            .filter(|(stmt, _)| {
+                (stmt.span.start.0 as usize) <= offset && offset < (stmt.span.end.0 as usize)
+            })
+            .last()
+    }
+}
+
+// Returns the option name without the `l:` or `g:` scope of `&l:name` and `&g:name`.
+pub(crate) fn strip_option_scope(name: &str) -> &str {
+    if name.starts_with("l:") || name.starts_with("g:") {
+        return &name[2..];
+    }
+    name
+}
+
+fn option_hover(name: &str) -> Option<String> {
+    let option = builtins::find_option(name).or_else(|| {
+        // `set noexpandtab` and `set invpaste` refer to `expandtab` and `paste`.
+        if name.starts_with("no") {
+            builtins::find_option(&name[2..])
+        } else if name.starts_with("inv") {
+            builtins::find_option(&name[3..])
+        } else {
+            None
+        }
+    })?;
+    Some(format!(
+        "```vim\n&{}\n```\n\n{} (default {})\n\n{}",
+        option.name, option.option_type, option.default, option.help
+    ))
+}
+
+fn function_hover(source: &str, definitions: &Definitions, name: &str) -> Option<String> {
+    if let Some((stmt, _)) = definitions
+        .functions
+        .iter()
+        .find(|(_, f)| f.name.to_string() == name)
+    {
+        let start = stmt.span.start.0 as usize;
+        let header = source[start..].lines().next().unwrap_or("").trim_end();
+        let mut value = format!("```vim\n{}\n```", header);
+        let comments = comments_above(source, start);
+        if !comments.is_empty() {
+            value.push_str("\n\n");
+            value.push_str(&comments.join("\n"));
+        }
+        return Some(value);
+    }
+    let function = builtins::find_function(name)?;
+    Some(format!(
+        "```vim\n{}\n```\n\nReturns `{}`.\n\n{}",
+        function.signature(),
+        function.return_type,
+        function.help
+    ))
+}
+
+// Returns the content of the comment block immediately above the line with given offset.
+pub(crate) fn comments_above(source: &str, offset: usize) -> Vec<&str> {
+    let line = source[..offset].matches('\n').count();
+    let mut comments: Vec<&str> = source
+        .lines()
+        .take(line)
+        .collect::<Vec<&str>>()
+        .into_iter()
+        .rev()
+        .map(|l| l.trim_start())
+        .take_while(|l| l.starts_with('"'))
+        .map(|l| {
+            let comment = &l[1..];
+            comment.strip_prefix(' ').unwrap_or(comment)
+        })
+        .collect();
+    comments.reverse();
+    comments
+}
+
+fn variable_hover(definitions: &Definitions, name: &str, offset: usize) -> String {
+    let function = definitions.enclosing_function(offset);
+    let (scope, variable) = resolve_scope(name, function.is_some());
+    let mut value = format!("```vim\n{}\n```\n\n{}", name, scope_description(scope));
+
+    let first_assignment = definitions.lets.iter().find(|(stmt, l)| {
+        let var = match &l.var.kind {
+            ExprKind::Identifier(var) => var,
+            _ => return false,
+        };
+        let let_function = definitions.enclosing_function(stmt.span.start.0 as usize);
+        if resolve_scope(&var.name, let_function.is_some()) != (scope, variable) {
+            return false;
+        }
+        // Local variables are only visible in the function that declares them.
+        scope != 'l' || let_function.map(|f| f.0.id) == function.map(|f| f.0.id)
+    });
+    if let Some((_, l)) = first_assignment {
+        if let Some(var_type) = infer_let_type(l) {
+            value.push_str(&format!(", `{}`", var_type));
+        }
+    }
+    value
+}
+
+// Returns the scope and the name without the scope prefix.
+//
+// Variables without explicit scope are local in functions and global otherwise.
+pub(crate) fn resolve_scope(name: &str, in_function: bool) -> (char, &str) {
+    let mut chars = name.chars();
+    if let (Some(scope), Some(':')) = (chars.next(), chars.next()) {
+        if "gslabwtv".contains(scope) {
+            return (scope, &name[2..]);
+        }
+    }
+    (if in_function { 'l' } else { 'g' }, name)
+}
+
+fn scope_description(scope: char) -> &'static str {
+    match scope {
+        'g' => "global variable",
+        's' => "script-local variable",
+        'l' => "function-local variable",
+        'a' => "function argument",
+        'b' => "buffer-local variable",
+        'w' => "window-local variable",
+        't' => "tab-local variable",
+        'v' => "Vim variable",
+        _ => "variable",
+    }
+}
+
+fn infer_let_type(stmt: &LetStatement) -> Option<String> {
+    match stmt.operator {
+        TokenType::DotAssign => Some("String".to_string()),
+        _ => infer_type(&stmt.value),
+    }
+}
+
+fn infer_type(expr: &Expr) -> Option<String> {
+    match &expr.kind {
+        ExprKind::Number(_) => Some("Number".to_string()),
+        ExprKind::StringLiteral(_) => Some("String".to_string()),
+        ExprKind::Array(_) => Some("List".to_string()),
+        ExprKind::Dictionary(_) => Some("Dictionary".to_string()),
+        ExprKind::Paren(e) => infer_type(&e.expr),
+        ExprKind::Unary(e) => match e.operator {
+            TokenType::Bang => Some("Number".to_string()),
+            _ => infer_type(&e.expr),
+        },
+        ExprKind::Infix(e) => match e.operator {
+            TokenType::Dot => Some("String".to_string()),
+            TokenType::Plus
+            | TokenType::Minus
+            | TokenType::Multiply
+            | TokenType::Divide
+            | TokenType::Modulo => infer_type(&e.left),
+            // All comparison and logical operators evaluate to a number.
+            _ => Some("Number".to_string()),
+        },
+        ExprKind::Choose(e) => {
+            let lhs = infer_type(&e.lhs)?;
+            if Some(&lhs) == infer_type(&e.rhs).as_ref() {
+                Some(lhs)
+            } else {
+                None
+            }
+        }
+        ExprKind::Function(e) => {
+            let name = match &e.callee.kind {
+                ExprKind::Identifier(callee) => callee.name(),
+                _ => return None,
+            };
+            let function = builtins::find_function(name)?;
+            match function.return_type {
+                "any" => None,
+                return_type => Some(return_type.to_string()),
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn hover_value(source: &str, line: u32, character: u32) -> Option<String> {
+        let hover = hover(
+            source,
+            Position {
+                line: line,
+                character: character,
+            },
+            MarkupKind::Markdown,
+        )?;
+        match hover.contents {
+            HoverContents::Markup(content) => Some(content.value),
+            contents => panic!("unexpected hover contents: {:?}", contents),
+        }
+    }
+
+    #[test]
+    fn shows_builtin_function_signature() {
+        assert_eq!(
+            hover_value("call add(l:list, 1)", 0, 6).unwrap(),
+            "```vim\nadd({object}, {expr})\n```\n\nReturns `List/Blob`.\n\n\
+             Append the item {expr} to List or Blob {object}. Returns the resulting List or Blob."
+        );
+    }
+
+    #[test]
+    fn shows_plain_text_to_clients_without_markdown() {
+        let hover = hover(
+            "call add(l:list, 1)",
+            Position {
+                line: 0,
+                character: 6,
+            },
+            MarkupKind::PlainText,
+        )
+        .unwrap();
+        assert_eq!(
+            hover.contents,
+            HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::PlainText,
+                value: "add({object}, {expr})\n\nReturns List/Blob.\n\n\
+                        Append the item {expr} to List or Blob {object}. \
+                        Returns the resulting List or Blob."
+                    .to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn shows_user_function_header_with_comments() {
+        let source = "\" Adds two numbers.
+\" Returns the sum.
+function! s:Add(a, b) abort
+  return 0
+endfunction
+let s:sum = s:Add(1, 2)";
+        assert_eq!(
+            hover_value(source, 5, 13).unwrap(),
+            "```vim\nfunction! s:Add(a, b) abort\n```\n\nAdds two numbers.\nReturns the sum."
+        );
+    }
+
+    #[test]
+    fn shows_variable_scope_and_type() {
+        assert_eq!(
+            hover_value("let g:count = 5\nlet g:other = g:count", 1, 16).unwrap(),
+            "```vim\ng:count\n```\n\nglobal variable, `Number`"
+        );
+    }
+
+    #[test]
+    fn shows_local_variable_in_function() {
+        let source = "let name = 1
+function! F() abort
+  let name = 'a' . 'b'
+  return l:name
+endfunction";
+        assert_eq!(
+            hover_value(source, 3, 11).unwrap(),
+            "```vim\nl:name\n```\n\nfunction-local variable, `String`"
+        );
+    }
+
+    #[test]
+    fn shows_option() {
+        assert_eq!(
+            hover_value("let &ts = 4", 0, 5).unwrap(),
+            "```vim\n&tabstop\n```\n\nnumber (default 8)\n\n\
+             Number of spaces that a <Tab> in the file counts for."
+        );
+        assert_eq!(
+            hover_value("set noexpandtab", 0, 6).unwrap(),
+            "```vim\n&expandtab\n```\n\nboolean (default off)\n\n\
+             In Insert mode: Use the appropriate number of spaces to insert a <Tab>."
+        );
+    }
+
+    #[test]
+    fn returns_none_for_unknown_function() {
+        assert_eq!(hover_value("call unknown()", 0, 6), None);
+    }
+}
@@ -0,0 +1,296 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::call_hierarchy::function_name;
+use crate::conversion::position_to_source_position;
+use crate::conversion::token_position_to_range;
+use crate::index::Index;
+use crate::lexer::Lexer;
+use crate::lexer::SourceLocation;
+use crate::lexer::Token;
+use crate::lexer::TokenType;
+use crate::parser::Parser;
+use crate::references::find_references;
+use crate::references::reference_at;
+use crate::visitor::walk_program;
+use crate::visitor::walk_stmt;
+use crate::visitor::Visitor;
+use lsp_types::Location;
+use lsp_types::Position;
+use lsp_types::SymbolKind;
+use lsp_types::Url;
+
+/// Returns the dictionary functions that can be called by the method at the position, e.g. `run`
+/// in `self.run()` or `s:formatter.run()`.
+///
+/// Methods are functions defined on a dictionary (`function s:formatter.run()`) and funcrefs
+/// stored in a dictionary (`let s:formatter.run = function('s:Run')`). The type of `self` is not
+/// known, so all methods with the name are returned for it. For other dictionaries, methods
+/// defined on the same dictionary are preferred.
+pub fn implementations(index: &Index, uri: &Url, source: &str, pos: Position) -> Vec<Location> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    let offset = parser.l.source_offset(&position_to_source_position(&pos));
+    let (receiver, method) = match method_at(&parser, offset) {
+        Some(method) => method,
+        None => return Vec::new(),
+    };
+
+    // Methods with the dictionary that they are defined on.
+    let mut methods: Vec<(String, Location)> = Vec::new();
+    for (file, symbols) in index.files().map(|f| (f, index.symbols(f))) {
+        for symbol in symbols.iter().filter(|s| s.kind == SymbolKind::FUNCTION) {
+            let name = FunctionName::parse(&symbol.name);
+            if !name.is_dict_function() || name.name != method {
+                continue;
+            }
+            // Script-local dictionaries are not visible in other scripts.
+            if name.is_script_local() && file != uri {
+                continue;
+            }
+            methods.push((dictionary_name(&name), symbol.location.clone()));
+        }
+    }
+    let mut funcrefs = Funcrefs {
+        method: &method,
+        funcrefs: Vec::new(),
+    };
+    walk_program(&mut funcrefs, &program);
+    for (dictionary, function) in funcrefs.funcrefs {
+        if let Some(function) = index.find_function(uri, &function) {
+            methods.push((dictionary, function.location.clone()));
+        }
+    }
+
+    if receiver != "self" {
+        let receiver = variable_name(&receiver);
+        if methods
+            .iter()
+            .any(|(dictionary, _)| *dictionary == receiver)
+        {
+            methods.retain(|(dictionary, _)| *dictionary == receiver);
+        }
+    }
+    let mut locations: Vec<Location> = methods.into_iter().map(|(_, l)| l).collect();
+    locations.sort_by(|a, b| (a.uri.as_str(), a.range.start).cmp(&(b.uri.as_str(), b.range.start)));
+    locations.dedup();
+    locations
+}
+
+/// Returns the assignment that creates the dictionary at the position: the dictionary of the
+/// method for `self` in a dictionary function and the first assignment for other variables.
+pub fn type_definition(uri: &Url, source: &str, pos: Position) -> Option<Location> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    let offset = parser.l.source_offset(&position_to_source_position(&pos));
+    let references = find_references(&program, &parser);
+    let reference = reference_at(&references, offset)?;
+    let text = parser.l.token_text(&reference.location);
+
+    let definition = if text == "self" {
+        let mut functions = DictFunctions {
+            offset: offset,
+            dictionary: None,
+        };
+        walk_program(&mut functions, &program);
+        let dictionary = functions.dictionary?;
+        references
+            .iter()
+            .find(|r| r.write && variable_name(parser.l.token_text(&r.location)) == dictionary)?
+    } else {
+        references
+            .iter()
+            .find(|r| r.write && r.symbol == reference.symbol)?
+    };
+    Some(Location {
+        uri: uri.clone(),
+        range: token_position_to_range(&parser.resolve_location(definition.location.clone())),
+    })
+}
+
+// Returns the receiver and the name of the method at the offset, e.g. `s:formatter` and `run`
+// for `s:formatter.run`.
+fn method_at(parser: &Parser, offset: usize) -> Option<(String, String)> {
+    let tokens = parser.tokens();
+    let i = tokens.iter().position(|t| {
+        t.token_type == TokenType::Ident
+            && t.location.range.start <= offset
+            && offset <= t.location.range.end
+    })?;
+    let mut start = i;
+    while start >= 2 && is_key(&tokens[start - 2], &tokens[start - 1], &tokens[start]) {
+        start -= 2;
+    }
+    if start == i {
+        return None;
+    }
+    let receiver = SourceLocation {
+        range: tokens[start].location.range.start..tokens[i - 2].location.range.end,
+    };
+    Some((
+        parser.l.token_text(&receiver).to_string(),
+        parser.l.token_text(&tokens[i].location).to_string(),
+    ))
+}
+
+// Returns true if the tokens are `base.key` without spaces.
+fn is_key(base: &Token, dot: &Token, key: &Token) -> bool {
+    base.token_type == TokenType::Ident
+        && dot.token_type == TokenType::Dot
+        && base.location.range.end == dot.location.range.start
+        && dot.location.range.end == key.location.range.start
+}
+
+// Returns the name of the dictionary variable that the function is defined on, e.g. `s:formatter`
+// for `s:formatter.run`.
+fn dictionary_name(name: &FunctionName) -> String {
+    let scope = match name.scope {
+        Some(scope) => format!("{}:", scope),
+        None if name.sid => "s:".to_string(),
+        None => String::new(),
+    };
+    variable_name(&format!("{}{}", scope, name.dict_path.join(".")))
+}
+
+// Dictionaries without scope are global at the script level, where methods are defined.
+fn variable_name(name: &str) -> String {
+    name.strip_prefix("g:").unwrap_or(name).to_string()
+}
+
+// Collects dictionaries and names of functions stored in them under the method name, e.g.
+// `let s:formatter.run = function('s:Run')` and `let s:formatter = {'run': function('s:Run')}`.
+struct Funcrefs<'a> {
+    method: &'a str,
+    funcrefs: Vec<(String, String)>,
+}
+
+impl<'a> Visitor<'a> for Funcrefs<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::Let(l) = &stmt.kind {
+            match (&l.var.kind, &l.value.kind) {
+                (ExprKind::Member(member), _) if member.name == self.method => {
+                    if let (Some(function), ExprKind::Identifier(base)) =
+                        (funcref(&l.value), &member.base.kind)
+                    {
+                        self.funcrefs.push((variable_name(&base.name), function));
+                    }
+                }
+                (ExprKind::Identifier(var), ExprKind::Dictionary(dictionary)) => {
+                    for entry in &dictionary.entries {
+                        if entry.key == self.method {
+                            if let Some(function) = funcref(&entry.value) {
+                                self.funcrefs.push((variable_name(&var.name), function));
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+// Returns the name of the function referenced by `function('name')` or `funcref('name')`.
+fn funcref(expr: &Expr) -> Option<String> {
+    if let ExprKind::Function(call) = &expr.kind {
+        if let (ExprKind::Identifier(callee), Some(argument)) =
+            (&call.callee.kind, call.arguments.first())
+        {
+            if let ExprKind::StringLiteral(name) = &argument.kind {
+                if callee.name == "function" || callee.name == "funcref" {
+                    return Some(function_name(&name.value));
+                }
+            }
+        }
+    }
+    None
+}
+
+// Finds the dictionary of the dictionary function that contains the offset.
+struct DictFunctions {
+    offset: usize,
+    dictionary: Option<String>,
+}
+
+impl<'a> Visitor<'a> for DictFunctions {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::Function(f) = &stmt.kind {
+            let span = stmt.span.start.0 as usize..stmt.span.end.0 as usize;
+            if f.name.is_dict_function() && span.contains(&self.offset) {
+                self.dictionary = Some(dictionary_name(&f.name));
+            }
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const SOURCE: &str = "let s:formatter = {'run': function('s:Run')}
+let s:parser = {}
+function! s:formatter.format() abort
+  call self.run()
+  return s:parser.run()
+endfunction
+function! s:parser.run() abort
+endfunction
+function! s:Run() abort
+endfunction
+";
+
+    fn lines(locations: Vec<Location>) -> Vec<u32> {
+        locations.into_iter().map(|l| l.range.start.line).collect()
+    }
+
+    fn implementations_at(line: u32, character: u32) -> Vec<u32> {
+        let uri = Url::parse("file:///plugin/format.vim").unwrap();
+        let mut index = Index::new();
+        index.update(&uri, SOURCE);
+        lines(implementations(
+            &index,
+            &uri,
+            SOURCE,
+            Position::new(line, character),
+        ))
+    }
+
+    #[test]
+    fn finds_all_methods_called_on_self() {
+        assert_eq!(implementations_at(3, 13), vec![6, 8]);
+    }
+
+    #[test]
+    fn prefers_methods_of_the_same_dictionary() {
+        assert_eq!(implementations_at(4, 20), vec![6]);
+    }
+
+    #[test]
+    fn returns_nothing_for_function_calls() {
+        assert_eq!(implementations_at(0, 28), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn finds_dictionary_of_self() {
+        let uri = Url::parse("file:///plugin/format.vim").unwrap();
+        let location = type_definition(&uri, SOURCE, Position::new(3, 8)).unwrap();
+        assert_eq!(location.range.start, Position::new(0, 4));
+        let location = type_definition(&uri, SOURCE, Position::new(4, 12)).unwrap();
+        assert_eq!(location.range.start, Position::new(1, 4));
+    }
+}
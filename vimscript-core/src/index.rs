@@ -0,0 +1,505 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::call_hierarchy::function_calls;
+use crate::call_hierarchy::function_name;
+use crate::call_hierarchy::FunctionCall;
+use crate::document_symbol::document_symbols;
+use lsp_types::DocumentSymbol;
+use lsp_types::Location;
+use lsp_types::Position;
+use lsp_types::Range;
+use lsp_types::SymbolInformation;
+use lsp_types::SymbolKind;
+use lsp_types::Url;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Index of symbols defined in the workspace.
+///
+/// Files are parsed when they are added or changed, queries are answered from the index only.
+///
+/// Entries of files are shared between clones, so cloning the index to update a single file is
+/// cheap.
+#[derive(Clone)]
+pub struct Index {
+    files: HashMap<Url, Arc<Vec<IndexedSymbol>>>,
+    calls: HashMap<Url, Arc<Vec<FunctionCall>>>,
+    // Ranges of the whole Vim script files.
+    script_ranges: HashMap<Url, Range>,
+    // Tags defined in help files, e.g. `*plugin-options*`.
+    help_tags: HashMap<Url, Arc<Vec<(String, Location)>>>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct IndexedSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub location: Location,
+    pub container_name: Option<String>,
+}
+
+impl Index {
+    pub fn new() -> Index {
+        Index {
+            files: HashMap::new(),
+            calls: HashMap::new(),
+            script_ranges: HashMap::new(),
+            help_tags: HashMap::new(),
+        }
+    }
+
+    /// Replaces symbols and calls of the file with those in given source.
+    pub fn update(&mut self, uri: &Url, source: &str) {
+        let mut symbols = Vec::new();
+        collect_symbols(uri, document_symbols(source), None, &mut symbols);
+        self.files.insert(uri.clone(), Arc::new(symbols));
+        self.calls
+            .insert(uri.clone(), Arc::new(function_calls(source)));
+        let last_line = source.rsplit('\n').next().unwrap_or("");
+        let end = Position::new(
+            source.matches('\n').count() as u32,
+            last_line.chars().count() as u32,
+        );
+        self.script_ranges
+            .insert(uri.clone(), Range::new(Position::new(0, 0), end));
+    }
+
+    /// Replaces tags of the help file with tags defined in given source.
+    pub fn update_help(&mut self, uri: &Url, source: &str) {
+        let mut tags = Vec::new();
+        for (line_number, line) in source.lines().enumerate() {
+            for (start, tag) in help_tag_definitions(line) {
+                let start = line[..start].chars().count() as u32;
+                let end = start + tag.chars().count() as u32 + 2;
+                tags.push((
+                    tag.to_string(),
+                    Location {
+                        uri: uri.clone(),
+                        range: Range {
+                            start: Position::new(line_number as u32, start),
+                            end: Position::new(line_number as u32, end),
+                        },
+                    },
+                ));
+            }
+        }
+        self.help_tags.insert(uri.clone(), Arc::new(tags));
+    }
+
+    /// Returns the definition of the help tag.
+    pub fn find_help_tag(&self, tag: &str) -> Option<&Location> {
+        self.help_tags
+            .values()
+            .flat_map(|tags| tags.iter())
+            .find(|(name, _)| name == tag)
+            .map(|(_, location)| location)
+    }
+
+    /// Returns the indexed Vim script files.
+    pub fn files(&self) -> impl Iterator<Item = &Url> {
+        self.files.keys()
+    }
+
+    /// Returns symbols defined in the file.
+    pub fn symbols(&self, uri: &Url) -> &[IndexedSymbol] {
+        self.files.get(uri).map_or(&[], |symbols| &symbols[..])
+    }
+
+    /// Returns the range of the whole Vim script file.
+    pub fn script_range(&self, uri: &Url) -> Range {
+        self.script_ranges.get(uri).copied().unwrap_or_default()
+    }
+
+    /// Returns calls of functions in the file.
+    pub fn calls_in(&self, uri: &Url) -> &[FunctionCall] {
+        self.calls.get(uri).map_or(&[], |calls| &calls[..])
+    }
+
+    /// Returns calls of the function defined in the file, in all files of the workspace.
+    ///
+    /// Script-local functions can only be called from the same file.
+    pub fn calls_of<'a>(
+        &'a self,
+        uri: &'a Url,
+        name: &str,
+    ) -> impl Iterator<Item = (&'a Url, &'a FunctionCall)> + 'a {
+        let name = function_name(name);
+        let script_local = name.starts_with("s:");
+        self.calls
+            .iter()
+            .filter(move |(u, _)| !script_local || *u == uri)
+            .flat_map(|(u, calls)| calls.iter().map(move |call| (u, call)))
+            .filter(move |(_, call)| call.callee == name)
+    }
+
+    /// Returns the definition of the function called by the name from the file.
+    ///
+    /// Script-local functions are only looked up in the file itself.
+    pub fn find_function(&self, uri: &Url, name: &str) -> Option<&IndexedSymbol> {
+        let name = function_name(name);
+        let is_function =
+            |s: &&IndexedSymbol| s.kind == SymbolKind::FUNCTION && function_name(&s.name) == name;
+        if name.starts_with("s:") {
+            return self.symbols(uri).iter().find(is_function);
+        }
+        // Prefer the definition in the file, then make the result independent of the hash order.
+        self.symbols(uri).iter().find(is_function).or_else(|| {
+            self.files
+                .values()
+                .flat_map(|symbols| symbols.iter())
+                .filter(is_function)
+                .min_by(|a, b| a.location.uri.as_str().cmp(b.location.uri.as_str()))
+        })
+    }
+
+    /// Adds all Vim script files in the directory and its subdirectories, except hidden ones.
+    ///
+    /// Symbolic links to directories are not followed, as they may form cycles.
+    pub fn add_directory(&mut self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Failed to read directory {}: {}", dir.display(), e);
+                return;
+            }
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => self.add_directory(&path),
+                Ok(_) => self.add_file(&path),
+                Err(e) => eprintln!("Failed to read {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    /// Adds the file if it is a Vim script file or a help file in a `doc` directory.
+    pub fn add_file(&mut self, path: &Path) {
+        let is_help = path.extension().map_or(false, |e| e == "txt")
+            && path
+                .parent()
+                .and_then(|dir| dir.file_name())
+                .map_or(false, |d| d == "doc");
+        if path.extension().map_or(false, |e| e == "vim") {
+            match (fs::read_to_string(path), Url::from_file_path(path)) {
+                (Ok(source), Ok(uri)) => self.update(&uri, &source),
+                _ => eprintln!("Failed to index {}", path.display()),
+            }
+        } else if is_help {
+            match (fs::read_to_string(path), Url::from_file_path(path)) {
+                (Ok(source), Ok(uri)) => self.update_help(&uri, &source),
+                _ => eprintln!("Failed to index {}", path.display()),
+            }
+        }
+    }
+
+    /// Adds the files of the other index, except the ones for which `skip` returns true.
+    pub fn merge(&mut self, other: Index, skip: impl Fn(&Url) -> bool) {
+        for (uri, symbols) in other.files {
+            if !skip(&uri) {
+                self.files.insert(uri, symbols);
+            }
+        }
+        for (uri, calls) in other.calls {
+            if !skip(&uri) {
+                self.calls.insert(uri, calls);
+            }
+        }
+        for (uri, range) in other.script_ranges {
+            if !skip(&uri) {
+                self.script_ranges.insert(uri, range);
+            }
+        }
+        for (uri, tags) in other.help_tags {
+            if !skip(&uri) {
+                self.help_tags.insert(uri, tags);
+            }
+        }
+    }
+
+    /// Removes everything defined in the file.
+    pub fn remove(&mut self, uri: &Url) {
+        self.files.remove(uri);
+        self.calls.remove(uri);
+        self.script_ranges.remove(uri);
+        self.help_tags.remove(uri);
+    }
+
+    /// Returns symbols matching the query, best matches first.
+    ///
+    /// Symbols whose name (with or without the scope) starts with the query are ranked first,
+    /// followed by autoload functions with a path component starting with the query and then
+    /// fuzzy matches, which contain all characters of the query in the same order.
+    // `deprecated` is superseded by `tags`, but it still has to be set.
+    #[allow(deprecated)]
+    pub fn search(&self, query: &str) -> Vec<SymbolInformation> {
+        let query = query.to_lowercase();
+        let mut matches: Vec<((u8, usize), &IndexedSymbol)> = self
+            .files
+            .values()
+            .flat_map(|symbols| symbols.iter())
+            .filter_map(|symbol| Some((rank(&symbol.name, &query)?, symbol)))
+            .collect();
+        matches.sort_by(|(a_rank, a), (b_rank, b)| {
+            a_rank
+                .cmp(b_rank)
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a.location.uri.as_str().cmp(b.location.uri.as_str()))
+        });
+        matches
+            .into_iter()
+            .map(|(_, symbol)| SymbolInformation {
+                name: symbol.name.clone(),
+                kind: symbol.kind,
+                tags: None,
+                deprecated: None,
+                location: symbol.location.clone(),
+                container_name: symbol.container_name.clone(),
+            })
+            .collect()
+    }
+}
+
+// Flattens the outline to the symbols that are visible outside of the file.
+fn collect_symbols(
+    uri: &Url,
+    outline: Vec<DocumentSymbol>,
+    container_name: Option<&str>,
+    symbols: &mut Vec<IndexedSymbol>,
+) {
+    for symbol in outline {
+        let indexed = match symbol.kind {
+            SymbolKind::FUNCTION | SymbolKind::OPERATOR | SymbolKind::KEY => true,
+            SymbolKind::VARIABLE => is_global_variable(&symbol.name),
+            _ => false,
+        };
+        if indexed {
+            symbols.push(IndexedSymbol {
+                name: symbol.name.clone(),
+                kind: symbol.kind,
+                location: Location {
+                    uri: uri.clone(),
+                    range: symbol.selection_range,
+                },
+                container_name: container_name.map(|c| c.to_string()),
+            });
+        }
+        if let Some(children) = symbol.children {
+            collect_symbols(uri, children, Some(&symbol.name), symbols);
+        }
+    }
+}
+
+// Returns byte offsets and names of tags defined in the line of a help file, e.g. `*tag*`.
+fn help_tag_definitions(line: &str) -> Vec<(usize, &str)> {
+    let mut tags = Vec::new();
+    let mut rest_start = 0;
+    while let Some(i) = line[rest_start..].find('*') {
+        let start = rest_start + i;
+        let preceded_by_space = line[..start]
+            .chars()
+            .next_back()
+            .map_or(true, |c| c.is_whitespace());
+        let end = match line[start + 1..].find(|c: char| c == '*' || c.is_whitespace() || c == '|')
+        {
+            Some(end) => start + 1 + end,
+            None => break,
+        };
+        let followed_by_space = line[end + 1..]
+            .chars()
+            .next()
+            .map_or(true, |c| c.is_whitespace());
+        if preceded_by_space && end > start + 1 && line[end..].starts_with('*') && followed_by_space
+        {
+            tags.push((start, &line[start + 1..end]));
+            rest_start = end + 1;
+        } else {
+            rest_start = end;
+        }
+    }
+    tags
+}
+
+// Variables without scope are global at the script level, where the outline variables are
+// defined.
+fn is_global_variable(name: &str) -> bool {
+    name.starts_with("g:") || !name.contains(':')
+}
+
+// Returns the rank of the name for the lowercase query, lower is better, or None if the name does
+// not match.
+fn rank(name: &str, query: &str) -> Option<(u8, usize)> {
+    let name = name.to_lowercase();
+    let unscoped = match name.find(':') {
+        Some(i) if i == 1 => &name[2..],
+        _ => name.strip_prefix("<sid>").unwrap_or(&name),
+    };
+    if name.starts_with(query) || unscoped.starts_with(query) {
+        return Some((0, name.len()));
+    }
+    let autoload_path: Vec<&str> = name.split('#').collect();
+    if autoload_path.len() > 1
+        && autoload_path[..autoload_path.len() - 1]
+            .iter()
+            .any(|component| component.starts_with(query))
+    {
+        return Some((1, name.len()));
+    }
+    Some((2, fuzzy_gaps(&name, query)?))
+}
+
+// Returns the number of characters between the matched characters of the query, or None if the
+// name does not contain all characters of the query in order.
+fn fuzzy_gaps(name: &str, query: &str) -> Option<usize> {
+    let mut gaps = 0;
+    let mut last_match: Option<usize> = None;
+    let mut chars = name.char_indices();
+    for q in query.chars() {
+        let (i, _) = chars.find(|(_, c)| *c == q)?;
+        if let Some(last) = last_match {
+            gaps += i - last - 1;
+        }
+        last_match = Some(i);
+    }
+    Some(gaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn search_names(index: &Index, query: &str) -> Vec<String> {
+        index.search(query).into_iter().map(|s| s.name).collect()
+    }
+
+    fn create_index() -> Index {
+        let mut index = Index::new();
+        index.update(
+            &Url::parse("file:///plugin/format.vim").unwrap(),
+            "let g:format_enabled = 1
+let s:cache = {}
+function! s:FormatBuffer() abort
+endfunction
+function! s:UseTitle() abort
+endfunction
+command! -nargs=0 Format call s:FormatBuffer()
+nnoremap <leader>f :Format<CR>
+",
+        );
+        index.update(
+            &Url::parse("file:///autoload/format/util.vim").unwrap(),
+            "function! format#util#trim(text) abort
+endfunction
+function! ReformatAll() abort
+endfunction
+",
+        );
+        index
+    }
+
+    #[test]
+    fn ranks_prefix_matches_first() {
+        assert_eq!(
+            search_names(&create_index(), "format"),
+            vec![
+                "Format",
+                "s:FormatBuffer",
+                "format#util#trim",
+                "g:format_enabled",
+                "ReformatAll",
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_autoload_path_before_fuzzy_matches() {
+        assert_eq!(
+            search_names(&create_index(), "util"),
+            vec!["format#util#trim", "s:UseTitle"]
+        );
+    }
+
+    #[test]
+    fn matches_fuzzy() {
+        assert_eq!(
+            search_names(&create_index(), "fmtbuf"),
+            vec!["s:FormatBuffer"]
+        );
+    }
+
+    #[test]
+    fn does_not_index_script_variables() {
+        assert_eq!(search_names(&create_index(), "cache"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn finds_help_tags() {
+        let mut index = Index::new();
+        let uri = Url::parse("file:///doc/format.txt").unwrap();
+        index.update_help(
+            &uri,
+            "*format.txt*  Formats code\n\nOPTIONS       *format-options* *g:format_enabled*\n",
+        );
+        assert_eq!(
+            index.find_help_tag("g:format_enabled"),
+            Some(&Location {
+                uri: uri,
+                range: Range {
+                    start: Position::new(2, 31),
+                    end: Position::new(2, 49),
+                },
+            })
+        );
+        assert_eq!(index.find_help_tag("format"), None);
+    }
+
+    #[test]
+    fn replaces_symbols_of_updated_file() {
+        let mut index = create_index();
+        index.update(&Url::parse("file:///plugin/format.vim").unwrap(), "");
+        assert_eq!(search_names(&index, "fmtbuf"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn removes_symbols_of_deleted_file() {
+        let mut index = create_index();
+        index.remove(&Url::parse("file:///plugin/format.vim").unwrap());
+        assert_eq!(search_names(&index, "fmtbuf"), Vec::<String>::new());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn does_not_follow_symlinks_to_directories() {
+        let root = std::env::temp_dir().join(format!("vimscript-index-{}", std::process::id()));
+        fs::create_dir_all(root.join("plugin")).unwrap();
+        fs::write(
+            root.join("plugin/format.vim"),
+            "function! Format()\nendfunction\n",
+        )
+        .unwrap();
+        std::os::unix::fs::symlink("..", root.join("plugin/loop")).unwrap();
+        let mut index = Index::new();
+        index.add_directory(&root);
+        let _ = fs::remove_dir_all(&root);
+        assert_eq!(index.files().count(), 1);
+        assert_eq!(search_names(&index, "Format"), vec!["Format"]);
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::builtins;
+use crate::call_hierarchy::function_name;
+use crate::conversion::token_position_to_range;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::references::find_references;
+use crate::references::Symbol;
+use crate::visitor::walk_expr;
+use crate::visitor::walk_program;
+use crate::visitor::walk_stmt;
+use crate::visitor::Visitor;
+use lsp_types::InlayHint;
+use lsp_types::InlayHintKind;
+use lsp_types::InlayHintLabel;
+use lsp_types::Position;
+use lsp_types::Range;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Categories of inlay hints, configured by `inlayHints` in the configuration.
+#[derive(PartialEq, Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct InlayHintOptions {
+    /// Names of parameters at call sites with several arguments.
+    pub parameter_names: bool,
+    /// Scope of variables without a prefix, e.g. `l:` in functions.
+    pub implicit_scopes: bool,
+}
+
+impl Default for InlayHintOptions {
+    fn default() -> Self {
+        InlayHintOptions {
+            parameter_names: true,
+            implicit_scopes: false,
+        }
+    }
+}
+
+/// Returns hints in given range, ordered by their position.
+pub fn inlay_hints(source: &str, range: Range, options: &InlayHintOptions) -> Vec<InlayHint> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    let mut hints: Vec<(Position, String, InlayHintKind)> = Vec::new();
+
+    if options.parameter_names {
+        let mut calls = Calls {
+            calls: Vec::new(),
+            functions: Vec::new(),
+        };
+        walk_program(&mut calls, &program);
+        for (name, arguments) in calls.calls {
+            if arguments.len() < 2 {
+                continue;
+            }
+            for (argument, parameter) in arguments.iter().zip(parameters(&calls.functions, name)) {
+                if is_named_like(argument, &parameter) {
+                    continue;
+                }
+                let position = token_position_to_range(&parser.resolve_span(&argument.span)).start;
+                hints.push((
+                    position,
+                    format!("{}:", parameter),
+                    InlayHintKind::PARAMETER,
+                ));
+            }
+        }
+    }
+
+    if options.implicit_scopes {
+        for reference in find_references(&program, &parser) {
+            let scope = match reference.symbol {
+                // Arguments are defined without a prefix, but always used with `a:`.
+                Symbol::Variable { scope, .. } if scope != 'a' => scope,
+                _ => continue,
+            };
+            if parser.l.token_text(&reference.location).contains(':') {
+                continue;
+            }
+            let position =
+                token_position_to_range(&parser.resolve_location(reference.location)).start;
+            hints.push((position, format!("{}:", scope), InlayHintKind::TYPE));
+        }
+    }
+
+    hints.retain(|(position, _, _)| range.start <= *position && *position <= range.end);
+    hints.sort_by_key(|(position, _, _)| *position);
+    hints
+        .into_iter()
+        .map(|(position, label, kind)| InlayHint {
+            position: position,
+            padding_right: Some(kind == InlayHintKind::PARAMETER),
+            label: InlayHintLabel::String(label),
+            kind: Some(kind),
+            text_edits: None,
+            tooltip: None,
+            padding_left: None,
+            data: None,
+        })
+        .collect()
+}
+
+// Returns names of the parameters of the user function defined in the script or the builtin.
+fn parameters(functions: &[&FunctionStatement], name: &str) -> Vec<String> {
+    let normalized = function_name(name);
+    if let Some(function) = functions
+        .iter()
+        .find(|f| function_name(&f.name.to_string()) == normalized)
+    {
+        return function.arguments.iter().map(|a| a.name.clone()).collect();
+    }
+    match builtins::find_function(name) {
+        Some(function) => function
+            .parameters()
+            .iter()
+            .map(|p| p.trim_matches(|c| c == '{' || c == '}').to_string())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+// Returns true if the argument is a variable with the same name as the parameter, in which case
+// the hint would only repeat it.
+fn is_named_like(argument: &Expr, parameter: &str) -> bool {
+    match &argument.kind {
+        ExprKind::Identifier(identifier) => {
+            let name = identifier.name.as_str();
+            let base = match name.find(':') {
+                Some(1) => &name[2..],
+                _ => name,
+            };
+            base == parameter
+        }
+        _ => false,
+    }
+}
+
+// Collects calls of named functions with their arguments and function definitions.
+struct Calls<'a> {
+    calls: Vec<(&'a str, &'a [Expr])>,
+    functions: Vec<&'a FunctionStatement>,
+}
+
+impl<'a> Visitor<'a> for Calls<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Call(s) => self.calls.push((&s.name, &s.arguments)),
+            StmtKind::Function(f) => self.functions.push(f),
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Function(e) = &expr.kind {
+            if let ExprKind::Identifier(callee) = &e.callee.kind {
+                self.calls.push((&callee.name, &e.arguments));
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn hints(source: &str, options: &InlayHintOptions) -> Vec<String> {
+        let range = Range {
+            start: Position {
+                line: 0,
+                character: 0,
+            },
+            end: Position {
+                line: u32::MAX,
+                character: 0,
+            },
+        };
+        inlay_hints(source, range, options)
+            .into_iter()
+            .map(|hint| match hint.label {
+                InlayHintLabel::String(label) => format!(
+                    "{}:{} {}",
+                    hint.position.line, hint.position.character, label
+                ),
+                _ => panic!("unexpected label"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shows_parameter_names_of_user_functions_and_builtins() {
+        let source = "function! s:Join(items, separator) abort
+endfunction
+call s:Join(g:list, ',')
+let x = get(g:dict, 'key', 0)
+let y = len(g:list)";
+        assert_eq!(
+            hints(source, &InlayHintOptions::default()),
+            vec![
+                "2:12 items:",
+                "2:20 separator:",
+                "3:12 list:",
+                "3:20 idx:",
+                "3:27 default:",
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_arguments_named_like_parameters() {
+        let source = "function! s:Join(items, separator) abort
+endfunction
+call s:Join(l:items, ',')";
+        assert_eq!(
+            hints(source, &InlayHintOptions::default()),
+            vec!["2:21 separator:"]
+        );
+    }
+
+    #[test]
+    fn shows_implicit_scopes() {
+        let source = "let count = 1
+function! s:Run(arg) abort
+  let total = a:arg + g:count
+  return total
+endfunction";
+        let options = InlayHintOptions {
+            parameter_names: false,
+            implicit_scopes: true,
+        };
+        assert_eq!(hints(source, &options), vec!["0:4 g:", "2:6 l:", "3:9 l:"]);
+    }
+
+    #[test]
+    fn reads_options_with_defaults() {
+        let options: InlayHintOptions =
+            serde_json::from_value(serde_json::json!({"implicitScopes": true})).unwrap();
+        assert_eq!(
+            options,
+            InlayHintOptions {
+                parameter_names: true,
+                implicit_scopes: true,
+            }
+        );
+    }
+}
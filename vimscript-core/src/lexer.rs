@@ -19,6 +19,7 @@ use std::fmt;
 #[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum TokenType {
     Let,
+    Const,
     Assign,
     Ident,
     Number,
@@ -103,6 +104,7 @@ impl TokenType {
     pub fn as_str(&self) -> &str {
         match self {
             TokenType::Let => "`let`",
+            TokenType::Const => "`const`",
             TokenType::Assign => "`=`",
             TokenType::Ident => "identifier",
             TokenType::Number => "number",
@@ -271,6 +273,28 @@ impl<'a> Lexer<'a> {
         };
     }
 
+    // Returns the byte offset of given position, which is the inverse of `source_position`.
+    //
+    // Positions past the end of a line are clamped to the end of that line.
+    pub fn source_offset(&self, position: &SourcePosition) -> usize {
+        let mut line = 0;
+        let mut character = 0;
+        for (pos, c) in self.source.char_indices() {
+            if line == position.line && (character == position.character || c == '\n') {
+                return pos;
+            }
+            if line > position.line {
+                return pos;
+            }
+            character += 1;
+            if c == '\n' {
+                line += 1;
+                character = 0;
+            }
+        }
+        return self.source.len();
+    }
+
     // This is expensive, expected to be called only for errors.
     pub fn token_position(&self, location: &SourceLocation) -> TokenPosition {
         return TokenPosition {
@@ -373,6 +397,18 @@ impl<'a> Lexer<'a> {
     }
 
     fn read_less(&mut self) {
+        // `<SID>Name` is a script-local function name.
+        let rest = &self.source[self.start..];
+        if rest.len() > 5
+            && rest.as_bytes()[..5].eq_ignore_ascii_case(b"<SID>")
+            && rest[5..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        {
+            for _ in 0..4 {
+                self.chars.next();
+            }
+            self.read_identifier();
+            return;
+        }
         match self.chars.peek() {
             Some('=') => {
                 self.chars.next();
@@ -599,6 +635,7 @@ impl<'a> Lexer<'a> {
         let s = &self.source[self.start..self.chars.pos()];
         self.add_token(match s {
             "let" => TokenType::Let,
+            "const" => TokenType::Const,
             "function" => TokenType::Function,
             "endfunction" => TokenType::EndFunction,
             "if" => TokenType::If,
@@ -710,9 +747,10 @@ mod tests {
     #[test]
     fn parses_reserved_words() {
         assert_eq!(
-            parse_source("let return execute call break set for endfor while endwhile if endif function endfunction abort in finish"),
+            parse_source("let const return execute call break set for endfor while endwhile if endif function endfunction abort in finish"),
             &[
                 (TokenType::Let, "let"),
+                (TokenType::Const, "const"),
                 (TokenType::Return, "return"),
                 (TokenType::Execute, "execute"),
                 (TokenType::Call, "call"),
@@ -926,6 +964,29 @@ mod tests {
         )
     }
 
+    #[test]
+    fn parses_script_local_names_as_identifiers() {
+        assert_eq!(
+            parse_source("<SID>Format() a<b"),
+            &[
+                (TokenType::Ident, "<SID>Format"),
+                (TokenType::LeftParenthesis, "("),
+                (TokenType::RightParenthesis, ")"),
+                (TokenType::Ident, "a"),
+                (TokenType::Less, "<"),
+                (TokenType::Ident, "b"),
+            ]
+        )
+    }
+
+    #[test]
+    fn parses_less_followed_by_multibyte_characters() {
+        assert_eq!(
+            &parse_source("a <Sĳĳ")[..2],
+            &[(TokenType::Ident, "a"), (TokenType::Less, "<")]
+        )
+    }
+
     #[test]
     fn returns_correct_token_position() {
         let mut lexer = Lexer::new("unknown");
@@ -945,4 +1006,20 @@ mod tests {
             }
         )
     }
+
+    #[test]
+    fn returns_source_offset_of_position() {
+        let lexer = Lexer::new("let a = 1\nlet b = 2");
+        let offset = |line, character| {
+            lexer.source_offset(&SourcePosition {
+                line: line,
+                character: character,
+            })
+        };
+        assert_eq!(offset(0, 4), 4);
+        assert_eq!(offset(1, 4), 14);
+        // Position past the end of the line is clamped.
+        assert_eq!(offset(0, 20), 9);
+        assert_eq!(offset(5, 0), 19);
+    }
 }
@@ -13,13 +13,39 @@
 // limitations under the License.
 
 pub mod ast;
+pub mod builtins;
+pub mod call_hierarchy;
+pub mod capabilities;
+pub mod code_action;
+pub mod code_lens;
+pub mod config;
+pub mod conversion;
+pub mod diagnostics;
+pub mod document_highlight;
+pub mod document_link;
+pub mod document_symbol;
+pub mod folding_range;
 pub mod format;
+pub mod glob;
+pub mod hover;
+pub mod implementation;
+pub mod index;
+pub mod inlay_hint;
 pub mod lexer;
+pub mod lint;
 pub mod lsp;
 pub mod parser;
 pub mod peekable_chars_with_position;
 pub mod protocol;
+pub mod refactor;
+pub mod references;
 pub mod rename;
+pub mod selection_range;
+pub mod semantic_tokens;
 pub mod server;
+pub mod signature_help;
 pub mod source_map;
 pub mod span;
+pub mod transport;
+pub mod visitor;
+pub mod worker_pool;
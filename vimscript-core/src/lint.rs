@@ -0,0 +1,364 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::builtins::find_availability;
+use crate::builtins::find_option;
+use crate::config::Dialect;
+use crate::diagnostics::DiagnosticCode;
+use crate::lexer::SourceLocation;
+use crate::lexer::Token;
+use crate::lexer::TokenType;
+use crate::parser::Parser;
+use crate::parser::Program;
+use crate::references::find_references;
+use crate::references::Symbol;
+use crate::visitor::walk_expr;
+use crate::visitor::walk_program;
+use crate::visitor::walk_stmt;
+use crate::visitor::Visitor;
+use std::collections::HashSet;
+
+#[derive(PartialEq, Debug)]
+pub struct LintWarning {
+    pub code: DiagnosticCode,
+    pub message: String,
+    pub location: SourceLocation,
+}
+
+/// Returns style warnings for the parsed program, ordered by their location.
+pub fn lint(program: &Program, parser: &Parser) -> Vec<LintWarning> {
+    let mut linter = Linter {
+        parser: parser,
+        tokens: parser.tokens(),
+        warnings: Vec::new(),
+    };
+    walk_program(&mut linter, program);
+    let mut warnings = linter.warnings;
+
+    // Variables assigned at the script level without a scope are global, which is rarely
+    // intended. Only the first assignment is reported.
+    let mut reported = HashSet::new();
+    for reference in find_references(program, parser) {
+        if let Symbol::Variable {
+            scope: 'g', name, ..
+        } = &reference.symbol
+        {
+            let text = parser.l.token_text(&reference.location);
+            if reference.write && !text.contains(':') && reported.insert(name.clone()) {
+                warnings.push(LintWarning {
+                    code: DiagnosticCode::ImplicitScope,
+                    message: format!(
+                        "`{}` is a global variable, use `s:{}` for a script-local one",
+                        text, name
+                    ),
+                    location: reference.location,
+                });
+            }
+        }
+    }
+
+    warnings.sort_by_key(|w| w.location.range.start);
+    warnings
+}
+
+/// Returns warnings for uses of builtin functions and options that are missing in the dialect, or
+/// that were added to Vim after `min_version`, ordered by their location.
+pub fn unavailable_features(
+    program: &Program,
+    parser: &Parser,
+    dialect: Dialect,
+    min_version: Option<&str>,
+) -> Vec<LintWarning> {
+    let mut uses = Vec::new();
+    for reference in find_references(program, parser) {
+        match reference.symbol {
+            Symbol::Function(name) if !reference.write => uses.push((name, reference.location)),
+            Symbol::Option(name) => uses.push((format!("&{}", name), reference.location)),
+            _ => {}
+        }
+    }
+    let mut options = SetOptions {
+        tokens: parser.tokens(),
+        options: Vec::new(),
+    };
+    walk_program(&mut options, program);
+    uses.extend(options.options);
+
+    let mut warnings = Vec::new();
+    for (name, location) in uses {
+        let availability = match find_availability(&name) {
+            Some(availability) => availability,
+            None => continue,
+        };
+        let problem = match (dialect, availability.vim, min_version) {
+            (Dialect::Neovim, _, _) if !availability.neovim => {
+                "is not available in Neovim".to_string()
+            }
+            (Dialect::Vim, None, _) => "is only available in Neovim".to_string(),
+            (Dialect::Vim, Some(added), Some(min_version)) if version_less(min_version, added) => {
+                format!("requires Vim {}, but minVersion is {}", added, min_version)
+            }
+            _ => continue,
+        };
+        warnings.push(LintWarning {
+            code: DiagnosticCode::UnavailableFeature,
+            message: format!("`{}` {}", name, problem),
+            location: location,
+        });
+    }
+    warnings.sort_by_key(|w| w.location.range.start);
+    warnings
+}
+
+// Compares versions like `8.1.1305` by their numeric components, missing ones are zero.
+fn version_less(a: &str, b: &str) -> bool {
+    let components = |version: &str| -> Vec<u32> {
+        let mut components: Vec<u32> = version
+            .split('.')
+            .map(|c| c.trim().parse().unwrap_or(0))
+            .collect();
+        components.resize(3, 0);
+        components
+    };
+    components(a) < components(b)
+}
+
+// Collects options set with `set`, including `no` and `inv` forms of boolean options.
+struct SetOptions<'a> {
+    tokens: &'a [Token],
+    options: Vec<(String, SourceLocation)>,
+}
+
+impl<'a> Visitor<'a> for SetOptions<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::Set(s) = &stmt.kind {
+            let tokens = self.tokens;
+            let option = tokens
+                .iter()
+                .find(|t| t.location.range.start > stmt.span.start.0 as usize);
+            if let Some(option) = option {
+                let is_known = |name: &&str| {
+                    find_option(name).is_some()
+                        || find_availability(&format!("&{}", name)).is_some()
+                };
+                let name = [
+                    Some(s.option.as_str()),
+                    s.option.strip_prefix("no"),
+                    s.option.strip_prefix("inv"),
+                ]
+                .iter()
+                .flatten()
+                .copied()
+                .find(is_known)
+                .unwrap_or(&s.option);
+                let name = find_option(name).map_or(name, |o| o.name);
+                self.options
+                    .push((format!("&{}", name), option.location.clone()));
+            }
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+struct Linter<'a> {
+    parser: &'a Parser<'a>,
+    tokens: &'a [Token],
+    warnings: Vec<LintWarning>,
+}
+
+impl<'a> Linter<'a> {
+    fn warn(&mut self, code: DiagnosticCode, message: String, location: SourceLocation) {
+        self.warnings.push(LintWarning {
+            code: code,
+            message: message,
+            location: location,
+        });
+    }
+
+    fn token_at(&self, offset: usize) -> Option<&'a Token> {
+        let tokens = self.tokens;
+        tokens.iter().find(|t| t.location.range.start == offset)
+    }
+}
+
+impl<'a> Visitor<'a> for Linter<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::Function(f) = &stmt.kind {
+            if !f.overwrite {
+                if let Some(keyword) = self.token_at(stmt.span.start.0 as usize) {
+                    self.warn(
+                        DiagnosticCode::FunctionWithoutBang,
+                        format!(
+                            "`{}` fails when the script is sourced again, use `function!`",
+                            f.name
+                        ),
+                        keyword.location.clone(),
+                    );
+                }
+            }
+            if !f.abort {
+                self.warn(
+                    DiagnosticCode::MissingAbort,
+                    format!("function `{}` does not abort on errors", f.name),
+                    f.name_location.clone(),
+                );
+            }
+            let name = &f.name;
+            if name.scope.is_none()
+                && !name.sid
+                && name.autoload_path.is_empty()
+                && !name.is_dict_function()
+            {
+                self.warn(
+                    DiagnosticCode::ImplicitScope,
+                    format!(
+                        "`{}` is a global function, use `s:{}` for a script-local one",
+                        f.name, f.name
+                    ),
+                    f.name_location.clone(),
+                );
+            }
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Infix(e) = &expr.kind {
+            let is_number = |e: &Expr| matches!(e.kind, ExprKind::Number(_));
+            if (e.operator == TokenType::Equal || e.operator == TokenType::InEqual)
+                && !is_number(&e.left)
+                && !is_number(&e.right)
+            {
+                let start = e.left.span.end.0 as usize;
+                let end = e.right.span.start.0 as usize;
+                let tokens = self.tokens;
+                if let Some(operator) = tokens.iter().find(|t| {
+                    t.token_type == e.operator
+                        && t.location.range.start >= start
+                        && t.location.range.end <= end
+                }) {
+                    let text = self.parser.l.token_text(&operator.location);
+                    self.warn(
+                        DiagnosticCode::CaseInsensitiveComparison,
+                        format!(
+                            "`{}` depends on 'ignorecase', use `{}#` or `{}?`",
+                            text, text, text
+                        ),
+                        operator.location.clone(),
+                    );
+                }
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use pretty_assertions::assert_eq;
+
+    fn warnings(source: &str) -> Vec<(DiagnosticCode, String)> {
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse();
+        assert_eq!(parser.errors, &[]);
+        lint(&program, &parser)
+            .into_iter()
+            .map(|w| (w.code, source[w.location.range].to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn warns_about_function_definitions() {
+        assert_eq!(
+            warnings("function Format()\nendfunction\nfunction! s:Run() abort\nendfunction"),
+            vec![
+                (DiagnosticCode::FunctionWithoutBang, "function".to_string()),
+                (DiagnosticCode::MissingAbort, "Format".to_string()),
+                (DiagnosticCode::ImplicitScope, "Format".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_implicit_global_variables() {
+        assert_eq!(
+            warnings("let count = 1\nlet count = 2\nlet g:enabled = 1\nlet s:x = count"),
+            vec![(DiagnosticCode::ImplicitScope, "count".to_string())]
+        );
+    }
+
+    #[test]
+    fn warns_about_case_insensitive_comparisons() {
+        assert_eq!(
+            warnings("let s:a = s:x == 'a'\nlet s:b = s:x ==# 'b'\nlet s:c = s:n != 1"),
+            vec![(DiagnosticCode::CaseInsensitiveComparison, "==".to_string())]
+        );
+    }
+
+    fn unavailable(source: &str, dialect: Dialect, min_version: Option<&str>) -> Vec<String> {
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse();
+        assert_eq!(parser.errors, &[]);
+        unavailable_features(&program, &parser, dialect, min_version)
+            .into_iter()
+            .map(|w| format!("{}: {}", &source[w.location.range], w.message))
+            .collect()
+    }
+
+    #[test]
+    fn warns_about_features_missing_in_dialect() {
+        let source = "call jobstart(['ls'])\ncall nvim_buf_get_lines(0, 0, -1, v:true)\n\
+                      call popup_create('a', {})\nlet x = &shada\nset termguicolors\n";
+        assert_eq!(
+            unavailable(source, Dialect::Vim, None),
+            vec![
+                "jobstart: `jobstart` is only available in Neovim",
+                "nvim_buf_get_lines: `nvim_buf_get_lines` is only available in Neovim",
+                "&shada: `&shada` is only available in Neovim",
+            ]
+        );
+        assert_eq!(
+            unavailable(source, Dialect::Neovim, None),
+            vec!["popup_create: `popup_create` is not available in Neovim"]
+        );
+    }
+
+    #[test]
+    fn warns_about_features_newer_than_min_version() {
+        let source = "let x = trim(getenv('A'))\nlet y = json_decode(x)\nset notermguicolors\n";
+        assert_eq!(
+            unavailable(source, Dialect::Vim, Some("8.0")),
+            vec![
+                "trim: `trim` requires Vim 8.0.1630, but minVersion is 8.0",
+                "getenv: `getenv` requires Vim 8.1.1305, but minVersion is 8.0",
+            ]
+        );
+        assert_eq!(
+            unavailable(source, Dialect::Vim, Some("7.4")),
+            vec![
+                "trim: `trim` requires Vim 8.0.1630, but minVersion is 7.4",
+                "getenv: `getenv` requires Vim 8.1.1305, but minVersion is 7.4",
+                "json_decode: `json_decode` requires Vim 8.0, but minVersion is 7.4",
+                "notermguicolors: `&termguicolors` requires Vim 8.0, but minVersion is 7.4",
+            ]
+        );
+        assert_eq!(
+            unavailable(source, Dialect::Vim, Some("8.1.1305")),
+            Vec::<String>::new()
+        );
+    }
+}
@@ -12,206 +12,1399 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::lexer::Lexer;
-use crate::lexer::SourcePosition;
-use crate::lexer::TokenPosition;
-use crate::parser::Parser;
+use crate::call_hierarchy::incoming_calls;
+use crate::call_hierarchy::outgoing_calls;
+use crate::call_hierarchy::prepare_call_hierarchy;
+use crate::capabilities::hover_format;
+use crate::capabilities::server_capabilities;
+use crate::capabilities::supports_configuration_requests;
+use crate::capabilities::supports_diagnostic_refresh;
+use crate::capabilities::supports_pull_diagnostics;
+use crate::capabilities::supports_watched_files_registration;
+use crate::capabilities::supports_work_done_progress;
+use crate::code_action::code_actions;
+use crate::code_lens::code_lenses;
+use crate::code_lens::RUN_TEST_COMMAND;
+use crate::config::config_for_file;
+use crate::config::Config;
+use crate::diagnostics::diagnostics;
+use crate::diagnostics::result_id;
+use crate::diagnostics::workspace_diagnostics;
+use crate::document_highlight::document_highlights;
+use crate::document_link::document_links;
+use crate::document_symbol::document_symbols;
+use crate::folding_range::folding_ranges;
+use crate::hover::hover;
+use crate::implementation::implementations;
+use crate::implementation::type_definition;
+use crate::index::Index;
+use crate::inlay_hint::inlay_hints;
+use crate::refactor::refactorings;
 use crate::rename::rename;
+use crate::selection_range::selection_ranges;
+use crate::semantic_tokens;
+use crate::server::ErrorCode;
 use crate::server::LspSender;
 use crate::server::Message;
+use crate::server::Notification;
 use crate::server::Read;
 use crate::server::Request;
+use crate::server::ResponseError;
+use crate::server::ResponseHandle;
 use crate::server::Server;
 use crate::server::Write;
+use crate::signature_help::signature_help;
 use crate::source_map::SourceMap;
+use crate::worker_pool::WorkerPool;
+use lsp_types::CallHierarchyIncomingCall;
+use lsp_types::CallHierarchyIncomingCallsParams;
+use lsp_types::CallHierarchyItem;
+use lsp_types::CallHierarchyOutgoingCall;
+use lsp_types::CallHierarchyOutgoingCallsParams;
+use lsp_types::CallHierarchyPrepareParams;
+use lsp_types::CodeAction;
+use lsp_types::CodeActionParams;
+use lsp_types::CodeLens;
+use lsp_types::CodeLensParams;
+use lsp_types::ConfigurationItem;
+use lsp_types::ConfigurationParams;
 use lsp_types::Diagnostic;
-use lsp_types::DiagnosticSeverity;
+use lsp_types::DidChangeConfigurationParams;
 use lsp_types::DidChangeTextDocumentParams;
+use lsp_types::DidChangeWatchedFilesParams;
+use lsp_types::DidChangeWatchedFilesRegistrationOptions;
+use lsp_types::DidCloseTextDocumentParams;
 use lsp_types::DidOpenTextDocumentParams;
+use lsp_types::DidSaveTextDocumentParams;
+use lsp_types::DocumentDiagnosticParams;
+use lsp_types::DocumentDiagnosticReport;
+use lsp_types::DocumentDiagnosticReportResult;
 use lsp_types::DocumentHighlight;
 use lsp_types::DocumentHighlightParams;
-use lsp_types::Position;
+use lsp_types::DocumentLink;
+use lsp_types::DocumentLinkParams;
+use lsp_types::DocumentSymbolParams;
+use lsp_types::DocumentSymbolResponse;
+use lsp_types::ExecuteCommandParams;
+use lsp_types::FileChangeType;
+use lsp_types::FileSystemWatcher;
+use lsp_types::FoldingRange;
+use lsp_types::FoldingRangeParams;
+use lsp_types::FullDocumentDiagnosticReport;
+use lsp_types::GlobPattern;
+use lsp_types::GotoDefinitionParams;
+use lsp_types::Hover;
+use lsp_types::HoverParams;
+use lsp_types::InitializeParams;
+use lsp_types::InitializeResult;
+use lsp_types::InlayHint;
+use lsp_types::InlayHintParams;
+use lsp_types::Location;
+use lsp_types::MarkupKind;
+use lsp_types::MessageType;
+use lsp_types::ProgressParams;
+use lsp_types::ProgressParamsValue;
+use lsp_types::ProgressToken;
 use lsp_types::PublishDiagnosticsParams;
-use lsp_types::Range;
+use lsp_types::Registration;
+use lsp_types::RegistrationParams;
+use lsp_types::RelatedFullDocumentDiagnosticReport;
+use lsp_types::RelatedUnchangedDocumentDiagnosticReport;
 use lsp_types::RenameParams;
+use lsp_types::SelectionRange;
+use lsp_types::SelectionRangeParams;
+use lsp_types::SemanticTokens;
+use lsp_types::SemanticTokensDelta;
+use lsp_types::SemanticTokensDeltaParams;
+use lsp_types::SemanticTokensFullDeltaResult;
+use lsp_types::SemanticTokensParams;
+use lsp_types::SemanticTokensRangeParams;
+use lsp_types::SemanticTokensRangeResult;
+use lsp_types::ServerInfo;
+use lsp_types::SignatureHelp;
+use lsp_types::SignatureHelpParams;
+use lsp_types::SymbolInformation;
+use lsp_types::UnchangedDocumentDiagnosticReport;
 use lsp_types::Url;
+use lsp_types::WorkDoneProgress;
+use lsp_types::WorkDoneProgressBegin;
+use lsp_types::WorkDoneProgressEnd;
+use lsp_types::WorkspaceDiagnosticParams;
+use lsp_types::WorkspaceDiagnosticReport;
+use lsp_types::WorkspaceDiagnosticReportResult;
+use lsp_types::WorkspaceDocumentDiagnosticReport;
 use lsp_types::WorkspaceEdit;
+use lsp_types::WorkspaceFullDocumentDiagnosticReport;
+use lsp_types::WorkspaceSymbolParams;
+use lsp_types::WorkspaceUnchangedDocumentDiagnosticReport;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json::json;
 use std::collections::HashMap;
-use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
 
 /// Runs the main loop of the LSP server.
 ///
-/// This method finishes when `exit` notification is received.
-pub fn run<R: Read, W: Write + Send + 'static>(server: Server<R, W>) {
+/// Notifications that change documents are handled on the calling thread, read-only requests run
+/// on a worker pool with a snapshot of the state at the time they were received.
+///
+/// This method finishes when `exit` notification is received and all started requests are
+/// answered. It returns the exit code of the process, which is 0 only if the client asked the
+/// server to shut down before.
+pub fn run<R: Read, W: Write + Send + 'static>(server: Server<R, W>) -> i32 {
+    let (indexed_sender, indexed_receiver) = channel();
+    let (semantic_tokens_sender, semantic_tokens_receiver) = channel();
     let mut state = State {
-        source_map: SourceMap::new(),
+        snapshot: Snapshot {
+            source_map: Arc::new(SourceMap::new()),
+            index: Arc::new(Index::new()),
+            config: Arc::new(Config::default()),
+            hover_format: MarkupKind::PlainText,
+            revision: 0,
+        },
+        lifecycle: Lifecycle::Uninitialized,
+        watch_files: false,
+        pull_diagnostics: false,
+        pull_configuration: false,
+        refresh_diagnostics: false,
+        document_revisions: Arc::new(Mutex::new(HashMap::new())),
+        workspace_reports: Arc::new(Mutex::new(WorkspaceReports::default())),
+        root: None,
+        report_progress: false,
+        workers: WorkerPool::with_available_parallelism(),
+        indexed_sender: indexed_sender,
+        indexed_receiver: indexed_receiver,
+        semantic_tokens: HashMap::new(),
+        semantic_tokens_sender: semantic_tokens_sender,
+        semantic_tokens_receiver: semantic_tokens_receiver,
+        next_result_id: 0,
         sender: server.sender(),
     };
+    let sender = server.sender();
     for msg in server {
-        state.handle_message(msg);
+        // Requests are guarded separately, so that they can still be answered after a panic.
+        catch_panic(&sender, "message", || state.handle_message(msg));
+    }
+    if state.lifecycle == Lifecycle::ShutDown {
+        0
+    } else {
+        1
     }
 }
 
-struct State {
-    source_map: SourceMap,
-    sender: LspSender,
+#[derive(PartialEq, Clone, Copy)]
+enum Lifecycle {
+    // Only `initialize` is accepted.
+    Uninitialized,
+    Initialized,
+    // After `shutdown`, only `exit` is accepted.
+    ShutDown,
 }
 
-fn token_position_to_range(position: &TokenPosition) -> Range {
-    Range {
-        start: Position {
-            line: position.start.line as u64,
-            character: position.start.character as u64,
-        },
-        end: Position {
-            line: position.end.line as u64,
-            character: position.end.character as u64,
-        },
-    }
+/// Immutable view of the documents and the index, which workers use to answer requests.
+///
+/// Cloning is cheap, as the contents are shared with the state they were taken from.
+#[derive(Clone)]
+struct Snapshot {
+    source_map: Arc<SourceMap>,
+    index: Arc<Index>,
+    config: Arc<Config>,
+    hover_format: MarkupKind,
+    // Incremented on every change of a document, the index or the settings.
+    revision: u64,
+}
+
+// Diagnostics of a file reported by the last workspace pull.
+struct WorkspaceReport {
+    version: Option<i64>,
+    result_id: String,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// Reports of the last workspace pull, which are reused until the revision of the snapshot
+// changes, so that files are not read and checked again on each pull.
+#[derive(Default)]
+struct WorkspaceReports {
+    revision: u64,
+    reports: HashMap<Url, Arc<WorkspaceReport>>,
+}
+
+struct State {
+    snapshot: Snapshot,
+    lifecycle: Lifecycle,
+    // Whether the client can be asked to watch files with dynamic registration.
+    watch_files: bool,
+    // Whether the client requests diagnostics, in which case they are not published.
+    pull_diagnostics: bool,
+    // Whether settings can be requested with `workspace/configuration`.
+    pull_configuration: bool,
+    // Whether the client can be asked to pull diagnostics again after the settings changed.
+    refresh_diagnostics: bool,
+    // Revision of the last change of each document, used to drop outdated diagnostics.
+    document_revisions: Arc<Mutex<HashMap<Url, u64>>>,
+    // Workspace diagnostics of the last pull, shared with the workers that compute them.
+    workspace_reports: Arc<Mutex<WorkspaceReports>>,
+    // Workspace folder, which is indexed once the client is initialized.
+    root: Option<PathBuf>,
+    // Whether the client shows progress of work started by the server.
+    report_progress: bool,
+    workers: WorkerPool,
+    // Indexes of directories built by workers, which are added to the snapshot before handling the
+    // next message.
+    indexed_sender: Sender<Index>,
+    indexed_receiver: Receiver<Index>,
+    // Last semantic tokens sent for each document, used to compute deltas. Workers send the tokens
+    // they computed, which are stored before handling the next message.
+    semantic_tokens: HashMap<Url, Arc<SemanticTokens>>,
+    semantic_tokens_sender: Sender<(Url, Arc<SemanticTokens>)>,
+    semantic_tokens_receiver: Receiver<(Url, Arc<SemanticTokens>)>,
+    next_result_id: u64,
+    sender: LspSender,
 }
 
 impl State {
     fn handle_message(&mut self, msg: Message) {
+        self.merge_indexed_directories();
+        self.merge_semantic_tokens();
         match msg {
-            Message::Request(req) => match req.method.as_ref() {
-                "initialize" => {
-                    req.response_handle.respond(Ok(json!({"capabilities": {
-                        "renameProvider": true,
-                        "documentHighlightProvider": true,
-                    }})));
-                }
-                "textDocument/rename" => {
-                    self.handle_rename(req);
-                }
-                "textDocument/documentHighlight" => {
-                    self.handle_document_highlight(req);
-                }
-                method => {
-                    eprintln!("Unrecognized request: {}", method);
-                }
-            },
-            Message::Notification(notification) => match notification.method.as_ref() {
-                "initialized" => {}
-                "textDocument/didOpen" => {
-                    let params: DidOpenTextDocumentParams =
-                        serde_json::from_value(notification.params.clone()).unwrap();
-                    self.handle_did_open(params);
+            Message::Request(req) => self.handle_request(req),
+            Message::Notification(notification) => self.handle_notification(notification),
+        }
+    }
+
+    fn handle_request(&mut self, req: Request) {
+        let error = match (self.lifecycle, req.method.as_ref()) {
+            (Lifecycle::Uninitialized, "initialize") => None,
+            (Lifecycle::Uninitialized, _) => Some(ResponseError::new(
+                ErrorCode::ServerNotInitialized,
+                "server is not initialized",
+            )),
+            (Lifecycle::Initialized, "initialize") => Some(ResponseError::new(
+                ErrorCode::InvalidRequest,
+                "server is already initialized",
+            )),
+            (Lifecycle::Initialized, _) => None,
+            (Lifecycle::ShutDown, _) => Some(ResponseError::new(
+                ErrorCode::InvalidRequest,
+                "server is shutting down",
+            )),
+        };
+        if let Some(error) = error {
+            req.response_handle.respond(Err(error));
+            return;
+        }
+        match req.method.as_ref() {
+            "initialize" => {
+                self.handle(req, State::handle_initialize);
+            }
+            "shutdown" => {
+                self.lifecycle = Lifecycle::ShutDown;
+                req.response_handle.respond(Ok(serde_json::Value::Null));
+            }
+            "textDocument/rename" => {
+                self.spawn(req, Snapshot::handle_rename);
+            }
+            "textDocument/documentHighlight" => {
+                self.spawn(req, Snapshot::handle_document_highlight);
+            }
+            "textDocument/hover" => {
+                self.spawn(req, Snapshot::handle_hover);
+            }
+            "textDocument/signatureHelp" => {
+                self.spawn(req, Snapshot::handle_signature_help);
+            }
+            "textDocument/documentSymbol" => {
+                self.spawn(req, Snapshot::handle_document_symbol);
+            }
+            "workspace/symbol" => {
+                self.spawn(req, Snapshot::handle_workspace_symbol);
+            }
+            "textDocument/foldingRange" => {
+                self.spawn(req, Snapshot::handle_folding_range);
+            }
+            "textDocument/selectionRange" => {
+                self.spawn(req, Snapshot::handle_selection_range);
+            }
+            "textDocument/codeAction" => {
+                self.spawn(req, Snapshot::handle_code_action);
+            }
+            "textDocument/prepareCallHierarchy" => {
+                self.spawn(req, Snapshot::handle_prepare_call_hierarchy);
+            }
+            "callHierarchy/incomingCalls" => {
+                self.spawn(req, Snapshot::handle_incoming_calls);
+            }
+            "callHierarchy/outgoingCalls" => {
+                self.spawn(req, Snapshot::handle_outgoing_calls);
+            }
+            "textDocument/inlayHint" => {
+                self.spawn(req, Snapshot::handle_inlay_hint);
+            }
+            "textDocument/codeLens" => {
+                self.spawn(req, Snapshot::handle_code_lens);
+            }
+            "textDocument/implementation" => {
+                self.spawn(req, Snapshot::handle_implementation);
+            }
+            "textDocument/typeDefinition" => {
+                self.spawn(req, Snapshot::handle_type_definition);
+            }
+            "textDocument/documentLink" => {
+                self.spawn(req, Snapshot::handle_document_link);
+            }
+            "textDocument/diagnostic" => {
+                self.spawn(req, Snapshot::handle_document_diagnostic);
+            }
+            "workspace/diagnostic" => {
+                self.spawn_workspace_diagnostic(req);
+            }
+            "workspace/executeCommand" => {
+                self.handle(req, State::handle_execute_command);
+            }
+            "textDocument/semanticTokens/full" => {
+                self.spawn_semantic_tokens(req, |params: SemanticTokensParams| {
+                    (params.text_document.uri, None)
+                });
+            }
+            "textDocument/semanticTokens/full/delta" => {
+                self.spawn_semantic_tokens(req, |params: SemanticTokensDeltaParams| {
+                    (params.text_document.uri, Some(params.previous_result_id))
+                });
+            }
+            "textDocument/semanticTokens/range" => {
+                self.spawn(req, Snapshot::handle_semantic_tokens_range);
+            }
+            method => {
+                eprintln!("Unrecognized request: {}", method);
+                req.response_handle.respond(Err(ResponseError::new(
+                    ErrorCode::MethodNotFound,
+                    &format!("Unknown method: {}", method),
+                )));
+            }
+        }
+    }
+
+    fn handle_notification(&mut self, notification: Notification) {
+        // Notifications are dropped until the server is initialized, `exit` is handled by the
+        // server.
+        if self.lifecycle != Lifecycle::Initialized {
+            eprintln!("Ignored notification: {}", notification.method);
+            return;
+        }
+        match notification.method.as_ref() {
+            "initialized" => {
+                if let Some(root) = self.root.take() {
+                    self.spawn_indexing(root, self.report_progress);
                 }
-                "textDocument/didChange" => {
-                    let params: DidChangeTextDocumentParams =
-                        serde_json::from_value(notification.params.clone()).unwrap();
-                    self.handle_did_change(params);
+                if self.watch_files {
+                    self.register_file_watchers();
                 }
-                method => {
-                    eprintln!("Unrecognized notification: {}", method);
+                if self.pull_configuration {
+                    if let Err(e) = self.fetch_configuration() {
+                        log_message(
+                            &self.sender,
+                            MessageType::ERROR,
+                            &format!("Failed to fetch configuration: {}", e),
+                        );
+                    }
                 }
-            },
+            }
+            "textDocument/didOpen" => {
+                self.notify(notification, State::handle_did_open);
+            }
+            "textDocument/didChange" => {
+                self.notify(notification, State::handle_did_change);
+            }
+            "textDocument/didSave" => {
+                self.notify(notification, State::handle_did_save);
+            }
+            "textDocument/didClose" => {
+                self.notify(notification, State::handle_did_close);
+            }
+            "workspace/didChangeConfiguration" => {
+                self.notify(notification, State::handle_did_change_configuration);
+            }
+            "workspace/didChangeWatchedFiles" => {
+                self.notify(notification, State::handle_did_change_watched_files);
+            }
+            // Notifications starting with `$/` are optional.
+            method if method.starts_with("$/") => {}
+            method => {
+                eprintln!("Unrecognized notification: {}", method);
+            }
         }
     }
 
-    fn handle_did_open(&mut self, params: DidOpenTextDocumentParams) {
-        self.source_map.add(
-            &params.text_document.uri,
-            params.text_document.text.to_string(),
-        );
+    fn handle_initialize(&mut self, params: InitializeParams) -> Result<InitializeResult, Error> {
+        // The workspace is indexed after the client is initialized, as progress can't be
+        // reported before.
+        self.root = params.root_uri.and_then(|uri| uri.to_file_path().ok());
+        self.report_progress = supports_work_done_progress(&params.capabilities);
+        if let Some(options) = params.initialization_options {
+            match serde_json::from_value(options) {
+                Ok(config) => self.apply_config(config),
+                Err(e) => eprintln!("Invalid initializationOptions: {}", e),
+            }
+        }
+        self.snapshot.hover_format = hover_format(&params.capabilities);
+        self.watch_files = supports_watched_files_registration(&params.capabilities);
+        self.pull_diagnostics = supports_pull_diagnostics(&params.capabilities);
+        self.pull_configuration = supports_configuration_requests(&params.capabilities);
+        self.refresh_diagnostics = supports_diagnostic_refresh(&params.capabilities);
+        self.lifecycle = Lifecycle::Initialized;
+        let result = InitializeResult {
+            capabilities: server_capabilities(&params.capabilities),
+            server_info: Some(ServerInfo {
+                name: "vimscript-language-server".to_string(),
+                version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            }),
+        };
+        Ok(result)
+    }
 
-        publish_diagnostics(
-            &params.text_document.text,
-            params.text_document.uri,
-            &self.sender,
-        );
+    // Indexes the directory on a worker, so that indexing a large tree doesn't block handling of
+    // messages. The result is added to the snapshot by `merge_indexed_directories`.
+    fn spawn_indexing(&self, dir: PathBuf, report_progress: bool) {
+        let sender = self.sender.clone();
+        let indexed = self.indexed_sender.clone();
+        self.workers.execute(move || {
+            let token = ProgressToken::String(format!("indexing {}", dir.display()));
+            let progress = if report_progress {
+                sender
+                    .send_request("window/workDoneProgress/create", json!({ "token": token }))
+                    .ok()
+                    .map(|_| token)
+            } else {
+                None
+            };
+            if let Some(token) = &progress {
+                send_progress(
+                    &sender,
+                    token,
+                    WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                        title: "Indexing".to_string(),
+                        cancellable: Some(false),
+                        message: Some(dir.display().to_string()),
+                        percentage: None,
+                    }),
+                );
+            }
+            let mut index = Index::new();
+            index.add_directory(&dir);
+            // The receiver is only dropped together with the workers.
+            let _ = indexed.send(index);
+            if let Some(token) = &progress {
+                send_progress(
+                    &sender,
+                    token,
+                    WorkDoneProgress::End(WorkDoneProgressEnd { message: None }),
+                );
+            }
+        });
     }
 
-    fn handle_did_change(&mut self, params: DidChangeTextDocumentParams) {
+    // Indexes files that changed on disk on a worker, like `spawn_indexing`.
+    fn spawn_file_indexing(&self, paths: Vec<PathBuf>) {
+        if paths.is_empty() {
+            return;
+        }
+        let indexed = self.indexed_sender.clone();
+        self.workers.execute(move || {
+            let mut index = Index::new();
+            for path in paths.iter().filter(|path| path.exists()) {
+                index.add_file(path);
+            }
+            // The receiver is only dropped together with the workers.
+            let _ = indexed.send(index);
+        });
+    }
+
+    // Adds directories and files indexed by workers to the index. Diagnostics are computed again,
+    // as they depend on the other files.
+    fn merge_indexed_directories(&mut self) {
+        let indexes: Vec<Index> = self.indexed_receiver.try_iter().collect();
+        if indexes.is_empty() {
+            return;
+        }
+        let source_map = &self.snapshot.source_map;
+        let index = Arc::make_mut(&mut self.snapshot.index);
+        for indexed in indexes {
+            // Open documents are indexed from the content sent by the client.
+            index.merge(indexed, |uri| source_map.contains(uri));
+        }
+        self.snapshot.revision += 1;
+        self.rerun_diagnostics();
+    }
+
+    // Stores semantic tokens computed by workers, unless their document was closed meanwhile.
+    fn merge_semantic_tokens(&mut self) {
+        for (uri, tokens) in self.semantic_tokens_receiver.try_iter() {
+            if self.snapshot.source_map.contains(&uri) {
+                self.semantic_tokens.insert(uri, tokens);
+            }
+        }
+    }
+
+    // Asks the client to notify about changes of files that are not open, so that the index stays
+    // up to date.
+    fn register_file_watchers(&self) {
+        let watchers = ["**/*.vim", "**/doc/*.txt"]
+            .iter()
+            .map(|pattern| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(pattern.to_string()),
+                kind: None,
+            })
+            .collect();
+        let params = RegistrationParams {
+            registrations: vec![Registration {
+                id: "watched-files".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options: Some(
+                    serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                        watchers: watchers,
+                    })
+                    .unwrap(),
+                ),
+            }],
+        };
+        let sender = self.sender.clone();
+        // The request waits for the response, which shouldn't block handling of other messages.
+        self.workers.execute(move || {
+            if let Err(e) = sender.send_request(
+                "client/registerCapability",
+                serde_json::to_value(params).unwrap(),
+            ) {
+                eprintln!("Failed to register file watchers: {}", e.message);
+            }
+        });
+    }
+
+    // Requests the `vimscript` section of the client configuration. The main loop waits for the
+    // response, so that messages received after a change of the settings see the new ones.
+    fn fetch_configuration(&mut self) -> Result<(), Error> {
+        let params = ConfigurationParams {
+            items: vec![ConfigurationItem {
+                scope_uri: None,
+                section: Some("vimscript".to_string()),
+            }],
+        };
+        let response = self
+            .sender
+            .send_request(
+                "workspace/configuration",
+                serde_json::to_value(params).unwrap(),
+            )
+            .map_err(|e| Error::RequestFailed(e.message))?;
+        // The client sends null if the section is not configured.
+        let settings = match response.get(0) {
+            Some(serde_json::Value::Null) | None => json!({}),
+            Some(settings) => settings.clone(),
+        };
+        let config = parse_params(settings)?;
+        self.apply_config(config);
+        self.rerun_diagnostics();
+        Ok(())
+    }
+
+    fn handle_did_change_configuration(
+        &mut self,
+        params: DidChangeConfigurationParams,
+    ) -> Result<(), Error> {
+        // Clients that can be asked for the settings usually don't send them in the notification.
+        if self.pull_configuration {
+            return self.fetch_configuration();
+        }
+        if let Some(settings) = params.settings.get("vimscript") {
+            let config = parse_params(settings.clone())?;
+            self.apply_config(config);
+            self.rerun_diagnostics();
+        }
+        Ok(())
+    }
+
+    fn apply_config(&mut self, config: Config) {
+        // Directories removed from the runtimepath stay indexed until the server is restarted.
+        for dir in &config.runtimepath {
+            if !self.snapshot.config.runtimepath.contains(dir) {
+                self.spawn_indexing(dir.clone(), false);
+            }
+        }
+        self.snapshot.config = Arc::new(config);
+        self.snapshot.revision += 1;
+    }
+
+    // Recomputes diagnostics of open documents, e.g. after the settings changed.
+    fn rerun_diagnostics(&mut self) {
+        if self.pull_diagnostics {
+            if self.refresh_diagnostics {
+                let sender = self.sender.clone();
+                self.workers.execute(move || {
+                    if let Err(e) =
+                        sender.send_request("workspace/diagnostic/refresh", serde_json::Value::Null)
+                    {
+                        eprintln!("Failed to refresh diagnostics: {}", e.message);
+                    }
+                });
+            }
+            return;
+        }
+        // Diagnostics computed with the previous settings are dropped.
+        let uris: Vec<Url> = {
+            let mut revisions = self.document_revisions.lock().unwrap();
+            for revision in revisions.values_mut() {
+                *revision = self.snapshot.revision;
+            }
+            revisions.keys().cloned().collect()
+        };
+        for uri in uris {
+            self.spawn_diagnostics(uri, true);
+        }
+    }
+
+    fn handle_did_change_watched_files(
+        &mut self,
+        params: DidChangeWatchedFilesParams,
+    ) -> Result<(), Error> {
+        let index = Arc::make_mut(&mut self.snapshot.index);
+        let mut paths = Vec::new();
+        for change in params.changes {
+            // Open documents are indexed from the content sent by the client.
+            if self.snapshot.source_map.contains(&change.uri) {
+                continue;
+            }
+            if change.typ == FileChangeType::DELETED {
+                index.remove(&change.uri);
+            } else if let Ok(path) = change.uri.to_file_path() {
+                paths.push(path);
+            }
+        }
+        self.snapshot.revision += 1;
+        self.spawn_file_indexing(paths);
+        Ok(())
+    }
+
+    // Handles the request on the main thread, for requests that change the state.
+    fn handle<P, R>(&mut self, req: Request, handler: fn(&mut State, P) -> Result<R, Error>)
+    where
+        P: DeserializeOwned,
+        R: Serialize,
+    {
+        let Request {
+            method,
+            params,
+            response_handle,
+        } = req;
+        let sender = self.sender.clone();
+        let result = catch_panic(&sender, &method, || handler(self, parse_params(params)?));
+        respond(response_handle, result);
+    }
+
+    // Runs the read-only request on a worker with the current snapshot.
+    fn spawn<P, R>(&self, req: Request, handler: fn(&Snapshot, P) -> Result<R, Error>)
+    where
+        P: DeserializeOwned + 'static,
+        R: Serialize + 'static,
+    {
+        let Request {
+            method,
+            params,
+            response_handle,
+        } = req;
+        self.spawn_job(method, response_handle, move |snapshot| {
+            handler(snapshot, parse_params(params)?)
+        });
+    }
+
+    // Answers the request with the result of the job, which runs on a worker with the current
+    // snapshot.
+    fn spawn_job<R, F>(&self, method: String, response_handle: ResponseHandle, job: F)
+    where
+        R: Serialize + 'static,
+        F: FnOnce(&Snapshot) -> Result<R, Error> + Send + 'static,
+    {
+        let snapshot = self.snapshot.clone();
+        let sender = self.sender.clone();
+        self.workers.execute(move || {
+            // The request was cancelled or its document changed while it was queued, the
+            // response handle turns this into an error.
+            if response_handle.is_cancelled() {
+                response_handle.respond(Ok(serde_json::Value::Null));
+                return;
+            }
+            let result = catch_panic(&sender, &method, || job(&snapshot));
+            respond(response_handle, result);
+        });
+    }
+
+    // Computes workspace diagnostics on a worker, reusing the reports of the previous pull.
+    fn spawn_workspace_diagnostic(&self, req: Request) {
+        let Request {
+            method,
+            params,
+            response_handle,
+        } = req;
+        let reports = Arc::clone(&self.workspace_reports);
+        self.spawn_job(method, response_handle, move |snapshot| {
+            snapshot.handle_workspace_diagnostic(parse_params(params)?, &reports)
+        });
+    }
+
+    // Computes semantic tokens of the whole document on a worker. Only the result id and the
+    // previous tokens are taken on the main thread, the new tokens are stored by
+    // `merge_semantic_tokens` to compute the delta of the next request.
+    fn spawn_semantic_tokens<P: DeserializeOwned>(
+        &mut self,
+        req: Request,
+        document: fn(P) -> (Url, Option<String>),
+    ) {
+        let Request {
+            method,
+            params,
+            response_handle,
+        } = req;
+        let (uri, previous_result_id) = match parse_params(params) {
+            Ok(params) => document(params),
+            Err(e) => {
+                respond::<()>(response_handle, Some(Err(e)));
+                return;
+            }
+        };
+        let previous = self
+            .semantic_tokens
+            .get(&uri)
+            .filter(|p| p.result_id.is_some() && p.result_id == previous_result_id)
+            .cloned();
+        self.next_result_id += 1;
+        let result_id = self.next_result_id.to_string();
+        let semantic_tokens = self.semantic_tokens_sender.clone();
+        self.spawn_job(method, response_handle, move |snapshot| {
+            let content = snapshot.content(&uri)?;
+            let tokens = SemanticTokens {
+                result_id: Some(result_id),
+                data: semantic_tokens::semantic_tokens(&content, None),
+            };
+            let tokens = Arc::new(tokens);
+            // The tokens are sent before the response, so that they are stored when the client
+            // asks for the next delta. The receiver is only dropped together with the workers.
+            let _ = semantic_tokens.send((uri, tokens.clone()));
+            let result = match previous {
+                Some(previous) => SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+                    edits: semantic_tokens::diff(&previous.data, &tokens.data),
+                    result_id: tokens.result_id.clone(),
+                }),
+                // Serialized like the result of a request of all tokens.
+                None => SemanticTokensFullDeltaResult::Tokens((*tokens).clone()),
+            };
+            Ok(result)
+        });
+    }
+
+    // Handles the notification, errors are reported to the client as there is no response.
+    fn notify<P: DeserializeOwned>(
+        &mut self,
+        notification: Notification,
+        handler: fn(&mut State, P) -> Result<(), Error>,
+    ) {
+        if let Err(e) = parse_params(notification.params).and_then(|params| handler(self, params)) {
+            log_message(
+                &self.sender,
+                MessageType::ERROR,
+                &format!("Failed to handle {}: {}", notification.method, e),
+            );
+        }
+    }
+
+    fn handle_did_open(&mut self, params: DidOpenTextDocumentParams) -> Result<(), Error> {
+        let document = params.text_document;
+        self.update_document(&document.uri, document.text, document.version);
+        self.spawn_diagnostics(document.uri, true);
+        Ok(())
+    }
+
+    fn handle_did_change(&mut self, params: DidChangeTextDocumentParams) -> Result<(), Error> {
+        let uri = params.text_document.uri;
+        let version = params.text_document.version;
+        match self.snapshot.source_map.version(&uri) {
+            None => return Err(Error::UnknownDocument(uri)),
+            Some(current) if version <= current => {
+                return Err(Error::OutOfOrderChange(uri, version))
+            }
+            Some(_) => {}
+        }
         // TODO: Add support for partial content changes
-        if params.content_changes.len() != 1 {
-            panic!("unsupported not one content changes");
+        if params.content_changes.iter().any(|c| c.range.is_some()) {
+            return Err(Error::UnsupportedChange);
         }
-        if !params.content_changes[0].range.is_none() {
-            panic!("unsupported partial content change");
+        // Each change replaces the whole document, so only the last one matters.
+        let text = match params.content_changes.into_iter().last() {
+            Some(change) => change.text,
+            None => return Ok(()),
+        };
+        self.update_document(&uri, text, version);
+        self.spawn_diagnostics(uri, false);
+        Ok(())
+    }
+
+    fn handle_did_save(&mut self, params: DidSaveTextDocumentParams) -> Result<(), Error> {
+        let uri = params.text_document.uri;
+        if !self.snapshot.source_map.contains(&uri) {
+            return Err(Error::UnknownDocument(uri));
         }
-        self.source_map.add(
-            &params.text_document.uri,
-            params.content_changes[0].text.to_string(),
-        );
-        publish_diagnostics(
-            &params.content_changes[0].text,
-            params.text_document.uri,
-            &self.sender,
-        );
+        self.spawn_diagnostics(uri, true);
+        Ok(())
+    }
+
+    fn handle_did_close(&mut self, params: DidCloseTextDocumentParams) -> Result<(), Error> {
+        let uri = params.text_document.uri;
+        Arc::make_mut(&mut self.snapshot.source_map).remove(&uri);
+        // The content on disk is indexed on a worker instead, as the document may have unsaved
+        // changes.
+        Arc::make_mut(&mut self.snapshot.index).remove(&uri);
+        if let Ok(path) = uri.to_file_path() {
+            self.spawn_file_indexing(vec![path]);
+        }
+        self.semantic_tokens.remove(&uri);
+        self.snapshot.revision += 1;
+        // Diagnostics that are still being computed are dropped, as the document doesn't have a
+        // revision anymore.
+        let mut revisions = self.document_revisions.lock().unwrap();
+        revisions.remove(&uri);
+        if !self.pull_diagnostics {
+            publish_diagnostics(Vec::new(), uri, None, &self.sender);
+        }
+        Ok(())
+    }
+
+    // Stores the new content in a new revision.
+    fn update_document(&mut self, uri: &Url, text: String, version: i32) {
+        self.snapshot.revision += 1;
+        // Snapshots taken by running requests keep the previous content.
+        Arc::make_mut(&mut self.snapshot.index).update(uri, &text);
+        Arc::make_mut(&mut self.snapshot.source_map).add(uri, text, version);
+        self.document_revisions
+            .lock()
+            .unwrap()
+            .insert(uri.clone(), self.snapshot.revision);
+    }
+
+    // Computes diagnostics of the document on a worker. Workspace diagnostics are more expensive,
+    // so they are only computed when the document is opened or saved.
+    fn spawn_diagnostics(&self, uri: Url, include_workspace: bool) {
+        if self.pull_diagnostics {
+            return;
+        }
+        let revision = match self.document_revisions.lock().unwrap().get(&uri) {
+            Some(revision) => *revision,
+            None => return,
+        };
+        let snapshot = self.snapshot.clone();
+        let document_revisions = Arc::clone(&self.document_revisions);
+        let sender = self.sender.clone();
+        self.workers.execute(move || {
+            let is_current = |revisions: &HashMap<Url, u64>| revisions.get(&uri) == Some(&revision);
+            if !is_current(&document_revisions.lock().unwrap()) {
+                return;
+            }
+            let (text, version) = match (
+                snapshot.source_map.get_content(&uri),
+                snapshot.source_map.version(&uri),
+            ) {
+                (Some(text), Some(version)) => (text, version),
+                _ => return,
+            };
+            let diagnostics = catch_panic(&sender, "diagnostics", || {
+                snapshot.document_diagnostics(&uri, &text, include_workspace)
+            });
+            let diagnostics = match diagnostics {
+                Some(diagnostics) => diagnostics,
+                None => return,
+            };
+            // The lock is held while sending, so that diagnostics of a newer revision can't be
+            // overwritten by these.
+            let revisions = document_revisions.lock().unwrap();
+            if is_current(&revisions) {
+                publish_diagnostics(diagnostics, uri, Some(version), &sender);
+            }
+        });
+    }
+
+    fn handle_execute_command(
+        &mut self,
+        params: ExecuteCommandParams,
+    ) -> Result<serde_json::Value, Error> {
+        match params.command.as_ref() {
+            RUN_TEST_COMMAND => {
+                // Tests have to run in Vim, which is not supported yet, so the client is only
+                // told which test was requested.
+                let name = params
+                    .arguments
+                    .get(1)
+                    .and_then(|name| name.as_str())
+                    .unwrap_or_default();
+                self.sender.send_notification(
+                    "window/showMessage",
+                    json!({
+                        "type": MessageType::INFO,
+                        "message": format!("Running tests is not supported yet: {}", name),
+                    }),
+                );
+                Ok(serde_json::Value::Null)
+            }
+            command => Err(Error::UnknownCommand(command.to_string())),
+        }
+    }
+}
+
+impl Snapshot {
+    fn content(&self, uri: &Url) -> Result<String, Error> {
+        self.source_map
+            .get_content(uri)
+            .ok_or_else(|| Error::UnknownDocument(uri.clone()))
+    }
+
+    // Returns the settings of the client with the ones of project configuration files applied.
+    fn document_config(&self, uri: &Url) -> Config {
+        let path = match uri.to_file_path() {
+            Ok(path) => path,
+            Err(()) => return (*self.config).clone(),
+        };
+        config_for_file(&path, &self.config).unwrap_or_else(|e| {
+            eprintln!("Failed to read project configuration: {}", e);
+            (*self.config).clone()
+        })
     }
 
-    fn handle_rename(&self, req: Request) {
+    fn document_diagnostics(
+        &self,
+        uri: &Url,
+        text: &str,
+        include_workspace: bool,
+    ) -> Vec<Diagnostic> {
+        let config = self.document_config(uri);
+        let mut result = diagnostics(text, &config);
+        if include_workspace {
+            result.extend(workspace_diagnostics(&self.index, uri));
+        }
+        config.lint.apply(result)
+    }
+
+    fn handle_rename(&self, params: RenameParams) -> Result<WorkspaceEdit, Error> {
         // TODO: This doesn't work yet, it is still WIP!
-        let params: RenameParams = serde_json::from_value(req.params.clone()).unwrap();
-        let content = self
-            .source_map
-            .get_content(&params.text_document_position.text_document.uri)
-            .unwrap();
+        let content = self.content(&params.text_document_position.text_document.uri)?;
         let edits = rename(
             &content,
             params.text_document_position.position,
             &params.new_name,
         )
-        .unwrap();
+        .map_err(|_| Error::RequestFailed("nothing to rename at the position".to_string()))?;
         let mut changes = HashMap::new();
         changes.insert(params.text_document_position.text_document.uri, edits);
-        req.response_handle
-            .respond(Ok(serde_json::to_value(WorkspaceEdit {
-                changes: Some(changes),
-                document_changes: None,
-            })
-            .unwrap()))
+        Ok(WorkspaceEdit {
+            changes: Some(changes),
+            document_changes: None,
+            change_annotations: None,
+        })
     }
 
-    fn handle_document_highlight(&self, req: Request) {
-        // TODO: This doesn't work yet, it is still WIP!
-        let params: DocumentHighlightParams = serde_json::from_value(req.params.clone()).unwrap();
-        let content = self
-            .source_map
-            .get_content(&params.text_document_position_params.text_document.uri)
-            .unwrap();
-
-        let parser = Parser::new(Lexer::new(&content));
-        // let program = parser.parse();
-        let pos = params.text_document_position_params.position;
-        let token = parser
-            .find_token(SourcePosition {
-                line: i32::try_from(pos.line).unwrap(),
-                character: i32::try_from(pos.character).unwrap(),
+    fn handle_document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Vec<DocumentHighlight>, Error> {
+        let content = self.content(&params.text_document_position_params.text_document.uri)?;
+        let result = document_highlights(&content, params.text_document_position_params.position);
+        Ok(result)
+    }
+
+    fn handle_hover(&self, params: HoverParams) -> Result<Option<Hover>, Error> {
+        let content = self.content(&params.text_document_position_params.text_document.uri)?;
+        let result = hover(
+            &content,
+            params.text_document_position_params.position,
+            self.hover_format.clone(),
+        );
+        Ok(result)
+    }
+
+    fn handle_signature_help(
+        &self,
+        params: SignatureHelpParams,
+    ) -> Result<Option<SignatureHelp>, Error> {
+        let content = self.content(&params.text_document_position_params.text_document.uri)?;
+        let result = signature_help(&content, params.text_document_position_params.position);
+        Ok(result)
+    }
+
+    fn handle_document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<DocumentSymbolResponse, Error> {
+        let content = self.content(&params.text_document.uri)?;
+        let result = DocumentSymbolResponse::Nested(document_symbols(&content));
+        Ok(result)
+    }
+
+    fn handle_folding_range(&self, params: FoldingRangeParams) -> Result<Vec<FoldingRange>, Error> {
+        let content = self.content(&params.text_document.uri)?;
+        let result = folding_ranges(&content);
+        Ok(result)
+    }
+
+    fn handle_selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> Result<Vec<SelectionRange>, Error> {
+        let content = self.content(&params.text_document.uri)?;
+        let result = selection_ranges(&content, &params.positions);
+        Ok(result)
+    }
+
+    fn handle_code_action(&self, params: CodeActionParams) -> Result<Vec<CodeAction>, Error> {
+        let content = self.content(&params.text_document.uri)?;
+        let mut result = code_actions(
+            &content,
+            &params.text_document.uri,
+            &params.context.diagnostics,
+        );
+        result.extend(refactorings(
+            &content,
+            &params.text_document.uri,
+            params.range,
+        ));
+        // Kinds are hierarchical, e.g. `refactor` includes `refactor.extract`.
+        if let Some(only) = &params.context.only {
+            result.retain(|action| {
+                let kind = action.kind.as_ref().map_or("", |k| k.as_str());
+                only.iter()
+                    .any(|o| kind == o.as_str() || kind.starts_with(&format!("{}.", o.as_str())))
+            });
+        }
+        Ok(result)
+    }
+
+    fn handle_prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>, Error> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let content = self.content(uri)?;
+        let result = prepare_call_hierarchy(
+            &self.index,
+            uri,
+            &content,
+            params.text_document_position_params.position,
+        );
+        Ok(result)
+    }
+
+    fn handle_incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Vec<CallHierarchyIncomingCall>, Error> {
+        let result = incoming_calls(&self.index, &params.item);
+        Ok(result)
+    }
+
+    fn handle_outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Vec<CallHierarchyOutgoingCall>, Error> {
+        let result = outgoing_calls(&self.index, &params.item);
+        Ok(result)
+    }
+
+    fn handle_inlay_hint(&self, params: InlayHintParams) -> Result<Vec<InlayHint>, Error> {
+        let uri = &params.text_document.uri;
+        let content = self.content(uri)?;
+        let config = self.document_config(uri);
+        let result = inlay_hints(&content, params.range, &config.inlay_hints);
+        Ok(result)
+    }
+
+    fn handle_code_lens(&self, params: CodeLensParams) -> Result<Vec<CodeLens>, Error> {
+        let result = code_lenses(&self.index, &params.text_document.uri);
+        Ok(result)
+    }
+
+    fn handle_implementation(&self, params: GotoDefinitionParams) -> Result<Vec<Location>, Error> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let content = self.content(uri)?;
+        let result = implementations(
+            &self.index,
+            uri,
+            &content,
+            params.text_document_position_params.position,
+        );
+        Ok(result)
+    }
+
+    fn handle_type_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<Location>, Error> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let content = self.content(uri)?;
+        let result = type_definition(uri, &content, params.text_document_position_params.position);
+        Ok(result)
+    }
+
+    fn handle_document_link(&self, params: DocumentLinkParams) -> Result<Vec<DocumentLink>, Error> {
+        let uri = &params.text_document.uri;
+        let content = self.content(uri)?;
+        let result = document_links(&self.index, uri, &content);
+        Ok(result)
+    }
+
+    fn handle_document_diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult, Error> {
+        let uri = &params.text_document.uri;
+        let content = self.content(uri)?;
+        let items = self.document_diagnostics(uri, &content, true);
+        let result_id = result_id(&items);
+        let report = if params.previous_result_id.as_ref() == Some(&result_id) {
+            DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                related_documents: None,
+                unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                    result_id: result_id,
+                },
             })
-            .unwrap();
-        let token_position = parser.resolve_location(token.location);
+        } else {
+            DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: Some(result_id),
+                    items: items,
+                },
+            })
+        };
+        Ok(DocumentDiagnosticReportResult::Report(report))
+    }
 
-        req.response_handle
-            .respond(Ok(serde_json::to_value(vec![DocumentHighlight {
-                kind: None,
-                range: token_position_to_range(&token_position),
-            }])
-            .unwrap()))
+    // Reports diagnostics of all indexed files. Open documents are checked with their content in
+    // the editor, other files are read from disk. Reports are only computed again after the
+    // snapshot changed.
+    fn handle_workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+        cache: &Mutex<WorkspaceReports>,
+    ) -> Result<WorkspaceDiagnosticReportResult, Error> {
+        let previous_result_ids: HashMap<&Url, &String> = params
+            .previous_result_ids
+            .iter()
+            .map(|previous| (&previous.uri, &previous.value))
+            .collect();
+        let cached = {
+            let cache = cache.lock().unwrap();
+            if cache.revision == self.revision {
+                cache.reports.clone()
+            } else {
+                HashMap::new()
+            }
+        };
+        let mut reports = HashMap::new();
+        let mut uris: Vec<&Url> = self.index.files().collect();
+        uris.sort();
+        let mut items = Vec::new();
+        for uri in uris {
+            let report = match cached.get(uri) {
+                Some(report) => Arc::clone(report),
+                None => match self.workspace_report(uri) {
+                    Some(report) => Arc::new(report),
+                    None => continue,
+                },
+            };
+            reports.insert(uri.clone(), Arc::clone(&report));
+            if previous_result_ids.get(uri) == Some(&&report.result_id) {
+                items.push(WorkspaceDocumentDiagnosticReport::Unchanged(
+                    WorkspaceUnchangedDocumentDiagnosticReport {
+                        uri: uri.clone(),
+                        version: report.version,
+                        unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                            result_id: report.result_id.clone(),
+                        },
+                    },
+                ));
+            } else {
+                items.push(WorkspaceDocumentDiagnosticReport::Full(
+                    WorkspaceFullDocumentDiagnosticReport {
+                        uri: uri.clone(),
+                        version: report.version,
+                        full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                            result_id: Some(report.result_id.clone()),
+                            items: report.diagnostics.clone(),
+                        },
+                    },
+                ));
+            }
+        }
+        let mut cache = cache.lock().unwrap();
+        // A pull of a newer snapshot may have finished first.
+        if cache.revision <= self.revision {
+            *cache = WorkspaceReports {
+                revision: self.revision,
+                reports: reports,
+            };
+        }
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items: items },
+        ))
+    }
+
+    // Checks the file, or returns None if it can't be read.
+    fn workspace_report(&self, uri: &Url) -> Option<WorkspaceReport> {
+        let (text, version) = match self.source_map.get_content(uri) {
+            Some(text) => (text, self.source_map.version(uri).map(i64::from)),
+            None => match uri.to_file_path().map(fs::read_to_string) {
+                Ok(Ok(text)) => (text, None),
+                _ => return None,
+            },
+        };
+        let diagnostics = self.document_diagnostics(uri, &text, true);
+        Some(WorkspaceReport {
+            version: version,
+            result_id: result_id(&diagnostics),
+            diagnostics: diagnostics,
+        })
+    }
+
+    fn handle_workspace_symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Vec<SymbolInformation>, Error> {
+        let result = self.index.search(&params.query);
+        Ok(result)
+    }
+
+    fn handle_semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> Result<SemanticTokensRangeResult, Error> {
+        let content = self.content(&params.text_document.uri)?;
+        let result = SemanticTokensRangeResult::Tokens(SemanticTokens {
+            result_id: None,
+            data: semantic_tokens::semantic_tokens(&content, Some(params.range)),
+        });
+        Ok(result)
     }
 }
 
-fn publish_diagnostics(text: &str, uri: Url, sender: &LspSender) {
-    let mut parser = Parser::new(Lexer::new(text));
-    parser.parse();
-    let mut diagnostics_params = PublishDiagnosticsParams {
+/// Errors of request and notification handlers, which are reported to the client.
+#[derive(Debug)]
+enum Error {
+    InvalidParams(serde_json::Error),
+    // The document was not opened or was already closed.
+    UnknownDocument(Url),
+    UnknownCommand(String),
+    UnsupportedChange,
+    // A change with a version that is not newer than the version of the document.
+    OutOfOrderChange(Url, i32),
+    // The request is valid, but the server can't answer it, e.g. rename of a keyword.
+    RequestFailed(String),
+    Panicked,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidParams(e) => write!(f, "invalid params: {}", e),
+            Error::UnknownDocument(uri) => write!(f, "unknown document: {}", uri),
+            Error::UnknownCommand(command) => write!(f, "unknown command: {}", command),
+            Error::UnsupportedChange => write!(f, "partial content changes are not supported"),
+            Error::OutOfOrderChange(uri, version) => {
+                write!(f, "version {} of {} is outdated", version, uri)
+            }
+            Error::RequestFailed(message) => write!(f, "{}", message),
+            Error::Panicked => write!(f, "internal error, see the log for details"),
+        }
+    }
+}
+
+impl From<Error> for ResponseError {
+    fn from(error: Error) -> ResponseError {
+        let code = match error {
+            Error::InvalidParams(_) | Error::UnknownDocument(_) | Error::UnknownCommand(_) => {
+                ErrorCode::InvalidParams
+            }
+            Error::UnsupportedChange | Error::OutOfOrderChange(_, _) | Error::RequestFailed(_) => {
+                ErrorCode::RequestFailed
+            }
+            Error::Panicked => ErrorCode::InternalError,
+        };
+        ResponseError::new(code, &error.to_string())
+    }
+}
+
+fn parse_params<P: DeserializeOwned>(params: serde_json::Value) -> Result<P, Error> {
+    serde_json::from_value(params).map_err(Error::InvalidParams)
+}
+
+fn respond<R: Serialize>(response_handle: ResponseHandle, result: Option<Result<R, Error>>) {
+    let result = result.unwrap_or(Err(Error::Panicked)).and_then(|result| {
+        serde_json::to_value(result).map_err(|e| Error::RequestFailed(e.to_string()))
+    });
+    response_handle.respond(result.map_err(ResponseError::from));
+}
+
+// Runs the function and returns its result, or None if it panicked. Panics are reported to the
+// client, so that a bug in one feature doesn't take down the whole server.
+fn catch_panic<T>(sender: &LspSender, context: &str, f: impl FnOnce() -> T) -> Option<T> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => Some(result),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            log_message(
+                sender,
+                MessageType::ERROR,
+                &format!("Panic while handling {}: {}", context, message),
+            );
+            None
+        }
+    }
+}
+
+fn send_progress(sender: &LspSender, token: &ProgressToken, progress: WorkDoneProgress) {
+    let params = ProgressParams {
+        token: token.clone(),
+        value: ProgressParamsValue::WorkDone(progress),
+    };
+    sender.send_notification("$/progress", serde_json::to_value(params).unwrap());
+}
+
+fn log_message(sender: &LspSender, typ: MessageType, message: &str) {
+    sender.send_notification(
+        "window/logMessage",
+        json!({
+            "type": typ,
+            "message": message,
+        }),
+    );
+}
+
+fn publish_diagnostics(
+    diagnostics: Vec<Diagnostic>,
+    uri: Url,
+    version: Option<i32>,
+    sender: &LspSender,
+) {
+    let diagnostics_params = PublishDiagnosticsParams {
         uri: uri,
-        diagnostics: Vec::new(),
-        version: None,
+        diagnostics: diagnostics,
+        version: version,
     };
-    for error in parser.errors {
-        diagnostics_params.diagnostics.push(Diagnostic {
-            range: token_position_to_range(&error.position),
-            message: error.message,
-            code: None,
-            related_information: None,
-            severity: Some(DiagnosticSeverity::Error),
-            source: None,
-            tags: None,
-        });
-    }
     sender.send_notification(
         "textDocument/publishDiagnostics",
         serde_json::to_value(diagnostics_params).unwrap(),
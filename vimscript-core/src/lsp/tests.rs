@@ -1,4 +1,8 @@
 use super::*;
+use lsp_types::DocumentHighlight;
+use lsp_types::DocumentHighlightKind;
+use lsp_types::Position;
+use lsp_types::Range;
 use std::io;
 use std::sync::mpsc::channel;
 use std::sync::mpsc::Receiver;
@@ -66,6 +70,32 @@ fn create_client_and_server() -> (Client, Server<FakeReader, FakeWriter>) {
     return (client, server);
 }
 
+fn initialize(client: &Client) {
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": "initialize",
+            "params": {
+                "processId": serde_json::Value::Null,
+                "rootUri": serde_json::Value::Null,
+                "capabilities": {
+                },
+            },
+        }))
+        .unwrap();
+    client.recv().unwrap();
+}
+
+fn exit(client: &Client) {
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "method": "exit",
+        }))
+        .unwrap();
+}
+
 #[test]
 fn responds_to_initialize() {
     let (client, server) = create_client_and_server();
@@ -86,7 +116,15 @@ fn responds_to_initialize() {
             },
         }))
         .unwrap();
-    client.recv().unwrap();
+    let response = client.recv().unwrap();
+    assert_eq!(
+        response["result"]["serverInfo"]["name"],
+        json!("vimscript-language-server")
+    );
+    assert_eq!(
+        response["result"]["capabilities"]["textDocumentSync"]["change"],
+        json!(1)
+    );
     client
         .send(json!({
             "jsonrpc": "2.0",
@@ -97,6 +135,123 @@ fn responds_to_initialize() {
     t.join().unwrap();
 }
 
+#[test]
+fn responds_to_unknown_request_with_error() {
+    let (client, server) = create_client_and_server();
+    let t = std::thread::spawn(move || {
+        run(server);
+    });
+
+    initialize(&client);
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "unknown/method",
+            "params": {},
+        }))
+        .unwrap();
+    assert_eq!(
+        client.recv().unwrap(),
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": {
+                "code": -32601,
+                "message": "Unknown method: unknown/method",
+            },
+        })
+    );
+    exit(&client);
+
+    t.join().unwrap();
+}
+
+#[test]
+fn rejects_requests_before_initialize() {
+    let (client, server) = create_client_and_server();
+    let t = std::thread::spawn(move || run(server));
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "textDocument/hover",
+            "params": {},
+        }))
+        .unwrap();
+    assert_eq!(
+        client.recv().unwrap(),
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": {
+                "code": -32002,
+                "message": "server is not initialized",
+            },
+        })
+    );
+    exit(&client);
+
+    assert_eq!(t.join().unwrap(), 1);
+}
+
+#[test]
+fn exits_successfully_after_shutdown() {
+    let (client, server) = create_client_and_server();
+    let t = std::thread::spawn(move || run(server));
+
+    initialize(&client);
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "shutdown",
+        }))
+        .unwrap();
+    assert_eq!(
+        client.recv().unwrap(),
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": null,
+        })
+    );
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "textDocument/hover",
+            "params": {},
+        }))
+        .unwrap();
+    assert_eq!(
+        client.recv().unwrap(),
+        json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "error": {
+                "code": -32600,
+                "message": "server is shutting down",
+            },
+        })
+    );
+    exit(&client);
+
+    assert_eq!(t.join().unwrap(), 0);
+}
+
+#[test]
+fn exits_with_error_without_shutdown() {
+    let (client, server) = create_client_and_server();
+    let t = std::thread::spawn(move || run(server));
+
+    initialize(&client);
+    exit(&client);
+
+    assert_eq!(t.join().unwrap(), 1);
+}
+
 #[test]
 fn document_hightlight_highlights_the_same_variable() {
     // TODO: This has to be refactor to make writing tests easy.
@@ -161,23 +316,36 @@ fn document_hightlight_highlights_the_same_variable() {
     let response = client.recv().unwrap();
     let result = response.get("result").unwrap().clone();
     let x: Vec<DocumentHighlight> = serde_json::from_value(result).unwrap();
-    // TODO: This is invalid response, we should actually report both variables not just the
-    // first one.
     assert_eq!(
         x,
-        vec![DocumentHighlight {
-            kind: None,
-            range: Range {
-                start: Position {
-                    line: 0,
-                    character: 4,
+        vec![
+            DocumentHighlight {
+                kind: Some(DocumentHighlightKind::WRITE),
+                range: Range {
+                    start: Position {
+                        line: 0,
+                        character: 4,
+                    },
+                    end: Position {
+                        line: 0,
+                        character: 9,
+                    },
                 },
-                end: Position {
-                    line: 0,
-                    character: 9,
+            },
+            DocumentHighlight {
+                kind: Some(DocumentHighlightKind::WRITE),
+                range: Range {
+                    start: Position {
+                        line: 1,
+                        character: 4,
+                    },
+                    end: Position {
+                        line: 1,
+                        character: 9,
+                    },
                 },
             },
-        }]
+        ]
     );
 
     // Exit
@@ -190,3 +358,710 @@ fn document_hightlight_highlights_the_same_variable() {
 
     t.join().unwrap();
 }
+
+#[test]
+fn responds_with_error_to_request_for_unknown_document() {
+    let (client, server) = create_client_and_server();
+    let t = std::thread::spawn(move || run(server));
+
+    initialize(&client);
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "textDocument/hover",
+            "params": {
+                "textDocument": {"uri": "file:///unknown.vim"},
+                "position": {"line": 0, "character": 0},
+            },
+        }))
+        .unwrap();
+    assert_eq!(
+        client.recv().unwrap(),
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": {
+                "code": -32602,
+                "message": "unknown document: file:///unknown.vim",
+            },
+        })
+    );
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "textDocument/hover",
+            "params": {"textDocument": 1},
+        }))
+        .unwrap();
+    let response = client.recv().unwrap();
+    assert_eq!(response["error"]["code"], json!(-32602));
+    exit(&client);
+
+    t.join().unwrap();
+}
+
+#[test]
+fn tracks_versions_and_clears_diagnostics_of_closed_documents() {
+    let (client, server) = create_client_and_server();
+    let t = std::thread::spawn(move || run(server));
+    let uri = "file:///nonexistent/test.vim";
+
+    initialize(&client);
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "vim",
+                    "version": 3,
+                    "text": "call s:Missing()\n",
+                },
+            },
+        }))
+        .unwrap();
+    let diagnostics = client.recv().unwrap();
+    assert_eq!(
+        diagnostics["method"],
+        json!("textDocument/publishDiagnostics")
+    );
+    assert_eq!(diagnostics["params"]["version"], json!(3));
+    assert_eq!(
+        diagnostics["params"]["diagnostics"][0]["code"],
+        json!("unknown-function")
+    );
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": {"uri": uri, "version": 2},
+                "contentChanges": [{"text": ""}],
+            },
+        }))
+        .unwrap();
+    assert_eq!(
+        client.recv().unwrap()["params"]["message"],
+        json!(format!(
+            "Failed to handle textDocument/didChange: version 2 of {} is outdated",
+            uri
+        ))
+    );
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didClose",
+            "params": {"textDocument": {"uri": uri}},
+        }))
+        .unwrap();
+    assert_eq!(
+        client.recv().unwrap(),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {"uri": uri, "diagnostics": []},
+        })
+    );
+    exit(&client);
+
+    t.join().unwrap();
+}
+
+#[test]
+fn reports_unchanged_pulled_diagnostics() {
+    let (client, server) = create_client_and_server();
+    let t = std::thread::spawn(move || run(server));
+    let uri = "file:///nonexistent/test.vim";
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": "initialize",
+            "params": {
+                "processId": serde_json::Value::Null,
+                "rootUri": serde_json::Value::Null,
+                "capabilities": {"textDocument": {"diagnostic": {}}},
+            },
+        }))
+        .unwrap();
+    let response = client.recv().unwrap();
+    assert_eq!(
+        response["result"]["capabilities"]["diagnosticProvider"]["workspaceDiagnostics"],
+        json!(true)
+    );
+    // Diagnostics are not published to clients pulling them.
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "vim",
+                    "version": 1,
+                    "text": "call s:Missing()\n",
+                },
+            },
+        }))
+        .unwrap();
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "textDocument/diagnostic",
+            "params": {"textDocument": {"uri": uri}},
+        }))
+        .unwrap();
+    let response = client.recv().unwrap();
+    assert_eq!(response["id"], json!(1));
+    assert_eq!(response["result"]["kind"], json!("full"));
+    assert_eq!(
+        response["result"]["items"][0]["code"],
+        json!("unknown-function")
+    );
+    let result_id = response["result"]["resultId"].clone();
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "textDocument/diagnostic",
+            "params": {"textDocument": {"uri": uri}, "previousResultId": result_id},
+        }))
+        .unwrap();
+    assert_eq!(
+        client.recv().unwrap()["result"],
+        json!({"kind": "unchanged", "resultId": result_id})
+    );
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "workspace/diagnostic",
+            "params": {"previousResultIds": [{"uri": uri, "value": result_id}]},
+        }))
+        .unwrap();
+    assert_eq!(
+        client.recv().unwrap()["result"],
+        json!({
+            "items": [{
+                "kind": "unchanged",
+                "resultId": result_id,
+                "uri": uri,
+                "version": 1,
+            }],
+        })
+    );
+    exit(&client);
+
+    t.join().unwrap();
+}
+
+#[test]
+fn computes_delta_of_semantic_tokens() {
+    let (client, server) = create_client_and_server();
+    let t = std::thread::spawn(move || run(server));
+    let uri = "file:///nonexistent/test.vim";
+    // Diagnostics are pulled, so that only responses are received.
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": "initialize",
+            "params": {
+                "processId": serde_json::Value::Null,
+                "rootUri": serde_json::Value::Null,
+                "capabilities": {"textDocument": {"diagnostic": {}}},
+            },
+        }))
+        .unwrap();
+    client.recv().unwrap();
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "vim",
+                    "version": 1,
+                    "text": "let a = 1\nlet b = 2\n",
+                },
+            },
+        }))
+        .unwrap();
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "textDocument/semanticTokens/full",
+            "params": {"textDocument": {"uri": uri}},
+        }))
+        .unwrap();
+    let response = client.recv().unwrap();
+    assert_eq!(response["id"], json!(1));
+    assert_eq!(response["result"]["data"].as_array().unwrap().len(), 40);
+    let result_id = response["result"]["resultId"].clone();
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": {"uri": uri, "version": 2},
+                "contentChanges": [{"text": "let a = 1\nlet b = 'x'\n"}],
+            },
+        }))
+        .unwrap();
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "textDocument/semanticTokens/full/delta",
+            "params": {"textDocument": {"uri": uri}, "previousResultId": result_id},
+        }))
+        .unwrap();
+    let response = client.recv().unwrap();
+    assert_eq!(response["id"], json!(2));
+    assert_ne!(response["result"]["resultId"], result_id);
+    assert_eq!(response["result"]["edits"][0]["start"], json!(35));
+    assert_eq!(response["result"]["edits"][0]["deleteCount"], json!(5));
+
+    // Tokens of another result are sent in full.
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "textDocument/semanticTokens/full/delta",
+            "params": {"textDocument": {"uri": uri}, "previousResultId": result_id},
+        }))
+        .unwrap();
+    let response = client.recv().unwrap();
+    assert_eq!(response["result"]["data"].as_array().unwrap().len(), 40);
+    exit(&client);
+
+    t.join().unwrap();
+}
+
+#[test]
+fn applies_configuration_of_client() {
+    let (client, server) = create_client_and_server();
+    let t = std::thread::spawn(move || run(server));
+    let uri = "file:///nonexistent/test.vim";
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": "initialize",
+            "params": {
+                "processId": serde_json::Value::Null,
+                "rootUri": serde_json::Value::Null,
+                "capabilities": {"workspace": {"configuration": true}},
+                "initializationOptions": {"lint": {"rules": {"unknown-function": "error"}}},
+            },
+        }))
+        .unwrap();
+    client.recv().unwrap();
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "method": "initialized",
+            "params": {},
+        }))
+        .unwrap();
+    let request = client.recv().unwrap();
+    assert_eq!(request["method"], json!("workspace/configuration"));
+    assert_eq!(
+        request["params"]["items"],
+        json!([{"section": "vimscript"}])
+    );
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "result": [{"lint": {"rules": {"unknown-function": "off"}}}],
+        }))
+        .unwrap();
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "vim",
+                    "version": 1,
+                    "text": "call s:Missing()\n",
+                },
+            },
+        }))
+        .unwrap();
+    assert_eq!(client.recv().unwrap()["params"]["diagnostics"], json!([]));
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "method": "workspace/didChangeConfiguration",
+            "params": {"settings": {}},
+        }))
+        .unwrap();
+    let request = client.recv().unwrap();
+    assert_eq!(request["method"], json!("workspace/configuration"));
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "result": [null],
+        }))
+        .unwrap();
+    let diagnostics = client.recv().unwrap();
+    assert_eq!(
+        diagnostics["params"]["diagnostics"][0]["code"],
+        json!("unknown-function")
+    );
+    exit(&client);
+
+    t.join().unwrap();
+}
+
+#[test]
+fn indexes_workspace_after_initialized() {
+    let root = std::env::temp_dir().join(format!("vimscript-workspace-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("plugin")).unwrap();
+    std::fs::write(
+        root.join("plugin/format.vim"),
+        "function! FormatBuffer()\nendfunction\n",
+    )
+    .unwrap();
+    let (client, server) = create_client_and_server();
+    let t = std::thread::spawn(move || run(server));
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": "initialize",
+            "params": {
+                "processId": serde_json::Value::Null,
+                "rootUri": lsp_types::Url::from_file_path(&root).unwrap(),
+                "capabilities": {"window": {"workDoneProgress": true}},
+            },
+        }))
+        .unwrap();
+    client.recv().unwrap();
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "method": "initialized",
+            "params": {},
+        }))
+        .unwrap();
+    let request = client.recv().unwrap();
+    assert_eq!(request["method"], json!("window/workDoneProgress/create"));
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "result": null,
+        }))
+        .unwrap();
+    let begin = client.recv().unwrap();
+    assert_eq!(begin["params"]["token"], request["params"]["token"]);
+    assert_eq!(begin["params"]["value"]["kind"], json!("begin"));
+    let end = client.recv().unwrap();
+    assert_eq!(end["params"]["value"]["kind"], json!("end"));
+    let _ = std::fs::remove_dir_all(&root);
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "workspace/symbol",
+            "params": {"query": "FormatBuffer"},
+        }))
+        .unwrap();
+    assert_eq!(
+        client.recv().unwrap()["result"][0]["name"],
+        json!("FormatBuffer")
+    );
+    exit(&client);
+
+    t.join().unwrap();
+}
+
+#[test]
+fn reuses_workspace_diagnostics_until_files_change() {
+    let root = std::env::temp_dir().join(format!("vimscript-reports-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("plugin")).unwrap();
+    let path = root.join("plugin/check.vim");
+    std::fs::write(&path, "call s:Missing()\n").unwrap();
+    let uri = lsp_types::Url::from_file_path(&path).unwrap();
+    let (client, server) = create_client_and_server();
+    let t = std::thread::spawn(move || run(server));
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": "initialize",
+            "params": {
+                "processId": serde_json::Value::Null,
+                "rootUri": lsp_types::Url::from_file_path(&root).unwrap(),
+                "capabilities": {"textDocument": {"diagnostic": {}}},
+            },
+        }))
+        .unwrap();
+    client.recv().unwrap();
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "method": "initialized",
+            "params": {},
+        }))
+        .unwrap();
+    // The workspace is indexed in the background.
+    let mut id = 0;
+    let pull = |id: &mut i32, previous: serde_json::Value| {
+        *id += 1;
+        client
+            .send(json!({
+                "jsonrpc": "2.0",
+                "id": *id,
+                "method": "workspace/diagnostic",
+                "params": {"previousResultIds": previous},
+            }))
+            .unwrap();
+        client.recv().unwrap()["result"]["items"].clone()
+    };
+    let mut items = pull(&mut id, json!([]));
+    for _ in 0..100 {
+        if items != json!([]) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        items = pull(&mut id, json!([]));
+    }
+    assert_eq!(items[0]["kind"], json!("full"));
+    assert_eq!(items[0]["items"][0]["code"], json!("unknown-function"));
+    let result_id = items[0]["resultId"].clone();
+
+    // Changes that the client didn't notify about are not seen, as the file isn't read again.
+    std::fs::write(&path, "let s:x = 1\n").unwrap();
+    let previous = json!([{"uri": uri, "value": result_id}]);
+    let items = pull(&mut id, previous.clone());
+    assert_eq!(items[0]["kind"], json!("unchanged"));
+
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "method": "workspace/didChangeWatchedFiles",
+            "params": {"changes": [{"uri": uri, "type": 2}]},
+        }))
+        .unwrap();
+    // Calls in the file are indexed again in the background.
+    let mut items = pull(&mut id, previous.clone());
+    for _ in 0..100 {
+        if items[0]["items"] == json!([]) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        items = pull(&mut id, previous.clone());
+    }
+    let _ = std::fs::remove_dir_all(&root);
+    assert_eq!(items[0]["kind"], json!("full"));
+    assert_eq!(items[0]["items"], json!([]));
+    exit(&client);
+
+    t.join().unwrap();
+}
+
+// Deterministic pseudo-random numbers (xorshift), so that failures can be reproduced.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.next(items.len())]
+    }
+}
+
+const FUZZ_REQUESTS: &[&str] = &[
+    "textDocument/rename",
+    "textDocument/documentHighlight",
+    "textDocument/hover",
+    "textDocument/signatureHelp",
+    "textDocument/documentSymbol",
+    "workspace/symbol",
+    "textDocument/foldingRange",
+    "textDocument/selectionRange",
+    "textDocument/codeAction",
+    "textDocument/prepareCallHierarchy",
+    "callHierarchy/incomingCalls",
+    "callHierarchy/outgoingCalls",
+    "textDocument/inlayHint",
+    "textDocument/codeLens",
+    "textDocument/implementation",
+    "textDocument/typeDefinition",
+    "textDocument/documentLink",
+    "workspace/executeCommand",
+    "textDocument/semanticTokens/full",
+    "textDocument/semanticTokens/full/delta",
+    "textDocument/semanticTokens/range",
+    "textDocument/diagnostic",
+    "workspace/diagnostic",
+    "initialize",
+    "unknown/method",
+];
+
+const FUZZ_NOTIFICATIONS: &[&str] = &[
+    "textDocument/didOpen",
+    "textDocument/didChange",
+    "textDocument/didSave",
+    "textDocument/didClose",
+    "workspace/didChangeWatchedFiles",
+    "workspace/didChangeConfiguration",
+    "initialized",
+    "$/cancelRequest",
+    "unknown/notification",
+];
+
+const FUZZ_SNIPPETS: &[&str] = &[
+    "function! s:F(a, b) abort\n",
+    "function! d.m() dict\n",
+    "endfunction\n",
+    "let x = [1, 2\n",
+    "let l:y = {'a': function('s:F')}\n",
+    "call s:F(x, 'ą😀')\n",
+    "if exists('g:x') | echo 1 | endif\n",
+    "for [a, b] in items(d)\n",
+    "endfor\n",
+    "\" :h tag |other-tag|\n",
+    "source <sfile>:h/other.vim\n",
+    "au BufRead * call <SID>F()\n",
+    "nnoremap <silent> x :call plugin#f()<CR>\n",
+    "let s = a . b.c\n",
+    "execute 'normal!' x\n",
+    "(\n",
+    "\\ ]\n",
+    "while\n",
+];
+
+fn fuzz_params(random: &mut Random) -> serde_json::Value {
+    let uris = ["file:///a.vim", "file:///b.vim", "file:///doc/a.txt"];
+    let mut text = String::new();
+    for _ in 0..random.next(8) {
+        text.push_str(*random.choose(FUZZ_SNIPPETS));
+    }
+    let position = json!({"line": random.next(6), "character": random.next(12)});
+    let uri = *random.choose(&uris);
+    match random.next(10) {
+        0 => json!(null),
+        1 => json!([1, "a"]),
+        2 => json!({"textDocument": uri}),
+        _ => json!({
+            "textDocument": {"uri": uri, "languageId": "vim", "version": 1, "text": text},
+            "contentChanges": [{"text": text}],
+            "changes": [{"uri": uri, "type": 1 + random.next(3)}],
+            "position": position,
+            "positions": [position],
+            "range": {"start": position, "end": position},
+            "context": {"diagnostics": [], "triggerKind": 1},
+            "newName": "renamed",
+            "query": "f",
+            "previousResultId": random.next(4).to_string(),
+            "previousResultIds": [{"uri": uri, "value": random.next(4).to_string()}],
+            "settings": {"vimscript": {"lint": {"rules": {"missing-abort": "off"}}}},
+            "command": "vimscript.runTest",
+            "arguments": [uri, "Test_x"],
+            "id": random.next(100),
+            "capabilities": {},
+        }),
+    }
+}
+
+#[test]
+fn survives_random_messages() {
+    for seed in 1..30 {
+        let (client, server) = create_client_and_server();
+        let t = std::thread::spawn(move || run(server));
+        let mut random = Random(seed);
+
+        initialize(&client);
+        for id in 1..60 {
+            match random.next(10) {
+                0 => client
+                    .sender
+                    .send(
+                        random
+                            .choose(&["{", "[]", "null", "{\"id\": {}}"])
+                            .to_string(),
+                    )
+                    .unwrap(),
+                1..=3 => client
+                    .send(json!({
+                        "jsonrpc": "2.0",
+                        "method": random.choose(FUZZ_NOTIFICATIONS),
+                        "params": fuzz_params(&mut random),
+                    }))
+                    .unwrap(),
+                _ => client
+                    .send(json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "method": random.choose(FUZZ_REQUESTS),
+                        "params": fuzz_params(&mut random),
+                    }))
+                    .unwrap(),
+            }
+        }
+        client
+            .send(json!({
+                "jsonrpc": "2.0",
+                "id": "shutdown",
+                "method": "shutdown",
+            }))
+            .unwrap();
+        exit(&client);
+
+        assert_eq!(t.join().unwrap(), 0, "seed {}", seed);
+        // Responses of workers can arrive in any order. Panics are caught, but are reported.
+        let messages: Vec<serde_json::Value> = client
+            .receiver
+            .try_iter()
+            .map(|message| message.parse().unwrap())
+            .collect();
+        for message in &messages {
+            let text = message["params"]["message"].as_str().unwrap_or("");
+            assert!(
+                !text.starts_with("Panic while handling"),
+                "seed {}: {}",
+                seed,
+                text
+            );
+        }
+        assert!(
+            messages
+                .iter()
+                .any(|message| message["id"] == json!("shutdown") && message["result"].is_null()),
+            "seed {}",
+            seed
+        );
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::AugroupStatement;
+use crate::lexer::TokenType;
+use crate::parser::Parser;
+
+// Precondition - `augroup` token was already read.
+//
+// Augroup ::= 'augroup' '!'? GroupName NewLine Stmt* ('augroup' 'END' NewLine)?
+//
+// Like in Vim, the group lasts until the next group is started, `augroup END` or the end of file.
+pub fn parse(parser: &mut Parser) -> Option<AugroupStatement> {
+    let mut delete = false;
+    if parser.peek_token().token_type == TokenType::Bang {
+        parser.advance();
+        delete = true;
+    }
+    let token = parser.peek_token();
+    let words = parser.consume_words();
+    if words.is_empty() {
+        parser.error_and_recover("group name", token);
+        return None;
+    }
+    parser.expect_end_of_statement()?;
+
+    let mut group = AugroupStatement {
+        name: parser.l.token_text(&words[0]).to_string(),
+        name_location: words[0].clone(),
+        delete: delete,
+        body: Vec::new(),
+    };
+    if group.delete || group.is_end() {
+        return Some(group);
+    }
+    loop {
+        let token = parser.peek_token();
+        if token.token_type == TokenType::Eof {
+            return Some(group);
+        }
+        let next = parser.peek_nth_token(1);
+        let is_augroup = token.token_type == TokenType::Ident
+            && matches!(parser.identifier_name(&token).as_str(), "augroup" | "aug");
+        // `augroup! name` deletes a group, but doesn't change the current one.
+        if is_augroup && next.token_type != TokenType::Bang {
+            if parser
+                .l
+                .token_text(&next.location)
+                .eq_ignore_ascii_case("END")
+            {
+                parser.parse_statement();
+            }
+            return Some(group);
+        }
+        if let Some(stmt) = parser.parse_statement() {
+            group.body.push(stmt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn parses_augroup_with_autocmds() {
+        let mut parser = Parser::new(Lexer::new(
+            "augroup my-plugin
+              autocmd!
+              autocmd BufWritePre *.vim call s:Format()
+            augroup END",
+        ));
+        let program = parser.parse();
+        assert_eq!(parser.errors, &[]);
+        assert_eq!(
+            program.dump_for_testing(),
+            json!([{
+                "augroup": {
+                    "name": "my-plugin",
+                    "delete": false,
+                    "body": [
+                        {"autocmd": {
+                            "clear": true,
+                            "group": null,
+                            "events": [],
+                            "pattern": null,
+                            "command": null,
+                        }},
+                        {"autocmd": {
+                            "clear": false,
+                            "group": null,
+                            "events": ["BufWritePre"],
+                            "pattern": "*.vim",
+                            "command": "call s:Format()",
+                        }},
+                    ],
+                }
+            }])
+        );
+    }
+
+    #[test]
+    fn ends_augroup_at_next_group_or_end_of_file() {
+        let mut parser = Parser::new(Lexer::new(
+            "augroup first\nautocmd FileType vim setlocal\naugroup second\nautocmd!",
+        ));
+        let program = parser.parse();
+        assert_eq!(parser.errors, &[]);
+        assert_eq!(
+            program.dump_for_testing(),
+            json!([
+                {"augroup": {
+                    "name": "first",
+                    "delete": false,
+                    "body": [
+                        {"autocmd": {
+                            "clear": false,
+                            "group": null,
+                            "events": ["FileType"],
+                            "pattern": "vim",
+                            "command": "setlocal",
+                        }},
+                    ],
+                }},
+                {"augroup": {
+                    "name": "second",
+                    "delete": false,
+                    "body": [
+                        {"autocmd": {
+                            "clear": true,
+                            "group": null,
+                            "events": [],
+                            "pattern": null,
+                            "command": null,
+                        }},
+                    ],
+                }},
+            ])
+        );
+    }
+
+    #[test]
+    fn keeps_deleted_groups_in_body() {
+        let mut parser = Parser::new(Lexer::new("augroup test\naugroup! old\naugroup END"));
+        let program = parser.parse();
+        assert_eq!(parser.errors, &[]);
+        assert_eq!(
+            program.dump_for_testing(),
+            json!([{
+                "augroup": {
+                    "name": "test",
+                    "delete": false,
+                    "body": [
+                        {"augroup": {"name": "old", "delete": true, "body": []}},
+                    ],
+                }
+            }])
+        );
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::AutocmdStatement;
+use crate::builtins;
+use crate::lexer::TokenType;
+use crate::parser::Parser;
+
+// Precondition - `autocmd` token was already read.
+//
+// Autocmd ::= 'autocmd' '!'? GroupName? Events? Pattern? Command? NewLine
+pub fn parse(parser: &mut Parser) -> Option<AutocmdStatement> {
+    let mut clear = false;
+    if parser.peek_token().token_type == TokenType::Bang {
+        parser.advance();
+        clear = true;
+    }
+    let words = parser.consume_words();
+    parser.expect_end_of_statement()?;
+
+    let mut words = words.iter();
+    let mut group = None;
+    let mut events = Vec::new();
+    if let Some(word) = words.next() {
+        let text = parser.l.token_text(word);
+        // The group name is optional, events are recognized by their names.
+        let event_list = if text.split(',').all(builtins::is_autocmd_event) {
+            Some(text)
+        } else {
+            group = Some(text.to_string());
+            words.next().map(|w| parser.l.token_text(w))
+        };
+        if let Some(event_list) = event_list {
+            events = event_list.split(',').map(|e| e.to_string()).collect();
+        }
+    }
+    let pattern = words.next().map(|w| parser.l.token_text(w).to_string());
+    let rest: Vec<_> = words.cloned().collect();
+    let command = if rest.is_empty() {
+        None
+    } else {
+        Some(parser.words_text(&rest))
+    };
+
+    return Some(AutocmdStatement {
+        clear: clear,
+        group: group,
+        events: events,
+        pattern: pattern,
+        command: command,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn parses_autocmd_with_group_and_multiple_events() {
+        let mut parser = Parser::new(Lexer::new(
+            "autocmd MyGroup BufRead,BufNewFile *.vim ++once setlocal sw=2",
+        ));
+        let program = parser.parse();
+        assert_eq!(parser.errors, &[]);
+        assert_eq!(
+            program.dump_for_testing(),
+            json!([{"autocmd": {
+                "clear": false,
+                "group": "MyGroup",
+                "events": ["BufRead", "BufNewFile"],
+                "pattern": "*.vim",
+                "command": "++once setlocal sw=2",
+            }}])
+        );
+    }
+
+    #[test]
+    fn parses_autocmd_clearing_group() {
+        let mut parser = Parser::new(Lexer::new("au! MyGroup"));
+        let program = parser.parse();
+        assert_eq!(parser.errors, &[]);
+        assert_eq!(
+            program.dump_for_testing(),
+            json!([{"autocmd": {
+                "clear": true,
+                "group": "MyGroup",
+                "events": [],
+                "pattern": null,
+                "command": null,
+            }}])
+        );
+    }
+}
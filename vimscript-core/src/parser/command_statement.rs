@@ -0,0 +1,89 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::CommandStatement;
+use crate::lexer::TokenType;
+use crate::parser::Parser;
+
+// Precondition - `command` token was already read.
+//
+// Command ::= 'command' '!'? Attribute* CommandName Definition NewLine
+pub fn parse(parser: &mut Parser) -> Option<CommandStatement> {
+    let mut overwrite = false;
+    if parser.peek_token().token_type == TokenType::Bang {
+        parser.advance();
+        overwrite = true;
+    }
+    let words = parser.consume_words();
+    let token = parser.peek_token();
+    let attributes: Vec<String> = words
+        .iter()
+        .map(|w| parser.l.token_text(w))
+        .take_while(|w| w.starts_with('-'))
+        .map(|w| w.to_string())
+        .collect();
+    let name_location = match words.get(attributes.len()) {
+        Some(location) => location.clone(),
+        None => {
+            parser.error_and_recover("command name", token);
+            return None;
+        }
+    };
+    let definition = parser.words_text(&words[attributes.len() + 1..]);
+    parser.expect_end_of_statement()?;
+
+    return Some(CommandStatement {
+        name: parser.l.token_text(&name_location).to_string(),
+        name_location: name_location,
+        overwrite: overwrite,
+        attributes: attributes,
+        definition: definition,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn parses_command_statement() {
+        let mut parser = Parser::new(Lexer::new(
+            "command! -nargs=1 -bang Grep call s:Grep(<q-args>, <bang>0)",
+        ));
+        let program = parser.parse();
+        assert_eq!(parser.errors, &[]);
+        assert_eq!(
+            program.dump_for_testing(),
+            json!([{"command": {
+                "name": "Grep",
+                "overwrite": true,
+                "attributes": ["-nargs=1", "-bang"],
+                "definition": "call s:Grep(<q-args>, <bang>0)",
+            }}])
+        );
+    }
+
+    #[test]
+    fn returns_error_for_missing_command_name() {
+        let mut parser = Parser::new(Lexer::new("command -nargs=0\n"));
+        parser.parse();
+        assert_eq!(
+            parser.errors[0].message,
+            "expected command name, found new line"
+        );
+    }
+}
@@ -32,7 +32,7 @@ pub fn parse(parser: &mut Parser) -> Option<Expr> {
             return Some(Expr {
                 id: parser.next_id(),
                 span: Span {
-                    start: lhs.span.start,
+                    start: left.span.start,
                     end: rhs.span.end,
                 },
                 kind: ExprKind::Choose(ChooseExpression {
@@ -97,7 +97,12 @@ fn is_operator(token_type: TokenType) -> bool {
 fn parse_ident_expression(parser: &mut Parser) -> Option<Expr> {
     let name_location = parser.peek_token().location;
     let start = BytePos(name_location.range.start.try_into().unwrap());
-    let name = parser.expect_identifier()?;
+    let name = if parser.peek_token().token_type == TokenType::Function {
+        parser.advance();
+        parser.l.token_text(&name_location).to_string()
+    } else {
+        parser.expect_identifier()?
+    };
     let mut left = Expr {
         id: parser.next_id(),
         span: Span {
@@ -113,8 +118,7 @@ fn parse_ident_expression(parser: &mut Parser) -> Option<Expr> {
         match parser.peek_token().token_type {
             TokenType::LeftParenthesis => {
                 parser.advance();
-                let arguments =
-                    parser.parse_list(|p| p.parse_expression(), TokenType::RightParenthesis)?;
+                let arguments = parser.parse_call_arguments()?;
                 left = Expr {
                     id: parser.next_id(),
                     span: Span {
@@ -127,6 +131,25 @@ fn parse_ident_expression(parser: &mut Parser) -> Option<Expr> {
                     }),
                 };
             }
+            // `a.b` without spaces is a dictionary key, unless `b` has a scope, e.g. `a.s:b`,
+            // which is a concatenation.
+            TokenType::Dot if is_member_access(parser, &left) => {
+                parser.advance();
+                let name_location = parser.peek_token().location;
+                let name = parser.expect_identifier()?;
+                left = Expr {
+                    id: parser.next_id(),
+                    span: Span {
+                        start: start,
+                        end: parser.last_pos,
+                    },
+                    kind: ExprKind::Member(MemberExpression {
+                        base: Box::new(left),
+                        name: name,
+                        name_location: name_location,
+                    }),
+                };
+            }
             TokenType::LeftBracket => {
                 parser.advance();
                 let idx = parse_array_subscript(parser)?;
@@ -148,6 +171,16 @@ fn parse_ident_expression(parser: &mut Parser) -> Option<Expr> {
     }
 }
 
+// Returns true if the next token is a dot that accesses a key of the expression.
+fn is_member_access(parser: &mut Parser, base: &Expr) -> bool {
+    let dot = parser.peek_token();
+    let key = parser.peek_nth_token(1);
+    return dot.location.range.start == base.span.end.0 as usize
+        && key.token_type == TokenType::Ident
+        && key.location.range.start == dot.location.range.end
+        && !parser.l.token_text(&key.location).contains(':');
+}
+
 fn parse_array_subscript(parser: &mut Parser) -> Option<ArraySubscript> {
     let mut left = None;
     if parser.peek_token().token_type != TokenType::Colon {
@@ -213,7 +246,8 @@ fn parse_prefix_expression(parser: &mut Parser) -> Option<Expr> {
                 }),
             });
         }
-        TokenType::Ident => return parse_ident_expression(parser),
+        // `function` is also the builtin that creates a funcref, e.g. `function('s:Foo')`.
+        TokenType::Ident | TokenType::Function => return parse_ident_expression(parser),
         TokenType::LeftCurlyBrace => {
             parser.advance();
             let entries =
@@ -245,6 +279,7 @@ fn parse_prefix_expression(parser: &mut Parser) -> Option<Expr> {
         }
         TokenType::Minus | TokenType::Bang => {
             parser.advance();
+            let expr = parse_prefix_expression(parser)?;
             return Some(Expr {
                 id: parser.next_id(),
                 span: Span {
@@ -253,7 +288,7 @@ fn parse_prefix_expression(parser: &mut Parser) -> Option<Expr> {
                 },
                 kind: ExprKind::Unary(UnaryExpression {
                     operator: token.token_type,
-                    expr: Box::new(parse_prefix_expression(parser)?),
+                    expr: Box::new(expr),
                 }),
             });
         }
@@ -365,6 +400,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn recovers_from_unterminated_function_call() {
+        let mut parser = Parser::new(Lexer::new("outer(a, inner(b, \n"));
+        let expression = parse(&mut parser);
+        assert_eq!(parser.errors.len(), 1);
+        assert_eq!(parser.errors[0].message, "expected `)`, found new line");
+        assert_eq!(
+            expression.unwrap().dump_for_testing(),
+            json!({
+                "function": {
+                    "callee": { "identifier": "outer" },
+                    "arguments": [
+                        {"identifier": "a"},
+                        {
+                            "function": {
+                                "callee": { "identifier": "inner" },
+                                "arguments": [{"identifier": "b"}],
+                            },
+                        },
+                    ],
+                },
+            })
+        );
+    }
+
     #[test]
     fn parses_function_with_two_arguments_expression() {
         let mut parser = Parser::new(Lexer::new("myfunc(arg1, arg2)"));
@@ -684,6 +744,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn spans_cover_whole_choose_and_unary_expressions() {
+        let source = "!a ? -b : c";
+        let mut parser = Parser::new(Lexer::new(source));
+        let expression = parse(&mut parser).unwrap();
+        assert_eq!(parser.errors, &[]);
+        let text = |span: &Span| &source[span.start.0 as usize..span.end.0 as usize];
+        assert_eq!(text(&expression.span), "!a ? -b : c");
+        match &expression.kind {
+            ExprKind::Choose(choose) => {
+                assert_eq!(text(&choose.cond.span), "!a");
+                assert_eq!(text(&choose.lhs.span), "-b");
+            }
+            kind => panic!("expected choose expression, got {:?}", kind),
+        }
+    }
+
     #[test]
     fn parses_empty_dictionary() {
         assert_eq!(
@@ -708,4 +785,34 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn parses_method_call() {
+        assert_eq!(
+            parse_and_dump("self.items.add(1)"),
+            json!({
+                "function": {
+                    "callee": {
+                        "member": {
+                            "base": {
+                                "member": {
+                                    "base": {"identifier": "self"},
+                                    "name": "items",
+                                }
+                            },
+                            "name": "add",
+                        }
+                    },
+                    "arguments": [{"number": 1.0}],
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn parses_dot_with_spaces_or_scope_as_concatenation() {
+        for input in &["a . b", "a.s:b"] {
+            assert_eq!(parse_and_dump(input)["infix"]["operator"], json!("`.`"));
+        }
+    }
 }
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use crate::ast::ElseCond;
+use crate::ast::Expr;
 use crate::ast::IfStatement;
 use crate::lexer::TokenType;
 use crate::parser::Parser;
@@ -25,8 +26,12 @@ pub fn parse(parser: &mut Parser) -> Option<IfStatement> {
 
     parser.expect_end_of_statement()?;
 
+    parser.parse_block(TokenType::EndIf, |parser| parse_branches(parser, condition))
+}
+
+fn parse_branches(parser: &mut Parser, condition: Expr) -> Option<IfStatement> {
     let mut stmts = Vec::new();
-    while parser.peek_token().token_type != TokenType::Eof {
+    loop {
         if parser.peek_token().token_type == TokenType::EndIf {
             parser.advance();
             parser.expect_end_of_statement()?;
@@ -54,17 +59,22 @@ pub fn parse(parser: &mut Parser) -> Option<IfStatement> {
                 else_cond: ElseCond::ElseIf(Box::new(parse(parser)?)),
             });
         }
+        if parser.end_of_block() {
+            break;
+        }
 
         if let Some(stmt) = parser.parse_statement() {
             stmts.push(stmt);
         }
     }
+    parser.error_missing_end(TokenType::EndIf);
     return None;
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::StmtKind;
     use crate::lexer::Lexer;
     use pretty_assertions::assert_eq;
     use serde_json::json;
@@ -198,4 +208,26 @@ mod tests {
             }])
         );
     }
+
+    #[test]
+    fn ends_unterminated_if_at_end_of_enclosing_block() {
+        let mut parser = Parser::new(Lexer::new(
+            "function! s:F()
+  if 1
+    call s:G()
+endfunction
+call s:F()",
+        ));
+        let program = parser.parse();
+        assert_eq!(parser.errors.len(), 1);
+        assert_eq!(
+            parser.errors[0].message,
+            "expected `endif`, found `endfunction`"
+        );
+        assert_eq!(parser.errors[0].position.start.line, 3);
+        // The function and the call after it are still parsed.
+        assert_eq!(program.statements.len(), 2);
+        assert!(matches!(program.statements[0].kind, StmtKind::Function(_)));
+        assert!(matches!(program.statements[1].kind, StmtKind::Call(_)));
+    }
 }
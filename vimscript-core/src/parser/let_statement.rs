@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use crate::ast::LetStatement;
+use crate::diagnostics::DiagnosticCode;
 use crate::lexer::TokenType;
 use crate::parser::ParseError;
 use crate::parser::Parser;
@@ -30,8 +31,8 @@ fn is_assign_operator(token_type: TokenType) -> bool {
     }
 }
 
-// Let = 'let' VarName = Expression (NewLine | EOF)
-pub fn parse(parser: &mut Parser) -> Option<LetStatement> {
+// Let = ('let' | 'const') VarName = Expression (NewLine | EOF)
+pub fn parse(parser: &mut Parser, constant: bool) -> Option<LetStatement> {
     // TODO: This is not really correct, as only some expressions like ident, array subscript and
     // array are supported here.
     let var = parser.parse_expression()?;
@@ -44,6 +45,7 @@ pub fn parse(parser: &mut Parser) -> Option<LetStatement> {
                 parser.token_text(&operator)
             ),
             position: parser.l.token_position(&operator.location),
+            code: DiagnosticCode::SyntaxError,
         });
         parser.consume_until_end_of_statement();
         // TODO: error
@@ -59,6 +61,7 @@ pub fn parse(parser: &mut Parser) -> Option<LetStatement> {
         var: Box::new(var),
         operator: operator.token_type,
         value: Box::new(expr),
+        constant: constant,
     });
 }
 
@@ -93,6 +96,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parses_const_statement() {
+        let mut parser = Parser::new(Lexer::new("const s:limit = 15"));
+        let program = parser.parse();
+        assert_eq!(parser.errors, &[]);
+        assert_eq!(
+            program.dump_for_testing(),
+            json!([{
+                "const": {
+                    "var": {"identifier": "s:limit"},
+                    "operator": "`=`",
+                    "value": {
+                        "number": 15.0,
+                    },
+                },
+            }])
+        );
+    }
+
     #[test]
     fn parses_let_statement_with_different_operators() {
         let mut parser = Parser::new(Lexer::new("let l:var += 15"));
@@ -151,7 +173,8 @@ mod tests {
                         line: 0,
                         character: 11,
                     },
-                }
+                },
+                code: DiagnosticCode::SyntaxError,
             }]
         );
     }
@@ -173,7 +196,8 @@ mod tests {
                         line: 1,
                         character: 0,
                     },
-                }
+                },
+                code: DiagnosticCode::SyntaxError,
             }]
         );
         assert_eq!(program.statements.len(), 1);
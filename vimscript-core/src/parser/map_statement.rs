@@ -0,0 +1,105 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::MapStatement;
+use crate::lexer::TokenType;
+use crate::parser::Parser;
+
+const MAP_COMMANDS: &[&str] = &[
+    "map", "nmap", "vmap", "xmap", "smap", "omap", "imap", "lmap", "cmap", "tmap", "noremap",
+    "nnoremap", "vnoremap", "xnoremap", "snoremap", "onoremap", "inoremap", "lnoremap", "cnoremap",
+    "tnoremap",
+];
+
+const MAP_ARGUMENTS: &[&str] = &[
+    "<buffer>",
+    "<nowait>",
+    "<silent>",
+    "<special>",
+    "<script>",
+    "<expr>",
+    "<unique>",
+];
+
+pub fn is_map_command(name: &str) -> bool {
+    return MAP_COMMANDS.contains(&name);
+}
+
+// Precondition - map command token was already read.
+//
+// Map ::= MapCommand '!'? MapArgument* Lhs Rhs? NewLine
+pub fn parse(parser: &mut Parser, mut command: String) -> Option<MapStatement> {
+    if parser.peek_token().token_type == TokenType::Bang {
+        parser.advance();
+        command.push('!');
+    }
+    let words = parser.consume_words();
+    let token = parser.peek_token();
+    let arguments: Vec<String> = words
+        .iter()
+        .map(|w| parser.l.token_text(w))
+        .take_while(|w| MAP_ARGUMENTS.iter().any(|a| a.eq_ignore_ascii_case(w)))
+        .map(|w| w.to_string())
+        .collect();
+    let lhs_location = match words.get(arguments.len()) {
+        Some(location) => location.clone(),
+        None => {
+            parser.error_and_recover("mapping", token);
+            return None;
+        }
+    };
+    let rhs = parser.words_text(&words[arguments.len() + 1..]);
+    parser.expect_end_of_statement()?;
+
+    return Some(MapStatement {
+        command: command,
+        arguments: arguments,
+        lhs: parser.l.token_text(&lhs_location).to_string(),
+        lhs_location: lhs_location,
+        rhs: rhs,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn parses_map_statement() {
+        let mut parser = Parser::new(Lexer::new(
+            "nnoremap <silent> <buffer> <leader>f :call <SID>Format()<CR>",
+        ));
+        let program = parser.parse();
+        assert_eq!(parser.errors, &[]);
+        assert_eq!(
+            program.dump_for_testing(),
+            json!([{"map": {
+                "command": "nnoremap",
+                "arguments": ["<silent>", "<buffer>"],
+                "lhs": "<leader>f",
+                "rhs": ":call <SID>Format()<CR>",
+            }}])
+        );
+    }
+
+    #[test]
+    fn does_not_parse_map_function_as_statement() {
+        let mut parser = Parser::new(Lexer::new("let l = map(l, 'v:val')"));
+        parser.parse();
+        assert_eq!(parser.errors, &[]);
+    }
+}
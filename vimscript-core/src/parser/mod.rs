@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use crate::ast::*;
+use crate::diagnostics::DiagnosticCode;
 use crate::lexer::Lexer;
 use crate::lexer::SourceLocation;
 use crate::lexer::SourcePosition;
@@ -26,9 +27,13 @@ use std::convert::TryInto;
 use std::iter::Iterator;
 use std::iter::Peekable;
 
+mod augroup_statement;
+mod autocmd_statement;
+mod command_statement;
 mod expression;
 mod if_statement;
 mod let_statement;
+mod map_statement;
 mod return_statement;
 mod set_statement;
 mod try_statement;
@@ -53,6 +58,7 @@ impl Program {
 pub struct ParseError {
     pub message: String,
     pub position: TokenPosition,
+    pub code: DiagnosticCode,
 }
 
 pub struct Parser<'a> {
@@ -62,6 +68,8 @@ pub struct Parser<'a> {
     pub last_pos: BytePos,
     pub errors: Vec<ParseError>,
     id: NodeId,
+    // End keywords of the blocks being parsed, innermost last.
+    blocks: Vec<TokenType>,
 }
 
 impl<'a> Parser<'a> {
@@ -74,6 +82,7 @@ impl<'a> Parser<'a> {
             last_pos: BytePos(0),
             errors: Vec::new(),
             id: NodeId(0),
+            blocks: Vec::new(),
         };
     }
 
@@ -93,6 +102,16 @@ impl<'a> Parser<'a> {
         self.l.token_position(&loc)
     }
 
+    pub fn resolve_span(&self, span: &Span) -> TokenPosition {
+        self.resolve_location(SourceLocation {
+            range: span.start.0 as usize..span.end.0 as usize,
+        })
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
     pub fn find_token(&self, pos: SourcePosition) -> Result<Token, ()> {
         // TODO: This is very naive implementation, we can do a lot of optimizations here.
         for token in &self.tokens {
@@ -115,8 +134,8 @@ impl<'a> Parser<'a> {
         let token = self.lexer.next()?;
         let start = BytePos(token.location.range.start.try_into().unwrap());
         match token.token_type {
-            TokenType::Let => {
-                if let Some(stmt) = self.parse_let_statement() {
+            TokenType::Let | TokenType::Const => {
+                if let Some(stmt) = self.parse_let_statement(token.token_type == TokenType::Const) {
                     return Some(Stmt {
                         id: self.next_id(),
                         span: Span {
@@ -269,22 +288,52 @@ impl<'a> Parser<'a> {
                 })
             }
             TokenType::Pipe => {}
-            _ => {
-                self.errors.push(ParseError {
-                    message: format!("expected keyword, found {}", self.token_text(&token)),
-                    position: self.l.token_position(&token.location),
-                });
-                self.consume_until_end_of_statement();
+            // Ex commands that are not keywords, because their names can be used as variables or
+            // functions too (e.g. `map()`).
+            TokenType::Ident => {
+                let name = self.identifier_name(&token);
+                let kind = match name.as_str() {
+                    "augroup" | "aug" => augroup_statement::parse(self).map(StmtKind::Augroup),
+                    "autocmd" | "au" => autocmd_statement::parse(self).map(StmtKind::Autocmd),
+                    "command" | "com" => command_statement::parse(self).map(StmtKind::Command),
+                    name if map_statement::is_map_command(name) => {
+                        map_statement::parse(self, name.to_string()).map(StmtKind::Map)
+                    }
+                    _ => {
+                        self.error_expected_keyword(&token);
+                        return None;
+                    }
+                };
+                if let Some(kind) = kind {
+                    return Some(Stmt {
+                        id: self.next_id(),
+                        span: Span {
+                            start: start,
+                            end: self.last_pos,
+                        },
+                        kind: kind,
+                    });
+                }
             }
+            _ => self.error_expected_keyword(&token),
         }
         return None;
     }
 
+    fn error_expected_keyword(&mut self, token: &Token) {
+        self.errors.push(ParseError {
+            message: format!("expected keyword, found {}", self.token_text(token)),
+            position: self.l.token_position(&token.location),
+            code: DiagnosticCode::SyntaxError,
+        });
+        self.consume_until_end_of_statement();
+    }
+
     fn parse_call_statement(&mut self) -> Option<CallStatement> {
-        let name = self.expect_identifier()?;
+        let (name, _) = self.expect_dotted_identifier()?;
 
         self.expect_token(TokenType::LeftParenthesis)?;
-        let arguments = self.parse_list(|p| p.parse_expression(), TokenType::RightParenthesis)?;
+        let arguments = self.parse_call_arguments()?;
         self.expect_end_of_statement()?;
 
         return Some(CallStatement {
@@ -308,9 +357,9 @@ impl<'a> Parser<'a> {
         })
     }
 
-    // Let = 'let' VarName = ExprKind (NewLine | EOF)
-    fn parse_let_statement(&mut self) -> Option<LetStatement> {
-        return let_statement::parse(self);
+    // Let = ('let' | 'const') VarName = ExprKind (NewLine | EOF)
+    fn parse_let_statement(&mut self, constant: bool) -> Option<LetStatement> {
+        return let_statement::parse(self, constant);
     }
 
     fn consume_until_end_of_statement(&mut self) {
@@ -329,6 +378,7 @@ impl<'a> Parser<'a> {
     pub fn token_text(&self, token: &Token) -> String {
         match token.token_type {
             TokenType::NewLine => "new line".to_string(),
+            TokenType::Eof => "end of file".to_string(),
             _ => format!("`{}`", self.l.token_text(&token.location).to_string()),
         }
     }
@@ -375,21 +425,68 @@ impl<'a> Parser<'a> {
         return Some(LoopVariable::List(vars));
     }
 
-    // Parses statements until the next statement starts with given token or EOF is encountered.
+    // Parses statements until the next statement starts with given token, the end keyword of an
+    // enclosing block or EOF is encountered.
     fn parse_statements_until(&mut self, token_type: TokenType) -> Option<Vec<Stmt>> {
-        let mut stmts = Vec::new();
-        while self.peek_token().token_type != TokenType::Eof
-            && self.peek_token().token_type != token_type
-        {
-            // TODO: It would be nice to pass the expected token here, so that error message can
-            // include it as well.
-            if let Some(stmt) = self.parse_statement() {
-                stmts.push(stmt);
+        self.parse_block(token_type, |parser| {
+            let mut stmts = Vec::new();
+            while !parser.end_of_block() {
+                // TODO: It would be nice to pass the expected token here, so that error message
+                // can include it as well.
+                if let Some(stmt) = parser.parse_statement() {
+                    stmts.push(stmt);
+                }
             }
-        }
-        self.expect_token(token_type)?;
-        self.expect_end_of_statement()?;
-        return Some(stmts);
+            if parser.peek_token().token_type != token_type {
+                parser.error_missing_end(token_type);
+                return None;
+            }
+            parser.expect_token(token_type)?;
+            parser.expect_end_of_statement()?;
+            return Some(stmts);
+        })
+    }
+
+    // Parses a block that ends with given keyword. Blocks nested in it end at the keyword too, so
+    // that e.g. an `if` without `endif` doesn't consume the `endfunction` of its function.
+    pub fn parse_block<T>(
+        &mut self,
+        end: TokenType,
+        parse: impl FnOnce(&mut Parser<'a>) -> Option<T>,
+    ) -> Option<T> {
+        self.blocks.push(end);
+        let result = parse(self);
+        self.blocks.pop();
+        result
+    }
+
+    // Whether the next token is EOF or the end keyword of a block being parsed.
+    pub fn end_of_block(&mut self) -> bool {
+        let token_type = self.peek_token().token_type;
+        token_type == TokenType::Eof || self.blocks.contains(&token_type)
+    }
+
+    // Reports a block that is not terminated before the end of file or of its enclosing block,
+    // e.g. a function without `endfunction`.
+    pub fn error_missing_end(&mut self, token_type: TokenType) {
+        let code = match token_type {
+            TokenType::EndIf => DiagnosticCode::MissingEndif,
+            TokenType::EndFor => DiagnosticCode::MissingEndfor,
+            TokenType::EndWhile => DiagnosticCode::MissingEndwhile,
+            TokenType::EndTry => DiagnosticCode::MissingEndtry,
+            TokenType::EndFunction => DiagnosticCode::MissingEndfunction,
+            _ => DiagnosticCode::SyntaxError,
+        };
+        let found = self.peek_token();
+        self.errors.push(ParseError {
+            message: format!(
+                "expected {}, found {}",
+                token_type.as_str(),
+                self.token_text(&found)
+            ),
+            position: self.l.token_position(&found.location),
+            code: code,
+        });
     }
 
     fn parse_function_statement(&mut self) -> Option<FunctionStatement> {
@@ -401,15 +498,32 @@ impl<'a> Parser<'a> {
             overwrite = true;
         }
 
-        let name = self.expect_identifier()?;
+        let (name, name_location) = self.expect_dotted_identifier()?;
+        let name = FunctionName::parse(&name);
 
         self.expect_token(TokenType::LeftParenthesis)?;
 
-        let arguments = self.parse_list(|p| p.expect_identifier(), TokenType::RightParenthesis)?;
+        let mut arguments = Vec::new();
+        let mut variadic = false;
+        let parameters =
+            self.parse_list(|p| p.parse_function_argument(), TokenType::RightParenthesis)?;
+        for parameter in parameters {
+            match parameter {
+                Some(argument) => arguments.push(argument),
+                None => variadic = true,
+            }
+        }
 
-        if self.peek_token().token_type == TokenType::Abort {
+        // Functions defined on a dictionary are always `dict` functions.
+        let mut dict = name.is_dict_function();
+        loop {
+            let token = self.peek_token();
+            match token.token_type {
+                TokenType::Abort => abort = true,
+                TokenType::Ident if self.identifier_name(&token) == "dict" => dict = true,
+                _ => break,
+            }
             self.advance();
-            abort = true;
         }
         self.expect_end_of_statement()?;
 
@@ -417,13 +531,77 @@ impl<'a> Parser<'a> {
 
         return Some(FunctionStatement {
             name: name,
+            name_location: name_location,
             arguments: arguments,
+            variadic: variadic,
             body: body,
             abort: abort,
             overwrite: overwrite,
+            dict: dict,
         });
     }
 
+    // Returns None for `...`, which has to be the last argument.
+    //
+    // FunctionArg ::= VarName ('=' Expr)? | '...'
+    fn parse_function_argument(&mut self) -> Option<Option<FunctionArgument>> {
+        if self.peek_token().token_type == TokenType::Variadic {
+            self.advance();
+            let token = self.peek_token();
+            if token.token_type != TokenType::RightParenthesis {
+                self.error_and_recover("`)`", token);
+                return None;
+            }
+            return Some(None);
+        }
+        let name_location = self.peek_token().location;
+        let name = self.expect_identifier()?;
+        let mut default_value = None;
+        if self.peek_token().token_type == TokenType::Assign {
+            self.advance();
+            default_value = Some(self.parse_expression()?);
+        }
+        return Some(Some(FunctionArgument {
+            name: name,
+            name_location: name_location,
+            default_value: default_value,
+        }));
+    }
+
+    // Parses arguments of a function call, after the opening parenthesis was consumed.
+    //
+    // Unlike `parse_list`, this recovers when the statement ends before the closing parenthesis
+    // (usually because the call is still being typed) and returns the arguments parsed so far.
+    pub fn parse_call_arguments(&mut self) -> Option<Vec<Expr>> {
+        let mut arguments = Vec::new();
+        let mut needs_comma = false;
+        loop {
+            let token = self.peek_token();
+            match token.token_type {
+                TokenType::RightParenthesis => {
+                    self.advance();
+                    return Some(arguments);
+                }
+                t if Parser::end_of_statement_token(t) => {
+                    self.error_without_recovery("`)`", token);
+                    return Some(arguments);
+                }
+                TokenType::Comma if needs_comma => {
+                    self.advance();
+                    needs_comma = false;
+                }
+                _ if needs_comma => {
+                    self.error_and_recover("`,` or `)`", token);
+                    return None;
+                }
+                _ => {
+                    arguments.push(self.parse_expression()?);
+                    needs_comma = true;
+                }
+            }
+        }
+    }
+
     // Number ::= 0 | [1-9][0-9]*
     // StringLiteral ::= '.*'
     // ExprKind =
@@ -495,10 +673,71 @@ impl<'a> Parser<'a> {
         self.errors.push(ParseError {
             message: format!("expected {}, found {}", expected, self.token_text(&found)),
             position: self.l.token_position(&found.location),
+            code: DiagnosticCode::SyntaxError,
         });
         self.consume_until_end_of_statement();
     }
 
+    // Consumes the rest of the statement (without the new line) and returns locations of its
+    // whitespace separated words. Used for Ex commands whose arguments are not expressions, e.g.
+    // mappings.
+    pub fn consume_words(&mut self) -> Vec<SourceLocation> {
+        let start = self.peek_token().location.range.start;
+        while !Parser::end_of_statement_token(self.peek_token().token_type) {
+            self.advance();
+        }
+        let end = std::cmp::max(start, self.peek_token().location.range.start);
+        let text = self.l.token_text(&SourceLocation { range: start..end });
+
+        let mut words = Vec::new();
+        let mut word_start = None;
+        for (i, c) in text
+            .char_indices()
+            .chain(std::iter::once((text.len(), ' ')))
+        {
+            match (c.is_whitespace(), word_start) {
+                (true, Some(s)) => {
+                    words.push(SourceLocation {
+                        range: start + s..start + i,
+                    });
+                    word_start = None;
+                }
+                (false, None) => word_start = Some(i),
+                _ => {}
+            }
+        }
+        return words;
+    }
+
+    // Returns the source text from the start of the first word to the end of the last one.
+    pub fn words_text(&self, words: &[SourceLocation]) -> String {
+        match (words.first(), words.last()) {
+            (Some(first), Some(last)) => {
+                return self
+                    .l
+                    .token_text(&SourceLocation {
+                        range: first.range.start..last.range.end,
+                    })
+                    .to_string()
+            }
+            _ => return String::new(),
+        }
+    }
+
+    // Reports an error without consuming any tokens. Nested unterminated calls end at the same
+    // token, so the error is reported only once for each position.
+    fn error_without_recovery(&mut self, expected: &str, found: Token) {
+        let position = self.l.token_position(&found.location);
+        if self.errors.last().map(|e| &e.position) == Some(&position) {
+            return;
+        }
+        self.errors.push(ParseError {
+            message: format!("expected {}, found {}", expected, self.token_text(&found)),
+            position: position,
+            code: DiagnosticCode::SyntaxError,
+        });
+    }
+
     // If peek is identifier, returns name and advances.
     // Otherwise, consume until end of statement.
     fn expect_identifier(&mut self) -> Option<String> {
@@ -514,6 +753,27 @@ impl<'a> Parser<'a> {
         Some(name)
     }
 
+    // Parses an identifier that can be followed by dictionary keys, e.g. `s:formatter.run`, and
+    // returns its text and location.
+    fn expect_dotted_identifier(&mut self) -> Option<(String, SourceLocation)> {
+        let mut location = self.peek_token().location;
+        self.expect_identifier()?;
+        while self.peek_token().token_type == TokenType::Dot
+            && self.peek_token().location.range.start == location.range.end
+        {
+            let key = self.peek_nth_token(1);
+            if key.token_type != TokenType::Ident
+                || key.location.range.start != location.range.end + 1
+            {
+                break;
+            }
+            self.advance();
+            self.advance();
+            location.range.end = key.location.range.end;
+        }
+        return Some((self.l.token_text(&location).to_string(), location));
+    }
+
     pub fn identifier_name(&self, token: &Token) -> String {
         return self.l.token_text(&token.location).to_string();
     }
@@ -525,6 +785,16 @@ impl<'a> Parser<'a> {
         self.lexer.next();
     }
 
+    // Returns the token that follows the next token and n - 1 other tokens, e.g. the token after
+    // the next one for n = 1.
+    pub fn peek_nth_token(&self, n: usize) -> Token {
+        let index = self.tokens.len() - self.lexer.len() + n;
+        match self.tokens.get(index) {
+            Some(token) => token.clone(),
+            None => self.l.eof_token(),
+        }
+    }
+
     pub fn peek_token(&mut self) -> Token {
         match self.lexer.peek() {
             Some(token) => token.clone(),
@@ -556,7 +826,8 @@ mod tests {
                         line: 0,
                         character: 7,
                     },
-                }
+                },
+                code: DiagnosticCode::SyntaxError,
             }]
         );
     }
@@ -626,8 +897,27 @@ mod tests {
                     end: BytePos(90)
                 },
                 kind: StmtKind::Function(FunctionStatement {
-                    name: "my#method".to_string(),
-                    arguments: vec!["arg1".to_string(), "arg2".to_string()],
+                    name: FunctionName {
+                        scope: None,
+                        sid: false,
+                        autoload_path: vec!["my".to_string()],
+                        dict_path: vec![],
+                        name: "method".to_string(),
+                    },
+                    name_location: SourceLocation { range: 10..19 },
+                    arguments: vec![
+                        FunctionArgument {
+                            name: "arg1".to_string(),
+                            name_location: SourceLocation { range: 20..24 },
+                            default_value: None,
+                        },
+                        FunctionArgument {
+                            name: "arg2".to_string(),
+                            name_location: SourceLocation { range: 26..30 },
+                            default_value: None,
+                        }
+                    ],
+                    variadic: false,
                     body: vec![Stmt {
                         id: NodeId(1),
                         span: Span {
@@ -641,11 +931,87 @@ mod tests {
                     }],
                     overwrite: true,
                     abort: true,
+                    dict: false,
                 })
             }]
         );
     }
 
+    #[test]
+    fn parses_function_with_optional_and_variadic_arguments() {
+        let mut parser = Parser::new(Lexer::new("function F(a, b = 1, ...)\nendfunction"));
+        let program = parser.parse();
+        assert_eq!(parser.errors, &[]);
+        assert_eq!(
+            program.dump_for_testing(),
+            json!([{
+                "function": {
+                    "name": "F",
+                    "arguments": [
+                        {"name": "a"},
+                        {"name": "b", "default": {"number": 1.0}},
+                    ],
+                    "variadic": true,
+                    "body": [],
+                    "overwrite": false,
+                    "abort": false,
+                    "dict": false,
+                }
+            }])
+        );
+    }
+
+    #[test]
+    fn parses_dict_functions() {
+        let mut parser = Parser::new(Lexer::new(
+            "function! s:formatter.run() abort\nendfunction\nfunction <SID>Run() dict\nendfunction",
+        ));
+        let program = parser.parse();
+        assert_eq!(parser.errors, &[]);
+        let functions: Vec<(&FunctionName, bool)> = program
+            .statements
+            .iter()
+            .map(|stmt| match &stmt.kind {
+                StmtKind::Function(f) => (&f.name, f.dict),
+                stmt => panic!("expected function statement, got {:?}", stmt),
+            })
+            .collect();
+        assert_eq!(
+            functions,
+            vec![
+                (
+                    &FunctionName {
+                        scope: Some('s'),
+                        sid: false,
+                        autoload_path: vec![],
+                        dict_path: vec!["formatter".to_string()],
+                        name: "run".to_string(),
+                    },
+                    true
+                ),
+                (
+                    &FunctionName {
+                        scope: None,
+                        sid: true,
+                        autoload_path: vec![],
+                        dict_path: vec![],
+                        name: "Run".to_string(),
+                    },
+                    true
+                ),
+            ]
+        );
+        assert_eq!(functions[0].0.to_string(), "s:formatter.run");
+        assert_eq!(functions[1].0.to_string(), "<SID>Run");
+    }
+
+    #[test]
+    fn returns_error_for_arguments_after_variadic() {
+        let mut parser = Parser::new(Lexer::new("function F(..., a)\nendfunction"));
+        parser.parse();
+        assert_eq!(parser.errors[0].message, "expected `)`, found `,`");
+    }
+
     // #[test]
     // fn parses_for_statement_with_one_variable() {
     //     let mut parser = Parser::new(Lexer::new(
@@ -19,6 +19,10 @@ use crate::parser::Parser;
 
 pub fn parse(parser: &mut Parser) -> Option<TryStatement> {
     parser.expect_end_of_statement()?;
+    parser.parse_block(TokenType::EndTry, parse_body)
+}
+
+fn parse_body(parser: &mut Parser) -> Option<TryStatement> {
     let body = parse_statements_until(parser, |t| {
         return t == TokenType::EndTry || t == TokenType::Finally;
     });
@@ -41,7 +45,7 @@ pub fn parse(parser: &mut Parser) -> Option<TryStatement> {
             });
         }
         _ => {
-            parser.expect_token(TokenType::EndTry);
+            parser.error_missing_end(TokenType::EndTry);
             return None;
         }
     }
@@ -52,9 +56,7 @@ where
     F: Fn(TokenType) -> bool,
 {
     let mut stmts = Vec::new();
-    while parser.peek_token().token_type != TokenType::Eof
-        && !predicate(parser.peek_token().token_type)
-    {
+    while !parser.end_of_block() && !predicate(parser.peek_token().token_type) {
         if let Some(stmt) = parser.parse_statement() {
             stmts.push(stmt);
         }
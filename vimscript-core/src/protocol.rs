@@ -16,81 +16,213 @@
 use std::io;
 use std::io::prelude::*;
 
-/// Reads the content of the next message from given input.
+/// Reads contents of messages from given input.
 ///
-/// The input is expected to provide a message as described by "Base Protocol" of Language Server
-/// Protocol.
-///
-/// If this method is called when reader is empty, it returns `UnexpectedEof` error.
-pub fn read_message<R: BufRead>(input: &mut R) -> Result<String, io::Error> {
-    // Read in the "Content-Length: xx" part.
-    let mut size: Option<usize> = None;
-    loop {
-        let mut buffer = String::new();
-        input.read_line(&mut buffer)?;
-
-        // End of input.
-        if buffer.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "EOF encountered in the middle of reading LSP headers",
-            ));
-        }
+/// The input is expected to provide messages as described by "Base Protocol" of Language Server
+/// Protocol. Headers are parsed as described by RFC 7230, names are case-insensitive.
+pub struct MessageReader<R: BufRead> {
+    input: R,
+}
 
-        // Header section is finished, break from the loop.
-        if buffer == "\r\n" {
-            break;
-        }
+impl<R: BufRead> MessageReader<R> {
+    pub fn new(input: R) -> MessageReader<R> {
+        MessageReader { input: input }
+    }
 
-        let res: Vec<&str> = buffer.split(' ').collect();
+    /// Reads the content of the next message.
+    ///
+    /// If this method is called when reader is empty, it returns `UnexpectedEof` error.
+    pub fn read_message(&mut self) -> Result<String, io::Error> {
+        let mut size: Option<usize> = None;
+        loop {
+            let mut buffer = String::new();
+            self.input.read_line(&mut buffer)?;
 
-        // Make sure header is valid.
-        if res.len() != 2 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Header '{}' is malformed", buffer),
-            ));
-        }
-        let header_name = res[0].to_lowercase();
-        let header_value = res[1].trim();
-
-        match header_name.as_ref() {
-            "content-length:" => {
-                size = Some(usize::from_str_radix(header_value, 10).map_err(|_e| {
-                    io::Error::new(io::ErrorKind::InvalidData, "Couldn't read size")
-                })?);
+            // End of input.
+            if buffer.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "EOF encountered in the middle of reading LSP headers",
+                ));
             }
-            "content-type:" => {
-                if header_value != "utf8" && header_value != "utf-8" {
+
+            // Lines should end with CRLF, but a single LF is accepted too.
+            let line = buffer.trim_end_matches('\n').trim_end_matches('\r');
+            // Header section is finished, break from the loop.
+            if line.is_empty() {
+                break;
+            }
+
+            let (name, value) = parse_header(line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Header '{}' is malformed", line),
+                )
+            })?;
+            match name.to_lowercase().as_ref() {
+                "content-length" => {
+                    size = Some(value.parse().map_err(|_e| {
+                        io::Error::new(io::ErrorKind::InvalidData, "Couldn't read size")
+                    })?);
+                }
+                "content-type" if !is_utf8_content_type(value) => {
                     return Err(io::Error::new(
                         io::ErrorKind::InvalidData,
-                        format!("Content type '{}' is invalid", header_value),
+                        format!("Content type '{}' is invalid", value),
                     ));
                 }
+                // Ignore unknown headers (specification doesn't say what to do in this case).
+                _ => (),
             }
-            // Ignore unknown headers (specification doesn't say what to do in this case).
-            _ => (),
         }
-    }
-    let size = match size {
-        Some(size) => size,
-        None => {
+        let size = match size {
+            Some(size) => size,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Message is missing 'content-length' header",
+                ));
+            }
+        };
+
+        // The buffer grows with the content that is actually read, so that a bogus size doesn't
+        // allocate memory up front.
+        let mut content = Vec::new();
+        self.input
+            .by_ref()
+            .take(size as u64)
+            .read_to_end(&mut content)?;
+        if content.len() < size {
             return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Message is missing 'content-length' header",
+                io::ErrorKind::UnexpectedEof,
+                "EOF encountered in the middle of reading LSP content",
             ));
         }
-    };
 
-    let mut content = vec![0; size];
-    input.read_exact(&mut content)?;
+        String::from_utf8(content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Writes contents as messages described by "Base Protocol" of Language Server Protocol.
+pub struct MessageWriter<W: Write> {
+    output: W,
+}
+
+impl<W: Write> MessageWriter<W> {
+    pub fn new(output: W) -> MessageWriter<W> {
+        MessageWriter { output: output }
+    }
 
-    String::from_utf8(content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    /// Writes the message with a single write, so that messages written to the same stream from
+    /// different writers, e.g. to stdout, are not interleaved.
+    pub fn write_message(&mut self, content: &str) -> Result<(), io::Error> {
+        let mut message = format!("Content-Length: {}\r\n\r\n", content.len()).into_bytes();
+        message.extend_from_slice(content.as_bytes());
+        self.output.write_all(&message)?;
+        self.output.flush()
+    }
 }
 
-pub fn write_message(content: &str) -> Result<(), io::Error> {
-    std::io::stdout().write(format!("Content-Length: {}\r\n\r\n", content.len()).as_bytes())?;
-    std::io::stdout().write(content.as_bytes())?;
-    std::io::stdout().flush()?;
-    Ok(())
+// Splits `name: value` into the name and the value without surrounding whitespace. The name must
+// not contain whitespace.
+fn parse_header(line: &str) -> Option<(&str, &str)> {
+    let colon = line.find(':')?;
+    let name = &line[..colon];
+    if name.is_empty() || name.contains(|c: char| c.is_ascii_whitespace()) {
+        return None;
+    }
+    let value = line[colon + 1..].trim_matches(|c| c == ' ' || c == '\t');
+    Some((name, value))
+}
+
+// Returns true if the media type, e.g. `application/vscode-jsonrpc; charset=utf-8`, doesn't
+// specify a charset other than UTF-8. `utf8` is accepted for backwards compatibility.
+fn is_utf8_content_type(value: &str) -> bool {
+    value.split(';').skip(1).all(|parameter| {
+        let mut parts = parameter.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim();
+        let value = parts.next().unwrap_or("").trim().trim_matches('"');
+        !name.eq_ignore_ascii_case("charset")
+            || value.eq_ignore_ascii_case("utf-8")
+            || value.eq_ignore_ascii_case("utf8")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn read(input: &str) -> Result<String, io::Error> {
+        MessageReader::new(input.as_bytes()).read_message()
+    }
+
+    #[test]
+    fn reads_headers_with_optional_whitespace_and_any_case() {
+        assert_eq!(read("content-length:  2\r\n\r\n{}").unwrap(), "{}");
+        assert_eq!(read("Content-Length:\t2 \r\n\r\n{}").unwrap(), "{}");
+        assert_eq!(read("CONTENT-LENGTH:2\n\n{}").unwrap(), "{}");
+        assert_eq!(
+            read("Content-Length : 2\r\n\r\n{}").unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn accepts_utf8_content_types() {
+        for content_type in &[
+            "application/vscode-jsonrpc; charset=utf-8",
+            "application/vscode-jsonrpc;charset=\"UTF-8\"",
+            "application/vscode-jsonrpc",
+            "utf8",
+        ] {
+            let message = format!(
+                "Content-Length: 2\r\nContent-Type: {}\r\n\r\n{{}}",
+                content_type
+            );
+            assert_eq!(read(&message).unwrap(), "{}");
+        }
+        assert_eq!(
+            read("Content-Length: 2\r\nContent-Type: text/plain; charset=latin1\r\n\r\n{}")
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn reads_consecutive_messages() {
+        let mut reader =
+            MessageReader::new("Content-Length: 1\r\n\r\n1Content-Length: 1\r\n\r\n2".as_bytes());
+        assert_eq!(reader.read_message().unwrap(), "1");
+        assert_eq!(reader.read_message().unwrap(), "2");
+        assert_eq!(
+            reader.read_message().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn reports_content_shorter_than_its_length() {
+        assert_eq!(
+            read("Content-Length: 3\r\n\r\n{}").unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        assert_eq!(
+            read(&format!("Content-Length: {}\r\n\r\n{{}}", usize::MAX))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn writes_message_with_header() {
+        let mut output = Vec::new();
+        MessageWriter::new(&mut output).write_message("{}").unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Content-Length: 2\r\n\r\n{}"
+        );
+    }
 }
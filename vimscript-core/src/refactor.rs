@@ -0,0 +1,1027 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::conversion::position_to_source_position;
+use crate::conversion::token_position_to_range;
+use crate::format::format_stmt;
+use crate::lexer::Lexer;
+use crate::lexer::SourceLocation;
+use crate::lexer::TokenType;
+use crate::parser::Parser;
+use crate::parser::Program;
+use crate::references::find_references;
+use crate::references::reference_at;
+use crate::references::Reference;
+use crate::references::Symbol;
+use crate::span::BytePos;
+use crate::span::Span;
+use crate::visitor::walk_expr;
+use crate::visitor::walk_program;
+use crate::visitor::walk_stmt;
+use crate::visitor::Visitor;
+use lsp_types::CodeAction;
+use lsp_types::CodeActionKind;
+use lsp_types::Range;
+use lsp_types::TextEdit;
+use lsp_types::Url;
+use lsp_types::WorkspaceEdit;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops;
+
+/// Returns refactorings that are applicable to the selected range: extract function, extract
+/// variable and inline variable.
+pub fn refactorings(source: &str, uri: &Url, range: Range) -> Vec<CodeAction> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    let references = find_references(&program, &parser);
+    let document = Document {
+        source: source,
+        parser: &parser,
+        program: &program,
+        references: references,
+    };
+    let start = parser
+        .l
+        .source_offset(&position_to_source_position(&range.start));
+    let end = parser
+        .l
+        .source_offset(&position_to_source_position(&range.end));
+    let selection = document.trim(start..end);
+
+    let mut actions = Vec::new();
+    if let Some(edits) = extract_function(&document, &selection) {
+        actions.push(document.action(
+            "Extract into function",
+            CodeActionKind::REFACTOR_EXTRACT,
+            uri,
+            edits,
+        ));
+    }
+    if let Some(edits) = extract_variable(&document, &selection) {
+        actions.push(document.action(
+            "Extract into variable",
+            CodeActionKind::REFACTOR_EXTRACT,
+            uri,
+            edits,
+        ));
+    }
+    if let Some(edits) = inline_variable(&document, start) {
+        actions.push(document.action(
+            "Inline variable",
+            CodeActionKind::REFACTOR_INLINE,
+            uri,
+            edits,
+        ));
+    }
+    actions
+}
+
+type Edits = Vec<(ops::Range<usize>, String)>;
+
+struct Document<'a> {
+    source: &'a str,
+    parser: &'a Parser<'a>,
+    program: &'a Program,
+    references: Vec<Reference>,
+}
+
+// Statement that contains the selection, with the statements that enclose it.
+struct Enclosing<'a> {
+    stmt: &'a Stmt,
+    // Innermost function that contains the statement.
+    function: Option<&'a Stmt>,
+    // Statement at the script level that contains the statement, which can be the statement itself.
+    top_level: &'a Stmt,
+}
+
+impl<'a> Document<'a> {
+    fn action(&self, title: &str, kind: CodeActionKind, uri: &Url, edits: Edits) -> CodeAction {
+        let mut changes = HashMap::new();
+        changes.insert(
+            uri.clone(),
+            edits
+                .into_iter()
+                .map(|(range, new_text)| TextEdit {
+                    range: token_position_to_range(
+                        &self
+                            .parser
+                            .resolve_location(SourceLocation { range: range }),
+                    ),
+                    new_text: new_text,
+                })
+                .collect(),
+        );
+        CodeAction {
+            title: title.to_string(),
+            kind: Some(kind),
+            diagnostics: None,
+            edit: Some(WorkspaceEdit {
+                changes: Some(changes),
+                document_changes: None,
+                change_annotations: None,
+            }),
+            command: None,
+            is_preferred: None,
+            disabled: None,
+            data: None,
+        }
+    }
+
+    // Removes whitespace from both ends of the range.
+    fn trim(&self, range: ops::Range<usize>) -> ops::Range<usize> {
+        let bytes = self.source.as_bytes();
+        let mut start = range.start.min(bytes.len());
+        let mut end = range.end.min(bytes.len()).max(start);
+        while start < end && bytes[start].is_ascii_whitespace() {
+            start += 1;
+        }
+        while end > start && bytes[end - 1].is_ascii_whitespace() {
+            end -= 1;
+        }
+        start..end
+    }
+
+    // Returns the range of the statement without the new line at its end.
+    fn stmt_range(&self, stmt: &Stmt) -> ops::Range<usize> {
+        self.trim(stmt.span.start.0 as usize..stmt.span.end.0 as usize)
+    }
+
+    // Returns the offset of the start of the line that contains the offset.
+    fn line_start(&self, offset: usize) -> usize {
+        self.source[..offset].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    // Returns the whitespace before the statement, or None if it is not the first statement on
+    // its line.
+    fn indent(&self, stmt: &Stmt) -> Option<&'a str> {
+        let start = stmt.span.start.0 as usize;
+        let indent = &self.source[self.line_start(start)..start];
+        if indent.trim().is_empty() {
+            Some(indent)
+        } else {
+            None
+        }
+    }
+
+    // Returns the statements of the innermost list that are fully inside of the range, if no
+    // statement of the list is selected only partially.
+    fn selected_stmts(
+        &self,
+        stmts: &'a [Stmt],
+        range: &ops::Range<usize>,
+        function: Option<&'a Stmt>,
+        top_level: Option<&'a Stmt>,
+    ) -> Option<(&'a [Stmt], Enclosing<'a>)> {
+        let overlapping: Vec<usize> = (0..stmts.len())
+            .filter(|i| {
+                let r = self.stmt_range(&stmts[*i]);
+                r.start < range.end && range.start < r.end
+            })
+            .collect();
+        let (first, last) = (*overlapping.first()?, *overlapping.last()?);
+        let inside = |stmt: &Stmt| {
+            let r = self.stmt_range(stmt);
+            range.start <= r.start && r.end <= range.end
+        };
+        if overlapping.iter().all(|i| inside(&stmts[*i])) {
+            return Some((
+                &stmts[first..=last],
+                Enclosing {
+                    stmt: &stmts[first],
+                    function: function,
+                    top_level: top_level.unwrap_or(&stmts[first]),
+                },
+            ));
+        }
+        if first != last {
+            return None;
+        }
+        let stmt = &stmts[first];
+        let function = match &stmt.kind {
+            StmtKind::Function(_) => Some(stmt),
+            _ => function,
+        };
+        bodies(stmt)
+            .into_iter()
+            .filter(|body| self.body_contains(body, range))
+            .find_map(|body| {
+                self.selected_stmts(body, range, function, Some(top_level.unwrap_or(stmt)))
+            })
+    }
+
+    // Returns true if the range is between the first and the last statement of the body.
+    fn body_contains(&self, body: &[Stmt], range: &ops::Range<usize>) -> bool {
+        match (body.first(), body.last()) {
+            (Some(first), Some(last)) => {
+                self.stmt_range(first).start <= range.start
+                    && range.end <= self.stmt_range(last).end
+            }
+            _ => false,
+        }
+    }
+
+    // Returns the innermost statement that contains the range.
+    fn enclosing_stmt(
+        &self,
+        stmts: &'a [Stmt],
+        range: &ops::Range<usize>,
+        function: Option<&'a Stmt>,
+        top_level: Option<&'a Stmt>,
+    ) -> Option<Enclosing<'a>> {
+        let stmt = stmts.iter().find(|stmt| {
+            let r = self.stmt_range(stmt);
+            r.start <= range.start && range.end <= r.end
+        })?;
+        let inner_function = match &stmt.kind {
+            StmtKind::Function(_) => Some(stmt),
+            _ => function,
+        };
+        let top_level = top_level.unwrap_or(stmt);
+        bodies(stmt)
+            .into_iter()
+            .find_map(|body| self.enclosing_stmt(body, range, inner_function, Some(top_level)))
+            .or(Some(Enclosing {
+                stmt: stmt,
+                function: function,
+                top_level: top_level,
+            }))
+    }
+
+    // Returns the text of the reference as written in the source.
+    fn text(&self, reference: &Reference) -> &'a str {
+        &self.source[reference.location.range.clone()]
+    }
+}
+
+// Returns the lists of statements nested in the statement.
+fn bodies(stmt: &Stmt) -> Vec<&[Stmt]> {
+    match &stmt.kind {
+        StmtKind::Function(s) => vec![&s.body],
+        StmtKind::If(s) => {
+            let mut bodies: Vec<&[Stmt]> = Vec::new();
+            let mut s = s;
+            loop {
+                bodies.push(&s.then);
+                match &s.else_cond {
+                    ElseCond::None => break,
+                    ElseCond::Else(stmts) => {
+                        bodies.push(stmts);
+                        break;
+                    }
+                    ElseCond::ElseIf(elseif) => s = elseif,
+                }
+            }
+            bodies
+        }
+        StmtKind::While(s) => vec![&s.body],
+        StmtKind::For(s) => vec![&s.body],
+        StmtKind::Try(s) => {
+            let mut bodies: Vec<&[Stmt]> = vec![&s.body];
+            if let Some(finally) = &s.finally {
+                bodies.push(finally);
+            }
+            bodies
+        }
+        StmtKind::Augroup(s) => vec![&s.body],
+        _ => Vec::new(),
+    }
+}
+
+// Returns `name`, or `name` with the lowest number suffix that is not used yet.
+fn unique_name(name: &str, used: &HashSet<String>) -> String {
+    if !used.contains(name) {
+        return name.to_string();
+    }
+    (1..)
+        .map(|i| format!("{}{}", name, i))
+        .find(|n| !used.contains(n))
+        .unwrap()
+}
+
+fn identifier(name: &str) -> Expr {
+    expr(ExprKind::Identifier(IdentifierExpression {
+        name: name.to_string(),
+        name_location: SourceLocation { range: 0..0 },
+    }))
+}
+
+// Creates a node of code generated by a refactoring, which has no location in the source.
+fn expr(kind: ExprKind) -> Expr {
+    Expr {
+        id: NodeId(0),
+        span: Span {
+            start: BytePos(0),
+            end: BytePos(0),
+        },
+        kind: kind,
+    }
+}
+
+fn stmt(kind: StmtKind) -> Stmt {
+    Stmt {
+        id: NodeId(0),
+        span: Span {
+            start: BytePos(0),
+            end: BytePos(0),
+        },
+        kind: kind,
+    }
+}
+
+// Finds statements that can't be moved to another function.
+#[derive(Default)]
+struct ControlFlow {
+    loops: usize,
+    found: bool,
+}
+
+impl<'a> Visitor<'a> for ControlFlow {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Return(_) | StmtKind::Function(_) => self.found = true,
+            StmtKind::Break(_) if self.loops == 0 => self.found = true,
+            StmtKind::For(_) | StmtKind::While(_) => {
+                self.loops += 1;
+                walk_stmt(self, stmt);
+                self.loops -= 1;
+                return;
+            }
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+// Moves the selected statements to a new script-local function.
+//
+// Local variables that are defined before the selection and read in it become arguments, local
+// variables that are assigned in the selection and read after it are returned.
+fn extract_function(document: &Document, range: &ops::Range<usize>) -> Option<Edits> {
+    if range.start == range.end {
+        return None;
+    }
+    let (stmts, enclosing) =
+        document.selected_stmts(&document.program.statements, range, None, None)?;
+    let mut control_flow = ControlFlow::default();
+    for stmt in stmts {
+        control_flow.visit_stmt(stmt);
+    }
+    if control_flow.found {
+        return None;
+    }
+
+    let source = document.source;
+    let indent = document.indent(&stmts[0])?;
+    let block = document.line_start(stmts[0].span.start.0 as usize)
+        ..stmts[stmts.len() - 1].span.end.0 as usize;
+    let function_id = enclosing.function.map(|f| f.id.0);
+    let inside =
+        |r: &&Reference| block.start <= r.location.range.start && r.location.range.end <= block.end;
+    let references = &document.references;
+    // Compound assignments like `+=` read the variable too.
+    let mut compound = CompoundAssignments(HashSet::new());
+    walk_program(&mut compound, document.program);
+    let reads = |r: &Reference| !r.write || compound.0.contains(&r.location.range.start);
+
+    // Arguments and return values, with the text of their first reference in the selection.
+    let mut arguments: Vec<(&Symbol, &str, &str)> = Vec::new();
+    let mut returned: Vec<(&Symbol, &str)> = Vec::new();
+    for reference in references.iter().filter(inside) {
+        let (scope, name) = match &reference.symbol {
+            Symbol::Variable {
+                scope,
+                name,
+                function,
+            } if *function == function_id && function.is_some() => (*scope, name.as_str()),
+            _ => continue,
+        };
+        let symbol = &reference.symbol;
+        let same_symbol = |r: &&Reference| &r.symbol == symbol;
+        if scope == 'a' {
+            // a:0, a:1 and a:000 can't be passed with the same name.
+            if name.starts_with(|c: char| c.is_ascii_digit()) {
+                return None;
+            }
+            if !arguments.iter().any(|(s, _, _)| *s == symbol) {
+                arguments.push((symbol, name, document.text(reference)));
+            }
+            continue;
+        }
+        let defined_before = references
+            .iter()
+            .filter(same_symbol)
+            .any(|r| r.write && r.location.range.end <= block.start);
+        if reads(reference) && defined_before && !arguments.iter().any(|(s, _, _)| *s == symbol) {
+            arguments.push((symbol, name, document.text(reference)));
+        }
+        let read_after = references
+            .iter()
+            .filter(same_symbol)
+            .any(|r| reads(r) && r.location.range.start >= block.end);
+        if reference.write && read_after && !returned.iter().any(|(s, _)| *s == symbol) {
+            returned.push((symbol, document.text(reference)));
+        }
+    }
+
+    // Rewrite the references in the selection for the new function.
+    let mut replacements: Vec<(ops::Range<usize>, String)> = Vec::new();
+    let mut prologue = String::new();
+    for (symbol, name, _) in &arguments {
+        if let Symbol::Variable { scope: 'l', .. } = symbol {
+            let written = references
+                .iter()
+                .filter(inside)
+                .any(|r| &r.symbol == *symbol && r.write);
+            if written {
+                prologue.push_str(&format!("  let l:{} = a:{}\n", name, name));
+            } else {
+                for r in references
+                    .iter()
+                    .filter(inside)
+                    .filter(|r| &r.symbol == *symbol)
+                {
+                    replacements.push((r.location.range.clone(), format!("a:{}", name)));
+                }
+            }
+        }
+    }
+    if function_id.is_none() {
+        // Variables without scope are global at the script level, but local in functions.
+        for r in references.iter().filter(inside) {
+            if let Symbol::Variable {
+                scope: 'g', name, ..
+            } = &r.symbol
+            {
+                if !document.text(r).contains(':') {
+                    replacements.push((r.location.range.clone(), format!("g:{}", name)));
+                }
+            }
+        }
+    }
+    replacements.sort_by_key(|(range, _)| range.start);
+    let mut body = source[block.clone()].to_string();
+    for (range, text) in replacements.iter().rev() {
+        body.replace_range(range.start - block.start..range.end - block.start, text);
+    }
+    if !body.ends_with('\n') {
+        body.push('\n');
+    }
+    let body: String = body
+        .lines()
+        .map(|line| {
+            if line.trim().is_empty() {
+                "\n".to_string()
+            } else {
+                format!("  {}\n", line.strip_prefix(indent).unwrap_or(line))
+            }
+        })
+        .collect();
+
+    let used: HashSet<String> = document
+        .program
+        .statements
+        .iter()
+        .filter_map(|s| match &s.kind {
+            StmtKind::Function(f) => Some(f.name.to_string()),
+            _ => None,
+        })
+        .collect();
+    let name = unique_name("s:ExtractedFunction", &used);
+
+    let header = format_stmt(&stmt(StmtKind::Function(FunctionStatement {
+        name: FunctionName::parse(&name),
+        name_location: SourceLocation { range: 0..0 },
+        arguments: arguments
+            .iter()
+            .map(|(_, name, _)| FunctionArgument {
+                name: name.to_string(),
+                name_location: SourceLocation { range: 0..0 },
+                default_value: None,
+            })
+            .collect(),
+        variadic: false,
+        body: Vec::new(),
+        overwrite: true,
+        abort: true,
+        dict: false,
+    })));
+    let (header, footer) = header.split_at(header.find('\n')? + 1);
+    let returned_value = match returned.len() {
+        0 => None,
+        1 => Some(identifier(returned[0].1)),
+        _ => Some(expr(ExprKind::Array(ArrayExpression {
+            elements: returned.iter().map(|(_, text)| identifier(text)).collect(),
+        }))),
+    };
+    let mut function = format!("{}{}{}", header, prologue, body);
+    if let Some(value) = &returned_value {
+        function.push_str("  ");
+        function.push_str(&format_stmt(&stmt(StmtKind::Return(ReturnStatement {
+            value: Some(identifier(&format_expr_text(value))),
+        }))));
+    }
+    function.push_str(footer);
+
+    let call_arguments: Vec<Expr> = arguments
+        .iter()
+        .map(|(_, _, text)| identifier(text))
+        .collect();
+    let call = match returned_value {
+        None => stmt(StmtKind::Call(CallStatement {
+            name: name,
+            arguments: call_arguments,
+        })),
+        Some(value) => stmt(StmtKind::Let(LetStatement {
+            var: Box::new(value),
+            operator: TokenType::Assign,
+            value: Box::new(expr(ExprKind::Function(FunctionExpression {
+                callee: Box::new(identifier(&name)),
+                arguments: call_arguments,
+            }))),
+            constant: false,
+        })),
+    };
+    let call = format!("{}{}", indent, format_stmt(&call));
+
+    let top_level = enclosing.top_level;
+    if function_id.is_none() {
+        // Functions have to be defined before they are called at the script level.
+        let insert = document.line_start(top_level.span.start.0 as usize);
+        if insert == block.start {
+            return Some(vec![(block, format!("{}\n{}", function, call))]);
+        }
+        return Some(vec![
+            (insert..insert, format!("{}\n", function)),
+            (block, call),
+        ]);
+    }
+    let insert = top_level.span.end.0 as usize;
+    let separator = if source[..insert].ends_with('\n') {
+        "\n"
+    } else {
+        "\n\n"
+    };
+    Some(vec![
+        (block, call),
+        (insert..insert, format!("{}{}", separator, function)),
+    ])
+}
+
+// Collects the locations of variables assigned with compound operators, e.g. `+=`.
+struct CompoundAssignments(HashSet<usize>);
+
+impl<'a> Visitor<'a> for CompoundAssignments {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::Let(l) = &stmt.kind {
+            if let ExprKind::Identifier(var) = &l.var.kind {
+                if l.operator != TokenType::Assign {
+                    self.0.insert(var.name_location.range.start);
+                }
+            }
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+// Formats an expression of generated code, which only contains identifiers and lists.
+fn format_expr_text(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Identifier(e) => e.name.clone(),
+        ExprKind::Array(e) => format!(
+            "[{}]",
+            e.elements
+                .iter()
+                .map(format_expr_text)
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        _ => String::new(),
+    }
+}
+
+// Finds the expression with given span.
+struct ExprAt<'a> {
+    range: ops::Range<usize>,
+    found: Option<&'a Expr>,
+}
+
+impl<'a> Visitor<'a> for ExprAt<'a> {
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if expr.span.start.0 as usize == self.range.start
+            && expr.span.end.0 as usize == self.range.end
+            && self.found.is_none()
+        {
+            self.found = Some(expr);
+        }
+        walk_expr(self, expr);
+    }
+}
+
+// Assigns the selected expression to a new local variable before the statement that contains it.
+fn extract_variable(document: &Document, range: &ops::Range<usize>) -> Option<Edits> {
+    if range.start == range.end {
+        return None;
+    }
+    let enclosing = document.enclosing_stmt(&document.program.statements, range, None, None)?;
+    let function_id = enclosing.function?.id.0;
+    let stmt = enclosing.stmt;
+    let within =
+        |span: &Span| span.start.0 as usize <= range.start && range.end <= span.end.0 as usize;
+    match &stmt.kind {
+        // The condition of a loop is evaluated repeatedly and the conditions of `elseif` only if
+        // the previous conditions are false.
+        StmtKind::While(_) => return None,
+        StmtKind::If(s) if !within(&s.condition.span) => return None,
+        StmtKind::Let(s) if within(&s.var.span) => return None,
+        _ => {}
+    }
+    let mut finder = ExprAt {
+        range: range.clone(),
+        found: None,
+    };
+    walk_stmt(&mut finder, stmt);
+    finder.found?;
+
+    let used: HashSet<String> = document
+        .references
+        .iter()
+        .filter_map(|r| match &r.symbol {
+            Symbol::Variable { name, function, .. } if *function == Some(function_id) => {
+                Some(name.clone())
+            }
+            _ => None,
+        })
+        .collect();
+    let name = format!("l:{}", unique_name("value", &used));
+    let indent = document.indent(stmt)?;
+    let insert = document.line_start(stmt.span.start.0 as usize);
+    Some(vec![
+        (
+            insert..insert,
+            format!(
+                "{}let {} = {}\n",
+                indent,
+                name,
+                &document.source[range.clone()]
+            ),
+        ),
+        (range.clone(), name),
+    ])
+}
+
+// Replaces the reads of a local variable that is assigned once with its value, and removes the
+// assignment.
+fn inline_variable(document: &Document, offset: usize) -> Option<Edits> {
+    let symbol = &reference_at(&document.references, offset)?.symbol;
+    match symbol {
+        Symbol::Variable {
+            scope: 'l',
+            function: Some(_),
+            ..
+        } => {}
+        _ => return None,
+    }
+    let references: Vec<&Reference> = document
+        .references
+        .iter()
+        .filter(|r| &r.symbol == symbol)
+        .collect();
+    let writes: Vec<&&Reference> = references.iter().filter(|r| r.write).collect();
+    if writes.len() != 1 {
+        return None;
+    }
+    let write = writes[0];
+
+    let mut finder = LetOf {
+        location: write.location.range.clone(),
+        found: None,
+    };
+    for stmt in &document.program.statements {
+        finder.visit_stmt(stmt);
+    }
+    let (stmt, value) = finder.found?;
+    let stmt_end = stmt.span.end.0 as usize;
+    let reads: Vec<&&Reference> = references.iter().filter(|r| !r.write).collect();
+    if reads.iter().any(|r| r.location.range.start < stmt_end) {
+        return None;
+    }
+    // Calls may have side effects or return different values each time.
+    if contains_call(value) {
+        return None;
+    }
+    let value_range = value.span.start.0 as usize..value.span.end.0 as usize;
+    let last_read = reads.iter().map(|r| r.location.range.end).max()?;
+    // The value must be the same at every read.
+    let used: Vec<&Symbol> = document
+        .references
+        .iter()
+        .filter(|r| {
+            value_range.start <= r.location.range.start && r.location.range.end <= value_range.end
+        })
+        .map(|r| &r.symbol)
+        .collect();
+    if document.references.iter().any(|r| {
+        r.write
+            && used.contains(&&r.symbol)
+            && stmt_end <= r.location.range.start
+            && r.location.range.start < last_read
+    }) {
+        return None;
+    }
+    // A read in a loop would evaluate the value on each iteration, instead of once.
+    let mut loops = Loops(Vec::new());
+    for stmt in &document.program.statements {
+        loops.visit_stmt(stmt);
+    }
+    let contains = |range: &ops::Range<usize>, offset: usize| range.contains(&offset);
+    if reads.iter().any(|read| {
+        loops.0.iter().any(|range| {
+            contains(range, read.location.range.start)
+                && !contains(range, stmt.span.start.0 as usize)
+        })
+    }) {
+        return None;
+    }
+
+    let text = &document.source[value_range];
+    let text = match value.kind {
+        ExprKind::Infix(_) | ExprKind::Choose(_) => format!("({})", text),
+        _ => text.to_string(),
+    };
+    document.indent(stmt)?;
+    let mut edits = vec![(
+        document.line_start(stmt.span.start.0 as usize)..stmt_end,
+        String::new(),
+    )];
+    for read in reads {
+        edits.push((read.location.range.clone(), text.clone()));
+    }
+    Some(edits)
+}
+
+// Finds the `let` statement that assigns to the variable at given location.
+struct LetOf<'a> {
+    location: ops::Range<usize>,
+    found: Option<(&'a Stmt, &'a Expr)>,
+}
+
+impl<'a> Visitor<'a> for LetOf<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::Let(l) = &stmt.kind {
+            if let ExprKind::Identifier(var) = &l.var.kind {
+                if var.name_location.range == self.location && l.operator == TokenType::Assign {
+                    self.found = Some((stmt, &l.value));
+                }
+            }
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+// Collects ranges of `for` and `while` loops.
+struct Loops(Vec<ops::Range<usize>>);
+
+impl<'a> Visitor<'a> for Loops {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::For(_) | StmtKind::While(_) = &stmt.kind {
+            self.0
+                .push(stmt.span.start.0 as usize..stmt.span.end.0 as usize);
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+fn contains_call(expr: &Expr) -> bool {
+    struct Calls(bool);
+    impl<'a> Visitor<'a> for Calls {
+        fn visit_expr(&mut self, expr: &'a Expr) {
+            if let ExprKind::Function(_) = expr.kind {
+                self.0 = true;
+            }
+            walk_expr(self, expr);
+        }
+    }
+    let mut calls = Calls(false);
+    calls.visit_expr(expr);
+    calls.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::Position;
+    use pretty_assertions::assert_eq;
+
+    fn offset(source: &str, pos: Position) -> usize {
+        source
+            .split('\n')
+            .take(pos.line as usize)
+            .map(|l| l.len() + 1)
+            .sum::<usize>()
+            + pos.character as usize
+    }
+
+    // Applies the refactoring with given title to the range between the markers `<` and `>`, or
+    // at the position of `|`.
+    fn refactor(marked: &str, title: &str) -> Option<String> {
+        let (source, start, end) = match marked.find('|') {
+            Some(i) => (marked.replace('|', ""), i, i),
+            None => {
+                let start = marked.find('<').unwrap();
+                let end = marked.find('>').unwrap() - 1;
+                (marked.replace('<', "").replace('>', ""), start, end)
+            }
+        };
+        let parser = Parser::new(Lexer::new(&source));
+        let position = |offset: usize| {
+            token_position_to_range(&parser.resolve_location(SourceLocation {
+                range: offset..offset,
+            }))
+            .start
+        };
+        let uri = Url::parse("file:///test.vim").unwrap();
+        let actions = refactorings(
+            &source,
+            &uri,
+            Range {
+                start: position(start),
+                end: position(end),
+            },
+        );
+        let action = actions.into_iter().find(|a| a.title == title)?;
+        let mut edits = action.edit.unwrap().changes.unwrap().remove(&uri).unwrap();
+        edits.sort_by_key(|e| offset(&source, e.range.start));
+        let mut result = source.clone();
+        for edit in edits.iter().rev() {
+            result.replace_range(
+                offset(&source, edit.range.start)..offset(&source, edit.range.end),
+                &edit.new_text,
+            );
+        }
+        Some(result)
+    }
+
+    #[test]
+    fn extracts_function_with_arguments_and_return_value() {
+        let source = "function! s:Run(items) abort
+  let l:total = 0
+  <for l:item in a:items
+    let l:total += l:item
+  endfor
+  let l:count = len(a:items)>
+  return l:total / l:count
+endfunction
+";
+        assert_eq!(
+            refactor(source, "Extract into function").unwrap(),
+            "function! s:Run(items) abort
+  let l:total = 0
+  let [l:total, l:count] = s:ExtractedFunction(a:items, l:total)
+  return l:total / l:count
+endfunction
+
+function! s:ExtractedFunction(items, total) abort
+  let l:total = a:total
+  for l:item in a:items
+    let l:total += l:item
+  endfor
+  let l:count = len(a:items)
+  return [l:total, l:count]
+endfunction
+"
+        );
+    }
+
+    #[test]
+    fn extracts_function_at_script_level() {
+        let source = "let s:x = 1\n<let y = s:x + 1\ncall add(s:list, y)>\n";
+        assert_eq!(
+            refactor(source, "Extract into function").unwrap(),
+            "let s:x = 1
+function! s:ExtractedFunction() abort
+  let g:y = s:x + 1
+  call add(s:list, g:y)
+endfunction
+
+call s:ExtractedFunction()
+"
+        );
+    }
+
+    #[test]
+    fn does_not_extract_return_or_partial_statements() {
+        let source = "function! s:Run() abort\n  <return 1>\nendfunction\n";
+        assert_eq!(refactor(source, "Extract into function"), None);
+        let source = "function! s:Run() abort\n  <if 1\n    call s:A()>\n  endif\nendfunction\n";
+        assert_eq!(refactor(source, "Extract into function"), None);
+    }
+
+    #[test]
+    fn extracts_variable() {
+        let source = "function! s:Run() abort\n  call add(l:list, <l:a + 1>)\nendfunction\n";
+        assert_eq!(
+            refactor(source, "Extract into variable").unwrap(),
+            "function! s:Run() abort
+  let l:value = l:a + 1
+  call add(l:list, l:value)
+endfunction
+"
+        );
+    }
+
+    #[test]
+    fn does_not_extract_variable_from_loop_condition() {
+        let source = "function! s:Run() abort\n  while <l:a < 1>\n  endwhile\nendfunction\n";
+        assert_eq!(refactor(source, "Extract into variable"), None);
+    }
+
+    #[test]
+    fn inlines_variable() {
+        let source = "function! s:Run() abort
+  let l:sum = l:a + l:b
+  return l:s|um * l:sum
+endfunction
+";
+        assert_eq!(
+            refactor(source, "Inline variable").unwrap(),
+            "function! s:Run() abort
+  return (l:a + l:b) * (l:a + l:b)
+endfunction
+"
+        );
+    }
+
+    #[test]
+    fn does_not_inline_reassigned_variable() {
+        let source = "function! s:Run() abort
+  let l:sum = 1
+  let l:sum = 2
+  return l:s|um
+endfunction
+";
+        assert_eq!(refactor(source, "Inline variable"), None);
+    }
+
+    #[test]
+    fn does_not_inline_variable_when_its_value_changes() {
+        let source = "function! s:Run() abort
+  let l:sum = l:b + 1
+  let l:b = 5
+  return l:s|um
+endfunction
+";
+        assert_eq!(refactor(source, "Inline variable"), None);
+    }
+
+    #[test]
+    fn does_not_inline_call() {
+        let source = "function! s:Run() abort
+  let l:line = getline(1)
+  return l:l|ine
+endfunction
+";
+        assert_eq!(refactor(source, "Inline variable"), None);
+    }
+
+    #[test]
+    fn does_not_inline_variable_into_loop() {
+        let source = "function! s:Run() abort
+  let l:sum = l:a + 1
+  while l:i < 10
+    call add(l:list, l:s|um)
+    let l:i += 1
+  endwhile
+endfunction
+";
+        assert_eq!(refactor(source, "Inline variable"), None);
+        let source = "function! s:Run() abort
+  for l:i in range(3)
+    let l:sum = l:a + 1
+    call add(l:list, l:s|um)
+  endfor
+endfunction
+";
+        assert_eq!(
+            refactor(source, "Inline variable").unwrap(),
+            "function! s:Run() abort
+  for l:i in range(3)
+    call add(l:list, (l:a + 1))
+  endfor
+endfunction
+"
+        );
+    }
+}
@@ -0,0 +1,299 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::builtins::find_option;
+use crate::hover::resolve_scope;
+use crate::hover::strip_option_scope;
+use crate::lexer::SourceLocation;
+use crate::lexer::Token;
+use crate::lexer::TokenType;
+use crate::parser::Parser;
+use crate::parser::Program;
+use crate::visitor::walk_expr;
+use crate::visitor::walk_program;
+use crate::visitor::walk_stmt;
+use crate::visitor::Visitor;
+
+/// Symbol that an identifier refers to.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum Symbol {
+    Variable {
+        // One of `gslabwtv`.
+        scope: char,
+        // Name without the scope.
+        name: String,
+        // Function that declares the variable, only set for function-local variables and
+        // arguments.
+        function: Option<u32>,
+    },
+    // Function name, script-local functions always use the `s:` prefix.
+    Function(String),
+    // Full name of the option without `&` and the scope, e.g. `runtimepath` for `&l:rtp`.
+    // Unknown options keep the name as written.
+    Option(String),
+}
+
+/// Occurrence of a symbol in the program.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Reference {
+    pub symbol: Symbol,
+    pub location: SourceLocation,
+    // true for definitions and assignments
+    pub write: bool,
+}
+
+/// Returns all references in the program, in the order of their appearance in the AST.
+pub fn find_references(program: &Program, parser: &Parser) -> Vec<Reference> {
+    let mut finder = ReferenceFinder {
+        parser: parser,
+        tokens: parser.tokens(),
+        references: Vec::new(),
+        functions: Vec::new(),
+    };
+    walk_program(&mut finder, program);
+    finder.references
+}
+
+/// Returns the reference at given byte offset, including the offset just after the identifier.
+pub fn reference_at(references: &[Reference], offset: usize) -> Option<&Reference> {
+    references
+        .iter()
+        .find(|r| r.location.range.start <= offset && offset <= r.location.range.end)
+}
+
+pub fn function_symbol(name: &str) -> Symbol {
+    if let Some(name) = name.strip_prefix("<SID>") {
+        return Symbol::Function(format!("s:{}", name));
+    }
+    Symbol::Function(name.strip_prefix("g:").unwrap_or(name).to_string())
+}
+
+struct ReferenceFinder<'a> {
+    parser: &'a Parser<'a>,
+    tokens: &'a [Token],
+    references: Vec<Reference>,
+    // Ids of the enclosing functions, innermost last.
+    functions: Vec<u32>,
+}
+
+impl<'a> ReferenceFinder<'a> {
+    fn variable_symbol(&self, name: &str) -> Symbol {
+        if let Some(option) = name.strip_prefix('&') {
+            let option = strip_option_scope(option);
+            return Symbol::Option(find_option(option).map_or(option, |o| o.name).to_string());
+        }
+        let (scope, base) = resolve_scope(name, !self.functions.is_empty());
+        Symbol::Variable {
+            scope: scope,
+            name: base.to_string(),
+            function: match scope {
+                'l' | 'a' => self.functions.last().copied(),
+                _ => None,
+            },
+        }
+    }
+
+    fn add(&mut self, symbol: Symbol, location: &SourceLocation, write: bool) {
+        self.references.push(Reference {
+            symbol: symbol,
+            location: location.clone(),
+            write: write,
+        });
+    }
+
+    // Returns the identifier tokens that follow the first token of the statement, until a token
+    // of given type.
+    fn tokens_after(&self, stmt: &Stmt, until: TokenType) -> Vec<&'a Token> {
+        let tokens = self.tokens;
+        tokens
+            .iter()
+            .skip_while(|t| t.location.range.start <= stmt.span.start.0 as usize)
+            .take_while(|t| t.token_type != until && !Parser::end_of_statement_token(t.token_type))
+            .filter(|t| t.token_type == TokenType::Ident)
+            .collect()
+    }
+}
+
+impl<'a> Visitor<'a> for ReferenceFinder<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Function(f) => {
+                self.add(function_symbol(&f.name.to_string()), &f.name_location, true);
+                self.functions.push(stmt.id.0);
+                for argument in &f.arguments {
+                    let symbol = self.variable_symbol(&format!("a:{}", argument.name));
+                    self.add(symbol, &argument.name_location, true);
+                    if let Some(value) = &argument.default_value {
+                        self.visit_expr(value);
+                    }
+                }
+                walk_stmt(self, stmt);
+                self.functions.pop();
+                return;
+            }
+            StmtKind::Let(l) => {
+                if let ExprKind::Identifier(var) = &l.var.kind {
+                    let symbol = self.variable_symbol(&var.name);
+                    self.add(symbol, &var.name_location, true);
+                    self.visit_expr(&l.value);
+                    return;
+                }
+            }
+            StmtKind::For(_) => {
+                for token in self.tokens_after(stmt, TokenType::In) {
+                    let symbol = self.variable_symbol(&self.parser.identifier_name(token));
+                    self.add(symbol, &token.location, true);
+                }
+            }
+            StmtKind::Call(s) => {
+                if let Some(token) = self.tokens_after(stmt, TokenType::LeftParenthesis).first() {
+                    // `call dict.method()` calls a function stored in the dictionary.
+                    let symbol = if s.name.contains('.') {
+                        self.variable_symbol(&self.parser.identifier_name(token))
+                    } else {
+                        function_symbol(&s.name)
+                    };
+                    self.add(symbol, &token.location, false);
+                }
+            }
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Identifier(e) => {
+                let symbol = self.variable_symbol(&e.name);
+                self.add(symbol, &e.name_location, false);
+            }
+            ExprKind::Function(e) => {
+                if let ExprKind::Identifier(callee) = &e.callee.kind {
+                    self.add(function_symbol(&callee.name), &callee.name_location, false);
+                    for argument in &e.arguments {
+                        self.visit_expr(argument);
+                    }
+                    return;
+                }
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use pretty_assertions::assert_eq;
+
+    fn references(source: &str) -> Vec<(String, Symbol, bool)> {
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse();
+        find_references(&program, &parser)
+            .into_iter()
+            .map(|r| {
+                (
+                    source[r.location.range.clone()].to_string(),
+                    r.symbol,
+                    r.write,
+                )
+            })
+            .collect()
+    }
+
+    fn variable(scope: char, name: &str, function: Option<u32>) -> Symbol {
+        Symbol::Variable {
+            scope: scope,
+            name: name.to_string(),
+            function: function,
+        }
+    }
+
+    #[test]
+    fn resolves_scopes_of_variables() {
+        let source = "let count = 0
+function! s:Inc(step) abort
+  let count = a:step + g:count
+  return l:count
+endfunction
+call s:Inc(1)";
+        let function = Some(11);
+        assert_eq!(
+            references(source),
+            vec![
+                ("count".to_string(), variable('g', "count", None), true),
+                (
+                    "s:Inc".to_string(),
+                    Symbol::Function("s:Inc".to_string()),
+                    true
+                ),
+                ("step".to_string(), variable('a', "step", function), true),
+                ("count".to_string(), variable('l', "count", function), true),
+                ("a:step".to_string(), variable('a', "step", function), false),
+                ("g:count".to_string(), variable('g', "count", None), false),
+                (
+                    "l:count".to_string(),
+                    variable('l', "count", function),
+                    false
+                ),
+                (
+                    "s:Inc".to_string(),
+                    Symbol::Function("s:Inc".to_string()),
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_loop_variables_and_options() {
+        let source = "for [key, value] in items(&rtp)\nendfor";
+        assert_eq!(
+            references(source),
+            vec![
+                ("key".to_string(), variable('g', "key", None), true),
+                ("value".to_string(), variable('g', "value", None), true),
+                (
+                    "items".to_string(),
+                    Symbol::Function("items".to_string()),
+                    false
+                ),
+                (
+                    "&rtp".to_string(),
+                    Symbol::Option("runtimepath".to_string()),
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_short_names_and_scopes_of_options() {
+        let source = "let &l:sw = &shiftwidth + &g:my_option";
+        assert_eq!(
+            references(source)
+                .into_iter()
+                .map(|(_, symbol, _)| symbol)
+                .collect::<Vec<_>>(),
+            vec![
+                Symbol::Option("shiftwidth".to_string()),
+                Symbol::Option("shiftwidth".to_string()),
+                Symbol::Option("my_option".to_string()),
+            ]
+        );
+    }
+}
@@ -15,6 +15,7 @@
 use crate::ast::ExprKind;
 use crate::ast::Stmt;
 use crate::ast::StmtKind;
+use crate::conversion::token_position_to_range;
 use crate::lexer::Lexer;
 use crate::lexer::SourcePosition;
 use crate::lexer::TokenPosition;
@@ -22,7 +23,6 @@ use crate::lexer::TokenType;
 use crate::parser::Parser;
 use crate::parser::Program;
 use lsp_types::Position;
-use lsp_types::Range;
 use lsp_types::TextEdit;
 use std::collections::HashMap;
 
@@ -34,20 +34,6 @@ pub fn rename(source: &str, pos: Position, new_name: &str) -> Result<Vec<TextEdi
     rename_op.rename(&parser, pos, new_name)
 }
 
-fn token_position_to_range(position: &TokenPosition) -> Range {
-    Range {
-        start: source_position_to_position(&position.start),
-        end: source_position_to_position(&position.end),
-    }
-}
-
-fn source_position_to_position(position: &SourcePosition) -> Position {
-    Position {
-        line: position.line as u64,
-        character: position.character as u64,
-    }
-}
-
 struct Rename {
     token_to_positions: HashMap<String, Vec<TokenPosition>>,
 }
@@ -106,7 +92,8 @@ impl Rename {
             return Err(());
         }
         let val = parser.identifier_name(&token);
-        let positions = &self.token_to_positions[&val];
+        // Only identifiers in call arguments are tracked yet.
+        let positions = self.token_to_positions.get(&val).ok_or(())?;
         let mut edits = Vec::new();
         for pos in positions {
             edits.push(TextEdit {
@@ -168,4 +155,17 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn fails_for_untracked_identifier() {
+        let res = rename(
+            "let x = 1",
+            Position {
+                line: 0,
+                character: 4,
+            },
+            "y",
+        );
+        assert_eq!(res, Err(()));
+    }
 }
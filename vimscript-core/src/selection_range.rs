@@ -0,0 +1,226 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::conversion::position_to_source_position;
+use crate::conversion::token_position_to_range;
+use crate::lexer::Lexer;
+use crate::lexer::SourceLocation;
+use crate::lexer::TokenType;
+use crate::parser::Parser;
+use crate::visitor::walk_expr;
+use crate::visitor::walk_program;
+use crate::visitor::walk_stmt;
+use crate::visitor::Visitor;
+use lsp_types::Position;
+use lsp_types::Range;
+use lsp_types::SelectionRange;
+use std::ops;
+
+/// Returns the selection ranges for each of the positions.
+///
+/// Each selection starts at the token under the cursor and is extended by the enclosing
+/// expressions, argument lists, statements and blocks, e.g. argument → argument list → call →
+/// statement → function body → function.
+pub fn selection_ranges(source: &str, positions: &[Position]) -> Vec<SelectionRange> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    positions
+        .iter()
+        .map(|pos| {
+            let offset = parser.l.source_offset(&position_to_source_position(pos));
+            let mut collector = Enclosing {
+                source: source,
+                offset: offset,
+                ranges: Vec::new(),
+            };
+            walk_program(&mut collector, &program);
+            if let Ok(token) = parser.find_token(position_to_source_position(pos)) {
+                if token.token_type != TokenType::NewLine {
+                    collector.ranges.push(token.location.range);
+                }
+            }
+            selection_range(&parser, *pos, &collector.ranges)
+        })
+        .collect()
+}
+
+// Builds the nested selection range from the byte ranges, ordered from the outermost one.
+fn selection_range(parser: &Parser, pos: Position, ranges: &[ops::Range<usize>]) -> SelectionRange {
+    let mut selection: Option<SelectionRange> = None;
+    let mut last: Option<&ops::Range<usize>> = None;
+    for range in ranges {
+        // Skip ranges that are the same as their parent or that are not inside of it.
+        if let Some(last) = last {
+            if range == last || range.start < last.start || range.end > last.end {
+                continue;
+            }
+        }
+        last = Some(range);
+        selection = Some(SelectionRange {
+            range: token_position_to_range(&parser.resolve_location(SourceLocation {
+                range: range.clone(),
+            })),
+            parent: selection.map(Box::new),
+        });
+    }
+    selection.unwrap_or(SelectionRange {
+        range: Range {
+            start: pos,
+            end: pos,
+        },
+        parent: None,
+    })
+}
+
+// Collects byte ranges of the nodes that contain the offset, in pre-order.
+struct Enclosing<'a> {
+    source: &'a str,
+    offset: usize,
+    ranges: Vec<ops::Range<usize>>,
+}
+
+impl<'a> Enclosing<'a> {
+    fn add(&mut self, range: ops::Range<usize>) {
+        if range.start <= self.offset && self.offset <= range.end {
+            self.ranges.push(range);
+        }
+    }
+
+    // Returns the range of the statement without the new line at its end.
+    fn stmt_range(&self, stmt: &Stmt) -> ops::Range<usize> {
+        let start = stmt.span.start.0 as usize;
+        let mut end = stmt.span.end.0 as usize;
+        if end > start && self.source[..end].ends_with('\n') {
+            end -= 1;
+        }
+        start..end
+    }
+
+    fn add_body(&mut self, body: &[Stmt]) {
+        if let (Some(first), Some(last)) = (body.first(), body.last()) {
+            self.add(self.stmt_range(first).start..self.stmt_range(last).end);
+        }
+    }
+
+    fn add_arguments(&mut self, arguments: &[Expr]) {
+        if let (Some(first), Some(last)) = (arguments.first(), arguments.last()) {
+            self.add(first.span.start.0 as usize..last.span.end.0 as usize);
+        }
+    }
+
+    fn add_if(&mut self, stmt: &IfStatement) {
+        self.add_body(&stmt.then);
+        match &stmt.else_cond {
+            ElseCond::None => {}
+            ElseCond::Else(stmts) => self.add_body(stmts),
+            ElseCond::ElseIf(stmt) => self.add_if(stmt),
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Enclosing<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        self.add(self.stmt_range(stmt));
+        match &stmt.kind {
+            StmtKind::Function(s) => self.add_body(&s.body),
+            StmtKind::If(s) => self.add_if(s),
+            StmtKind::While(s) => self.add_body(&s.body),
+            StmtKind::For(s) => self.add_body(&s.body),
+            StmtKind::Try(s) => {
+                self.add_body(&s.body);
+                if let Some(finally) = &s.finally {
+                    self.add_body(finally);
+                }
+            }
+            StmtKind::Augroup(s) => self.add_body(&s.body),
+            StmtKind::Call(s) => self.add_arguments(&s.arguments),
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        self.add(expr.span.start.0 as usize..expr.span.end.0 as usize);
+        if let ExprKind::Function(e) = &expr.kind {
+            self.add_arguments(&e.arguments);
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    // Returns the text of the selection and all its parents.
+    fn selections(source: &str, line: u32, character: u32) -> Vec<String> {
+        let lines: Vec<&str> = source.split('\n').collect();
+        let offset = |pos: Position| {
+            lines[..pos.line as usize]
+                .iter()
+                .map(|l| l.len() + 1)
+                .sum::<usize>()
+                + pos.character as usize
+        };
+        let ranges = selection_ranges(
+            source,
+            &[Position {
+                line: line,
+                character: character,
+            }],
+        );
+        let mut result = Vec::new();
+        let mut selection = Some(&ranges[0]);
+        while let Some(s) = selection {
+            result.push(source[offset(s.range.start)..offset(s.range.end)].to_string());
+            selection = s.parent.as_deref();
+        }
+        result
+    }
+
+    #[test]
+    fn expands_from_argument_to_function() {
+        let source = "function! s:Run() abort
+  let x = 1
+  call add(l:list, get(l:dict, 'key'))
+endfunction";
+        assert_eq!(
+            selections(source, 2, 24),
+            vec![
+                "l:dict",
+                "l:dict, 'key'",
+                "get(l:dict, 'key')",
+                "l:list, get(l:dict, 'key')",
+                "call add(l:list, get(l:dict, 'key'))",
+                "let x = 1\n  call add(l:list, get(l:dict, 'key'))",
+                source,
+            ]
+        );
+    }
+
+    #[test]
+    fn expands_choose_and_unary_expressions() {
+        assert_eq!(
+            selections("let x = !a ? -b : c", 0, 14),
+            vec!["b", "-b", "!a ? -b : c", "let x = !a ? -b : c"]
+        );
+    }
+
+    #[test]
+    fn returns_empty_range_outside_of_statements() {
+        assert_eq!(selections("\n\nlet x = 1", 1, 0), vec![""]);
+    }
+}
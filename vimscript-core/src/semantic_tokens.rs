@@ -0,0 +1,590 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::builtins;
+use crate::hover::resolve_scope;
+use crate::lexer::Lexer;
+use crate::lexer::Token;
+use crate::lexer::TokenType;
+use crate::parser::Parser;
+use crate::visitor::walk_expr;
+use crate::visitor::walk_program;
+use crate::visitor::walk_stmt;
+use crate::visitor::Visitor;
+use lsp_types::Position;
+use lsp_types::Range;
+use lsp_types::SemanticToken;
+use lsp_types::SemanticTokenModifier;
+use lsp_types::SemanticTokenType;
+use lsp_types::SemanticTokensEdit;
+use lsp_types::SemanticTokensLegend;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+// Token types, in the order of the legend.
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Kind {
+    Function,
+    Parameter,
+    Variable,
+    Option,
+    Keyword,
+    String,
+    Number,
+    Operator,
+    Comment,
+}
+
+// Token modifiers, bits in the order of the legend.
+const DECLARATION: u32 = 1;
+const READONLY: u32 = 1 << 1;
+const DEPRECATED: u32 = 1 << 2;
+const DEFAULT_LIBRARY: u32 = 1 << 3;
+const LOCAL: u32 = 1 << 4;
+const SCRIPT: u32 = 1 << 5;
+const GLOBAL: u32 = 1 << 6;
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: vec![
+            SemanticTokenType::FUNCTION,
+            SemanticTokenType::PARAMETER,
+            SemanticTokenType::VARIABLE,
+            SemanticTokenType::PROPERTY,
+            SemanticTokenType::KEYWORD,
+            SemanticTokenType::STRING,
+            SemanticTokenType::NUMBER,
+            SemanticTokenType::OPERATOR,
+            SemanticTokenType::COMMENT,
+        ],
+        token_modifiers: vec![
+            SemanticTokenModifier::DECLARATION,
+            SemanticTokenModifier::READONLY,
+            SemanticTokenModifier::DEPRECATED,
+            SemanticTokenModifier::DEFAULT_LIBRARY,
+            // Scopes of variables, `l:`, `s:` and `g:`.
+            SemanticTokenModifier::new("local"),
+            SemanticTokenModifier::new("script"),
+            SemanticTokenModifier::new("global"),
+        ],
+    }
+}
+
+/// Returns semantic tokens of the whole source, or only of the tokens in given range.
+pub fn semantic_tokens(source: &str, range: Option<Range>) -> Vec<SemanticToken> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    let tokens = parser.tokens();
+    let mut classifier = Classifier {
+        tokens: tokens,
+        source: source,
+        identifiers: HashMap::new(),
+        raw: BTreeMap::new(),
+        function_depth: 0,
+    };
+    walk_program(&mut classifier, &program);
+
+    let mut result = Vec::new();
+    let mut positions = PositionTracker::new(source);
+    let mut previous = Position::new(0, 0);
+    // Tokens are in increasing order, so the raw ranges are visited once alongside them.
+    let mut raw = classifier.raw.iter().peekable();
+    let mut raw_end = 0;
+    for token in tokens {
+        let start = token.location.range.start;
+        while let Some((_, end)) = raw.next_if(|(raw_start, _)| **raw_start <= start) {
+            raw_end = raw_end.max(*end);
+        }
+        if start < raw_end {
+            continue;
+        }
+        let (kind, modifiers) = match classify_token(token.token_type) {
+            Some(kind) => (kind, 0),
+            None => match classifier.identifiers.get(&start) {
+                Some(classification) => *classification,
+                None => continue,
+            },
+        };
+        let position = positions.position(start);
+        let text = &source[token.location.range.clone()];
+        // Tokens can't span multiple lines, so only the first line is highlighted.
+        let length = text.split('\n').next().unwrap_or("").encode_utf16().count() as u32;
+        if length == 0 {
+            continue;
+        }
+        if let Some(range) = &range {
+            let end = Position::new(position.line, position.character + length);
+            if end <= range.start || range.end <= position {
+                continue;
+            }
+        }
+        result.push(SemanticToken {
+            delta_line: position.line - previous.line,
+            delta_start: if position.line == previous.line {
+                position.character - previous.character
+            } else {
+                position.character
+            },
+            length: length,
+            token_type: kind as u32,
+            token_modifiers_bitset: modifiers,
+        });
+        previous = position;
+    }
+    result
+}
+
+/// Returns edits that transform the old tokens to the new ones.
+///
+/// Only the changed part in the middle is replaced, which is usually small when the user types.
+pub fn diff(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let deleted = old.len() - prefix - suffix;
+    let inserted = &new[prefix..new.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return Vec::new();
+    }
+    // Edits index the flattened array, which has 5 numbers per token.
+    vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: (deleted * 5) as u32,
+        data: Some(inserted.to_vec()),
+    }]
+}
+
+fn classify_token(token_type: TokenType) -> Option<Kind> {
+    match token_type {
+        TokenType::Let
+        | TokenType::Const
+        | TokenType::Function
+        | TokenType::EndFunction
+        | TokenType::If
+        | TokenType::Else
+        | TokenType::ElseIf
+        | TokenType::EndIf
+        | TokenType::Try
+        | TokenType::Catch
+        | TokenType::Finally
+        | TokenType::EndTry
+        | TokenType::Set
+        | TokenType::For
+        | TokenType::EndFor
+        | TokenType::While
+        | TokenType::EndWhile
+        | TokenType::In
+        | TokenType::Abort
+        | TokenType::Call
+        | TokenType::Break
+        | TokenType::Execute
+        | TokenType::Return
+        | TokenType::Finish => Some(Kind::Keyword),
+        TokenType::StringLiteral => Some(Kind::String),
+        TokenType::Number => Some(Kind::Number),
+        TokenType::Comment => Some(Kind::Comment),
+        TokenType::Assign
+        | TokenType::PlusAssign
+        | TokenType::MinusAssign
+        | TokenType::MultiplyAssign
+        | TokenType::DivideAssign
+        | TokenType::ModuloAssign
+        | TokenType::DotAssign
+        | TokenType::EqualCaseSensitive
+        | TokenType::InEqualCaseSensitive
+        | TokenType::EqualCaseInSensitive
+        | TokenType::InEqualCaseInSensitive
+        | TokenType::Equal
+        | TokenType::InEqual
+        | TokenType::Less
+        | TokenType::LessOrEqual
+        | TokenType::Greater
+        | TokenType::GreaterOrEqual
+        | TokenType::RegexpMatchesIgnoreCase
+        | TokenType::RegexpMatchesCaseSensitive
+        | TokenType::RegexpMatchesCaseInSensitive
+        | TokenType::And
+        | TokenType::Or
+        | TokenType::Plus
+        | TokenType::Minus
+        | TokenType::Multiply
+        | TokenType::Divide
+        | TokenType::Modulo
+        | TokenType::Dot
+        | TokenType::QuestionMark => Some(Kind::Operator),
+        _ => None,
+    }
+}
+
+// Classifies identifiers using the AST, as the same identifier token can be a function, variable
+// or a command.
+struct Classifier<'a> {
+    tokens: &'a [Token],
+    source: &'a str,
+    // Classification of identifier tokens, by their start offset.
+    identifiers: HashMap<usize, (Kind, u32)>,
+    // Arguments of Ex commands that are not parsed, e.g. the right hand side of mappings, as end
+    // offsets by their start offset.
+    raw: BTreeMap<usize, usize>,
+    function_depth: usize,
+}
+
+impl<'a> Classifier<'a> {
+    // Returns the index of the token that starts at given offset or the first one after it.
+    fn token_index(&self, offset: usize) -> usize {
+        // Tokens are sorted by their offset.
+        self.tokens
+            .partition_point(|t| t.location.range.start < offset)
+    }
+
+    fn mark_raw(&mut self, range: std::ops::Range<usize>) {
+        let end = self.raw.entry(range.start).or_insert(range.end);
+        *end = (*end).max(range.end);
+    }
+
+    fn classify(&mut self, offset: usize, kind: Kind, modifiers: u32) {
+        self.identifiers.entry(offset).or_insert((kind, modifiers));
+    }
+
+    fn classify_variable(&mut self, offset: usize, name: &str, modifiers: u32) {
+        if name.starts_with('&') {
+            self.classify(offset, Kind::Option, modifiers);
+            return;
+        }
+        let (kind, scope_modifiers) = match resolve_scope(name, self.function_depth > 0).0 {
+            'a' => (Kind::Parameter, 0),
+            'v' => (Kind::Variable, READONLY | DEFAULT_LIBRARY),
+            'l' => (Kind::Variable, LOCAL),
+            's' => (Kind::Variable, SCRIPT),
+            'g' => (Kind::Variable, GLOBAL),
+            _ => (Kind::Variable, 0),
+        };
+        self.classify(offset, kind, modifiers | scope_modifiers);
+    }
+
+    fn classify_function(&mut self, offset: usize, name: &str, modifiers: u32) {
+        let modifiers = match builtins::find_function(name) {
+            Some(f) if f.deprecated => modifiers | DEFAULT_LIBRARY | DEPRECATED,
+            Some(_) => modifiers | DEFAULT_LIBRARY,
+            None if name.starts_with("s:") || name.starts_with("<SID>") => modifiers | SCRIPT,
+            None => modifiers,
+        };
+        self.classify(offset, Kind::Function, modifiers);
+    }
+
+    // Marks the command name as a keyword and the rest of the statement as not parsed.
+    fn classify_command(&mut self, stmt: &Stmt) {
+        let start = stmt.span.start.0 as usize;
+        self.classify(start, Kind::Keyword, 0);
+        let index = self.token_index(start);
+        if let Some(token) = self.tokens.get(index) {
+            self.mark_raw(token.location.range.end..stmt.span.end.0 as usize);
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Classifier<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        let start = stmt.span.start.0 as usize;
+        match &stmt.kind {
+            StmtKind::Function(f) => {
+                self.classify_function(
+                    f.name_location.range.start,
+                    &f.name.to_string(),
+                    DECLARATION,
+                );
+                for argument in &f.arguments {
+                    self.classify(
+                        argument.name_location.range.start,
+                        Kind::Parameter,
+                        DECLARATION,
+                    );
+                }
+                self.function_depth += 1;
+                walk_stmt(self, stmt);
+                self.function_depth -= 1;
+                return;
+            }
+            StmtKind::Let(l) => {
+                if let ExprKind::Identifier(var) = &l.var.kind {
+                    let mut modifiers = 0;
+                    if l.operator == TokenType::Assign {
+                        modifiers |= DECLARATION;
+                    }
+                    if l.constant {
+                        modifiers |= READONLY;
+                    }
+                    self.classify_variable(var.name_location.range.start, &var.name, modifiers);
+                }
+            }
+            StmtKind::Call(s) => {
+                let index = self.token_index(start) + 1;
+                if let Some(token) = self.tokens.get(index) {
+                    let offset = token.location.range.start;
+                    self.classify_function(offset, &s.name, 0);
+                }
+            }
+            StmtKind::Set(_) => {
+                let index = self.token_index(start) + 1;
+                if let Some(token) = self.tokens.get(index) {
+                    let offset = token.location.range.start;
+                    self.classify(offset, Kind::Option, 0);
+                }
+            }
+            StmtKind::For(_) => {
+                // Loop variables are all identifiers between `for` and `in`.
+                let tokens = self.tokens;
+                for token in tokens[self.token_index(start) + 1..]
+                    .iter()
+                    .take_while(|t| t.token_type != TokenType::In)
+                    .filter(|t| t.token_type == TokenType::Ident)
+                {
+                    let name = &self.source[token.location.range.clone()];
+                    self.classify_variable(token.location.range.start, name, DECLARATION);
+                }
+            }
+            StmtKind::Augroup(_) => {
+                // Only the group name is not parsed, the body contains regular statements.
+                self.classify(start, Kind::Keyword, 0);
+                if let Some(token) = self.tokens.get(self.token_index(start) + 1) {
+                    let end = self.source[token.location.range.start..]
+                        .find('\n')
+                        .map_or(self.source.len(), |i| token.location.range.start + i);
+                    self.mark_raw(token.location.range.start..end);
+                }
+            }
+            StmtKind::Autocmd(_) | StmtKind::Command(_) | StmtKind::Map(_) => {
+                self.classify_command(stmt)
+            }
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Identifier(e) => {
+                self.classify_variable(e.name_location.range.start, &e.name, 0)
+            }
+            ExprKind::Function(e) => {
+                if let ExprKind::Identifier(callee) = &e.callee.kind {
+                    self.classify_function(callee.name_location.range.start, &callee.name, 0);
+                }
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+// Converts byte offsets to positions in UTF-16 code units, expects offsets in increasing order.
+struct PositionTracker<'a> {
+    source: &'a str,
+    offset: usize,
+    position: Position,
+}
+
+impl<'a> PositionTracker<'a> {
+    fn new(source: &'a str) -> PositionTracker<'a> {
+        PositionTracker {
+            source: source,
+            offset: 0,
+            position: Position::new(0, 0),
+        }
+    }
+
+    fn position(&mut self, offset: usize) -> Position {
+        for c in self.source[self.offset..offset].chars() {
+            if c == '\n' {
+                self.position.line += 1;
+                self.position.character = 0;
+            } else {
+                self.position.character += c.len_utf16() as u32;
+            }
+        }
+        self.offset = offset;
+        self.position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    // Returns text, kind and modifiers of all tokens, resolved to absolute positions.
+    fn tokens(source: &str) -> Vec<(String, Kind, u32)> {
+        let kinds = [
+            Kind::Function,
+            Kind::Parameter,
+            Kind::Variable,
+            Kind::Option,
+            Kind::Keyword,
+            Kind::String,
+            Kind::Number,
+            Kind::Operator,
+            Kind::Comment,
+        ];
+        let lines: Vec<&str> = source.lines().collect();
+        let mut line = 0;
+        let mut character = 0;
+        let mut result = Vec::new();
+        for token in semantic_tokens(source, None) {
+            if token.delta_line > 0 {
+                line += token.delta_line as usize;
+                character = 0;
+            }
+            character += token.delta_start as usize;
+            let units: Vec<u16> = lines[line].encode_utf16().collect();
+            let text =
+                String::from_utf16(&units[character..character + token.length as usize]).unwrap();
+            result.push((
+                text,
+                kinds[token.token_type as usize],
+                token.token_modifiers_bitset,
+            ));
+        }
+        result
+    }
+
+    fn t(text: &str, kind: Kind, modifiers: u32) -> (String, Kind, u32) {
+        (text.to_string(), kind, modifiers)
+    }
+
+    #[test]
+    fn classifies_variables_by_scope() {
+        assert_eq!(
+            tokens(
+                "const s:limit = 10
+function! s:Check(count) abort
+  let total = a:count + v:count
+  return total < s:limit
+endfunction"
+            ),
+            vec![
+                t("const", Kind::Keyword, 0),
+                t("s:limit", Kind::Variable, DECLARATION | READONLY | SCRIPT),
+                t("=", Kind::Operator, 0),
+                t("10", Kind::Number, 0),
+                t("function", Kind::Keyword, 0),
+                t("s:Check", Kind::Function, DECLARATION | SCRIPT),
+                t("count", Kind::Parameter, DECLARATION),
+                t("abort", Kind::Keyword, 0),
+                t("let", Kind::Keyword, 0),
+                t("total", Kind::Variable, DECLARATION | LOCAL),
+                t("=", Kind::Operator, 0),
+                t("a:count", Kind::Parameter, 0),
+                t("+", Kind::Operator, 0),
+                t("v:count", Kind::Variable, READONLY | DEFAULT_LIBRARY),
+                t("return", Kind::Keyword, 0),
+                t("total", Kind::Variable, LOCAL),
+                t("<", Kind::Operator, 0),
+                t("s:limit", Kind::Variable, SCRIPT),
+                t("endfunction", Kind::Keyword, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn classifies_functions_options_and_comments() {
+        assert_eq!(
+            tokens(
+                "\" Setup
+set expandtab
+let &tabstop = len('abc')
+call buffer_exists(1)"
+            ),
+            vec![
+                t("\" Setup", Kind::Comment, 0),
+                t("set", Kind::Keyword, 0),
+                t("expandtab", Kind::Option, 0),
+                t("let", Kind::Keyword, 0),
+                t("&tabstop", Kind::Option, DECLARATION),
+                t("=", Kind::Operator, 0),
+                t("len", Kind::Function, DEFAULT_LIBRARY),
+                t("'abc'", Kind::String, 0),
+                t("call", Kind::Keyword, 0),
+                t(
+                    "buffer_exists",
+                    Kind::Function,
+                    DEFAULT_LIBRARY | DEPRECATED
+                ),
+                t("1", Kind::Number, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn does_not_classify_mapping_arguments() {
+        assert_eq!(
+            tokens("nnoremap <leader>f :call Format()<CR>\nlet g:x = 1"),
+            vec![
+                t("nnoremap", Kind::Keyword, 0),
+                t("let", Kind::Keyword, 0),
+                t("g:x", Kind::Variable, DECLARATION | GLOBAL),
+                t("=", Kind::Operator, 0),
+                t("1", Kind::Number, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn measures_tokens_in_utf16_code_units() {
+        assert_eq!(
+            tokens("let s:face = '😀' . 'é'"),
+            vec![
+                t("let", Kind::Keyword, 0),
+                t("s:face", Kind::Variable, DECLARATION | SCRIPT),
+                t("=", Kind::Operator, 0),
+                t("'😀'", Kind::String, 0),
+                t(".", Kind::Operator, 0),
+                t("'é'", Kind::String, 0),
+            ]
+        );
+        let tokens = semantic_tokens("let s:face = '😀' . 'é'", None);
+        assert_eq!(tokens[3].length, 4);
+        assert_eq!(tokens[4].delta_start, 5);
+    }
+
+    #[test]
+    fn returns_tokens_in_range() {
+        let source = "let a = 1\nlet b = 2\nlet c = 3";
+        let tokens = semantic_tokens(
+            source,
+            Some(Range::new(Position::new(1, 0), Position::new(1, 9))),
+        );
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[0].delta_line, 1);
+    }
+
+    #[test]
+    fn returns_edit_of_changed_tokens() {
+        let old = semantic_tokens("let a = 1\nlet b = 2", None);
+        let new = semantic_tokens("let a = 1\nlet b = 'x'\n", None);
+        let edits = diff(&old, &new);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].start, 35);
+        assert_eq!(edits[0].delete_count, 5);
+        assert_eq!(
+            edits[0].data.as_ref().unwrap()[0].token_type,
+            Kind::String as u32
+        );
+        assert_eq!(diff(&new, &new), Vec::new());
+    }
+}
@@ -17,11 +17,14 @@ use serde::Serialize;
 use serde_json::json;
 use std::collections::HashMap;
 use std::io;
+use std::marker::PhantomData;
 use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::Weak;
+use std::thread;
 
 /// The Read trait allows for reading utf-8 packets from a source.
 pub trait Read {
@@ -49,28 +52,106 @@ pub struct Notification {
     pub params: serde_json::Value,
 }
 
+/// Error codes defined by JSON-RPC and LSP.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ErrorCode {
+    ParseError,
+    InvalidRequest,
+    MethodNotFound,
+    InvalidParams,
+    InternalError,
+    /// A request other than `initialize` was sent before `initialize`.
+    ServerNotInitialized,
+    /// The client cancelled the request.
+    RequestCancelled,
+    /// The document changed after the request was sent, so the result would be outdated.
+    ContentModified,
+    /// The request is valid, but the server failed to handle it.
+    RequestFailed,
+}
+
+impl ErrorCode {
+    pub fn code(self) -> i64 {
+        match self {
+            ErrorCode::ParseError => -32700,
+            ErrorCode::InvalidRequest => -32600,
+            ErrorCode::MethodNotFound => -32601,
+            ErrorCode::InvalidParams => -32602,
+            ErrorCode::InternalError => -32603,
+            ErrorCode::ServerNotInitialized => -32002,
+            ErrorCode::RequestCancelled => -32800,
+            ErrorCode::ContentModified => -32801,
+            ErrorCode::RequestFailed => -32803,
+        }
+    }
+}
+
+/// Error of a response, sent by the server or received from the client.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ResponseError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl ResponseError {
+    pub fn new(code: ErrorCode, message: &str) -> ResponseError {
+        ResponseError {
+            code: code.code(),
+            message: message.to_string(),
+            data: None,
+        }
+    }
+}
+
 pub struct ResponseHandle {
     id: Id,
     writer: Arc<Mutex<dyn Write + Send>>,
+    pending_requests: Arc<Mutex<PendingRequests>>,
 }
 
 impl ResponseHandle {
-    pub fn respond(self, response: Result<serde_json::Value, serde_json::Value>) {
-        // TODO: Improve error handling if responding fails.
-        self.writer
+    /// Sends the response to the client.
+    ///
+    /// Requests that were cancelled or whose document changed in the meantime are answered with
+    /// `RequestCancelled` or `ContentModified` errors instead.
+    pub fn respond(self, response: Result<serde_json::Value, ResponseError>) {
+        let state = self
+            .pending_requests
             .lock()
             .unwrap()
-            .write_packet(match response {
-                Ok(result) => {
-                    json!({ "jsonrpc": "2.0", "id": self.id, "result": result}).to_string()
-                }
-                Err(error) => json!({ "jsonrpc": "2.0", "id": self.id, "error": error}).to_string(),
-            })
-            .unwrap();
+            .remove(&self.id)
+            .map_or(RequestState::Running, |r| r.state);
+        let response = match state {
+            RequestState::Running => response,
+            RequestState::Cancelled => Err(ResponseError::new(
+                ErrorCode::RequestCancelled,
+                "request was cancelled",
+            )),
+            RequestState::ContentModified => Err(ResponseError::new(
+                ErrorCode::ContentModified,
+                "document was modified",
+            )),
+        };
+        write_response(&self.writer, json!(self.id), response);
+    }
+
+    /// Returns true if the result is not needed anymore, because the client cancelled the request
+    /// or changed the document. Long running handlers should check it and stop early.
+    pub fn is_cancelled(&self) -> bool {
+        self.pending_requests
+            .lock()
+            .unwrap()
+            .get(&self.id)
+            .map_or(false, |r| r.state != RequestState::Running)
     }
 }
 
 /// The LspSender allows to send messages (requests and notification) to the client.
+///
+/// Clones share the request ids, so they can be used from multiple threads.
+#[derive(Clone)]
 pub struct LspSender {
     next_id: Arc<Mutex<Counter>>,
     writer: Arc<Mutex<dyn Write + Send>>,
@@ -79,55 +160,42 @@ pub struct LspSender {
 
 impl LspSender {
     pub fn send_notification(&self, method: &str, params: serde_json::Value) {
-        // TODO: how to properly handle errors here?
-        self.writer
-            .lock()
-            .unwrap()
-            .write_packet(
-                json!(
-                {
-                    "jsonrpc": "2.0",
-                    "method": method,
-                    "params": params}
-                )
-                .to_string(),
-            )
-            .unwrap();
+        let packet = json!({ "jsonrpc": "2.0", "method": method, "params": params});
+        if let Err(e) = write_packet(&self.writer, packet) {
+            eprintln!("Failed to send notification {}: {}", method, e);
+        }
     }
 
+    /// Sends the request and waits for the response of the client.
     pub fn send_request(
         &self,
         method: &str,
         params: serde_json::Value,
-    ) -> Result<serde_json::Value, serde_json::Value> {
+    ) -> Result<serde_json::Value, ResponseError> {
+        let connection_closed =
+            || ResponseError::new(ErrorCode::InternalError, "connection closed");
         let running_requests = match self.running_requests.upgrade() {
             Some(x) => x,
-            None => panic!("failed to upgrade running_requests"),
+            None => return Err(connection_closed()),
         };
         let id: Id = Id::Number(self.next_id.lock().unwrap().next());
         let (sender, receiver) = channel();
         running_requests.lock().unwrap().insert(id.clone(), sender);
-        // TODO: how to properly handle errors here?
-        self.writer
-            .lock()
-            .unwrap()
-            .write_packet(
-                json!(
-                {
-                    "jsonrpc": "2.0",
-                    "id": id,
-                    "method": method,
-                    "params": params}
-                )
-                .to_string(),
-            )
-            .unwrap();
-        return receiver.recv().unwrap();
+        let packet = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        if let Err(e) = write_packet(&self.writer, packet) {
+            running_requests.lock().unwrap().remove(&id);
+            return Err(ResponseError::new(
+                ErrorCode::InternalError,
+                &format!("failed to send request: {}", e),
+            ));
+        }
+        return receiver.recv().unwrap_or_else(|_| Err(connection_closed()));
     }
 }
 
-type ResultOrError = Result<serde_json::Value, serde_json::Value>;
+type ResultOrError = Result<serde_json::Value, ResponseError>;
 type MyMap = HashMap<Id, Sender<ResultOrError>>;
+type PendingRequests = HashMap<Id, PendingRequest>;
 
 #[derive(Debug, PartialEq, Clone, Hash, Eq, Deserialize, Serialize)]
 #[serde(untagged)]
@@ -136,6 +204,20 @@ enum Id {
     String(String),
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum RequestState {
+    Running,
+    Cancelled,
+    ContentModified,
+}
+
+// Request received from the client that was not answered yet.
+struct PendingRequest {
+    state: RequestState,
+    // URI of the document that the request is about, if any.
+    document: Option<String>,
+}
+
 /// Basic implementation of LSP Server.
 ///
 /// Server is responsible for abstracting the communication between the client and the server. The
@@ -143,17 +225,23 @@ enum Id {
 /// * serializes and deserializes packets into proper LSP Messages,
 /// * hides the concept of request ID by providing APIs to send new requests, reply to messages
 ///   and receive responses,
+/// * answers malformed messages with JSON-RPC errors,
+/// * tracks `$/cancelRequest` notifications and document changes, see
+///   `ResponseHandle::is_cancelled`,
 /// * handles the `exit` notification, to stop the iterator from receiving any more messages.
 ///
-/// It exits after receiving `exit` notification and forwards all other requests and responses to
-/// handler passed in run method.
+/// Packets are read on a separate thread, so responses from the client and cancellations are
+/// processed while a message is being handled.
 ///
-/// TODO: Server also returns when error is encountered, but errors are not properly reported yet.
+/// It exits after receiving `exit` notification or when reading fails, and forwards all other
+/// requests and notifications to handler passed in run method.
 pub struct Server<R: Read, W: Write> {
-    reader: R,
+    receiver: Receiver<Message>,
     writer: Arc<Mutex<W>>,
     // Map of requests that are currently waiting for the response from client.
     running_requests: Arc<Mutex<MyMap>>,
+    pending_requests: Arc<Mutex<PendingRequests>>,
+    reader: PhantomData<R>,
 }
 
 impl<R, W> Iterator for Server<R, W>
@@ -165,70 +253,52 @@ where
 
     fn next(&mut self) -> Option<Message> {
         loop {
-            let packet = match self.reader.read_packet() {
-                Ok(packet) => packet,
-                // TODO: Save the error
-                Err(_) => return None,
-            };
-            let json: serde_json::Value = match serde_json::from_str(&packet) {
-                Ok(value) => value,
-                // TODO: We should probably reply with error?
-                Err(_) => return None,
-            };
-            match &json {
-                serde_json::Value::Object(map) => {
-                    if let Some(serde_json::Value::String(method)) = map.get("method") {
-                        if method == "exit" {
-                            return None;
-                        }
-                        if let Some(id_val) = map.get("id") {
-                            let id: Id = serde_json::from_value(id_val.clone()).unwrap();
-                            return Some(Message::Request(Request {
-                                method: method.to_string(),
-                                params: json["params"].clone(),
-                                response_handle: ResponseHandle {
-                                    id: id,
-                                    writer: self.writer.clone(),
-                                },
-                            }));
-                        }
-                        return Some(Message::Notification(Notification {
-                            method: method.to_string(),
-                            params: json["params"].clone(),
-                        }));
+            let message = self.receiver.recv().ok()?;
+            // Requests that were cancelled before they were handled are answered right away.
+            if let Message::Request(request) = &message {
+                if request.response_handle.is_cancelled() {
+                    if let Message::Request(request) = message {
+                        request.response_handle.respond(Ok(serde_json::Value::Null));
                     }
-                    if let Some(result) = map.get("result") {
-                        if let Some(id_val) = map.get("id") {
-                            let id: Id = serde_json::from_value(id_val.clone()).unwrap();
-                            self.running_requests.lock().unwrap()[&id]
-                                .send(Ok(result.clone()))
-                                .unwrap();
-                            continue;
-                        }
-                    }
-                    // TODO: I think we should just respond with error here.
-                    return None;
+                    continue;
                 }
-                // TODO: I think we should just respond with error here.
-                _ => return None,
             }
+            return Some(message);
         }
     }
 }
 
 impl<R, W> Server<R, W>
 where
-    R: Read,
+    R: Read + Send + 'static,
     W: Write + Send + 'static,
 {
     pub fn new(reader: R, writer: W) -> Server<R, W> {
-        return Server {
-            reader: reader,
+        let (sender, receiver) = channel();
+        let server = Server {
+            receiver: receiver,
             writer: Arc::new(Mutex::new(writer)),
             running_requests: Arc::new(Mutex::new(HashMap::new())),
+            pending_requests: Arc::new(Mutex::new(HashMap::new())),
+            reader: PhantomData,
         };
+        let mut messages = MessageReader {
+            reader: reader,
+            sender: sender,
+            writer: server.writer.clone(),
+            running_requests: server.running_requests.clone(),
+            pending_requests: server.pending_requests.clone(),
+        };
+        thread::spawn(move || messages.run());
+        return server;
     }
+}
 
+impl<R, W> Server<R, W>
+where
+    R: Read,
+    W: Write + Send + 'static,
+{
     pub fn sender(&self) -> LspSender {
         return LspSender {
             next_id: Arc::new(Mutex::new(Counter::new())),
@@ -238,6 +308,185 @@ where
     }
 }
 
+// Reads packets and forwards requests and notifications to the server.
+struct MessageReader<R: Read> {
+    reader: R,
+    sender: Sender<Message>,
+    writer: Arc<Mutex<dyn Write + Send>>,
+    running_requests: Arc<Mutex<MyMap>>,
+    pending_requests: Arc<Mutex<PendingRequests>>,
+}
+
+impl<R: Read> MessageReader<R> {
+    // Returns when reading fails, after the `exit` notification or when the server is dropped.
+    fn run(&mut self) {
+        loop {
+            let packet = match self.reader.read_packet() {
+                Ok(packet) => packet,
+                Err(_) => return,
+            };
+            let message = match serde_json::from_str(&packet) {
+                Ok(serde_json::Value::Object(message)) => message,
+                Ok(_) => {
+                    self.error(json!(null), ErrorCode::InvalidRequest, "expected an object");
+                    continue;
+                }
+                Err(e) => {
+                    self.error(json!(null), ErrorCode::ParseError, &e.to_string());
+                    continue;
+                }
+            };
+            let params = message.get("params").cloned().unwrap_or(json!(null));
+            let id = message.get("id").cloned();
+            let result = match (message.get("method"), id) {
+                (Some(serde_json::Value::String(method)), None) => {
+                    if method == "exit" {
+                        return;
+                    }
+                    self.notification(method, params)
+                }
+                (Some(serde_json::Value::String(method)), Some(id)) => {
+                    self.request(method, params, id)
+                }
+                (None, Some(id)) if message.contains_key("result") => {
+                    self.response(id, Ok(message["result"].clone()));
+                    Ok(())
+                }
+                (None, Some(id)) if message.contains_key("error") => {
+                    let error =
+                        serde_json::from_value(message["error"].clone()).unwrap_or_else(|_| {
+                            ResponseError::new(ErrorCode::InternalError, "invalid error")
+                        });
+                    self.response(id, Err(error));
+                    Ok(())
+                }
+                (_, id) => {
+                    let id = id.unwrap_or(json!(null));
+                    self.error(id, ErrorCode::InvalidRequest, "expected a request");
+                    Ok(())
+                }
+            };
+            if result.is_err() {
+                // The server was dropped.
+                return;
+            }
+        }
+    }
+
+    fn notification(&self, method: &str, params: serde_json::Value) -> Result<(), ()> {
+        match method {
+            "$/cancelRequest" => {
+                if let Ok(id) = serde_json::from_value::<Id>(params["id"].clone()) {
+                    self.mark_pending(|i, _| *i == id, RequestState::Cancelled);
+                }
+                return Ok(());
+            }
+            "textDocument/didChange" => {
+                if let Some(uri) = params["textDocument"]["uri"].as_str() {
+                    self.mark_pending(
+                        |_, r| r.document.as_deref() == Some(uri),
+                        RequestState::ContentModified,
+                    );
+                }
+            }
+            _ => {}
+        }
+        self.sender
+            .send(Message::Notification(Notification {
+                method: method.to_string(),
+                params: params,
+            }))
+            .map_err(|_| ())
+    }
+
+    fn request(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+        id: serde_json::Value,
+    ) -> Result<(), ()> {
+        let id: Id = match serde_json::from_value(id) {
+            Ok(id) => id,
+            Err(_) => {
+                self.error(json!(null), ErrorCode::InvalidRequest, "invalid id");
+                return Ok(());
+            }
+        };
+        self.pending_requests.lock().unwrap().insert(
+            id.clone(),
+            PendingRequest {
+                state: RequestState::Running,
+                document: params["textDocument"]["uri"]
+                    .as_str()
+                    .map(|s| s.to_string()),
+            },
+        );
+        self.sender
+            .send(Message::Request(Request {
+                method: method.to_string(),
+                params: params,
+                response_handle: ResponseHandle {
+                    id: id,
+                    writer: self.writer.clone(),
+                    pending_requests: self.pending_requests.clone(),
+                },
+            }))
+            .map_err(|_| ())
+    }
+
+    // Passes the response of the client to the sender of the request.
+    fn response(&self, id: serde_json::Value, response: ResultOrError) {
+        let sender = serde_json::from_value(id)
+            .ok()
+            .and_then(|id| self.running_requests.lock().unwrap().remove(&id));
+        match sender {
+            Some(sender) => {
+                // The sender might not wait for the response anymore.
+                let _ = sender.send(response);
+            }
+            None => eprintln!("Received response to unknown request"),
+        }
+    }
+
+    fn mark_pending<F>(&self, matches: F, state: RequestState)
+    where
+        F: Fn(&Id, &PendingRequest) -> bool,
+    {
+        for (id, request) in self.pending_requests.lock().unwrap().iter_mut() {
+            if request.state == RequestState::Running && matches(id, request) {
+                request.state = state;
+            }
+        }
+    }
+
+    fn error(&self, id: serde_json::Value, code: ErrorCode, message: &str) {
+        write_response(&self.writer, id, Err(ResponseError::new(code, message)));
+    }
+}
+
+fn write_response(
+    writer: &Arc<Mutex<dyn Write + Send>>,
+    id: serde_json::Value,
+    response: Result<serde_json::Value, ResponseError>,
+) {
+    let packet = match response {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result}),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error}),
+    };
+    if let Err(e) = write_packet(writer, packet) {
+        eprintln!("Failed to send response: {}", e);
+    }
+}
+
+// Writes the message without panicking, which would poison the lock of the writer for other
+// threads.
+fn write_packet(
+    writer: &Arc<Mutex<dyn Write + Send>>,
+    packet: serde_json::Value,
+) -> Result<(), io::Error> {
+    writer.lock().unwrap().write_packet(packet.to_string())
+}
+
 struct Counter {
     id: i64,
 }
@@ -289,8 +538,9 @@ mod tests {
 
     impl Write for FakeWriter {
         fn write_packet(&self, packet: String) -> Result<(), io::Error> {
-            self.sender.send(packet).unwrap();
-            Ok(())
+            self.sender
+                .send(packet)
+                .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client disconnected"))
         }
     }
 
@@ -468,4 +718,136 @@ mod tests {
         t.join().unwrap();
         t2.join().unwrap();
     }
+
+    #[test]
+    fn server_responds_with_error_to_malformed_messages() {
+        let (client, server) = create_client_and_server();
+
+        client.sender.send("{".to_string()).unwrap();
+        client.send(json!([1, 2])).unwrap();
+        client.send(json!({"jsonrpc": "2.0", "id": 1})).unwrap();
+        client.send(exit_notification()).unwrap();
+
+        assert_eq!(server.count(), 0);
+        let errors: Vec<(serde_json::Value, serde_json::Value)> = (0..3)
+            .map(|_| client.recv().unwrap())
+            .map(|response| (response["id"].clone(), response["error"]["code"].clone()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (json!(null), json!(-32700)),
+                (json!(null), json!(-32600)),
+                (json!(1), json!(-32600)),
+            ]
+        );
+    }
+
+    #[test]
+    fn server_receives_errors_from_client() {
+        let (client, server) = create_client_and_server();
+        let sender = server.sender();
+        let t = std::thread::spawn(move || {
+            let res = sender.send_request("someMethod", json!({}));
+            assert_eq!(
+                res,
+                Err(ResponseError {
+                    code: -32601,
+                    message: "unknown".to_string(),
+                    data: None,
+                })
+            );
+        });
+
+        assert_eq!(client.recv().unwrap()["id"], json!(1));
+        client
+            .send(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "error": {"code": -32601, "message": "unknown"},
+            }))
+            .unwrap();
+        t.join().unwrap();
+        std::mem::drop(server);
+    }
+
+    #[test]
+    fn server_survives_write_errors() {
+        let (writer_ch, writer) = FakeWriter::new();
+        let (reader_ch, reader) = FakeReader::new();
+        std::mem::drop(writer_ch);
+        let server = Server::new(reader, writer);
+        let sender = server.sender();
+
+        sender.send_notification("someMethod", json!({}));
+        let expected = Err(ResponseError::new(
+            ErrorCode::InternalError,
+            "failed to send request: client disconnected",
+        ));
+        assert_eq!(sender.send_request("someMethod", json!({})), expected);
+        // The writer is still usable after a failed write.
+        assert_eq!(sender.send_request("someMethod", json!({})), expected);
+        std::mem::drop(server);
+        std::mem::drop(reader_ch);
+    }
+
+    fn request(id: i64) -> serde_json::Value {
+        return json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "textDocument/hover",
+            "params": {"textDocument": {"uri": "file:///a.vim"}},
+        });
+    }
+
+    fn notification(method: &str, params: serde_json::Value) -> serde_json::Value {
+        return json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        });
+    }
+
+    #[test]
+    fn server_responds_with_error_to_cancelled_requests() {
+        let (client, mut server) = create_client_and_server();
+
+        client.send(request(1)).unwrap();
+        let request = match server.next().unwrap() {
+            Message::Request(r) => r,
+            _ => panic!("invalid message received, want request"),
+        };
+        assert!(!request.response_handle.is_cancelled());
+        client
+            .send(notification("$/cancelRequest", json!({"id": 1})))
+            .unwrap();
+        // Notifications are forwarded in order, so the cancellation was already processed.
+        client.send(notification("someMethod", json!({}))).unwrap();
+        server.next().unwrap();
+
+        assert!(request.response_handle.is_cancelled());
+        request.response_handle.respond(Ok(json!({})));
+        assert_eq!(client.recv().unwrap()["error"]["code"], json!(-32800));
+    }
+
+    #[test]
+    fn server_responds_with_error_when_document_changes() {
+        let (client, mut server) = create_client_and_server();
+
+        client.send(request(1)).unwrap();
+        let request = match server.next().unwrap() {
+            Message::Request(r) => r,
+            _ => panic!("invalid message received, want request"),
+        };
+        client
+            .send(notification(
+                "textDocument/didChange",
+                json!({"textDocument": {"uri": "file:///a.vim"}, "contentChanges": []}),
+            ))
+            .unwrap();
+        server.next().unwrap();
+
+        request.response_handle.respond(Ok(json!({})));
+        assert_eq!(client.recv().unwrap()["error"]["code"], json!(-32801));
+    }
 }
@@ -0,0 +1,330 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::builtins;
+use crate::conversion::position_to_source_position;
+use crate::hover::comments_above;
+use crate::lexer::Lexer;
+use crate::lexer::Token;
+use crate::lexer::TokenType;
+use crate::parser::Parser;
+use crate::visitor::walk_expr;
+use crate::visitor::walk_program;
+use crate::visitor::walk_stmt;
+use crate::visitor::Visitor;
+use lsp_types::Documentation;
+use lsp_types::ParameterInformation;
+use lsp_types::ParameterLabel;
+use lsp_types::Position;
+use lsp_types::SignatureHelp;
+use lsp_types::SignatureInformation;
+
+pub fn signature_help(source: &str, pos: Position) -> Option<SignatureHelp> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+    let offset = parser.l.source_offset(&position_to_source_position(&pos));
+    let tokens = parser.tokens();
+
+    let mut calls = Calls {
+        tokens: tokens,
+        calls: Vec::new(),
+        functions: Vec::new(),
+    };
+    walk_program(&mut calls, &program);
+
+    // Calls are collected in pre-order, so the last call that contains the offset is the
+    // innermost one.
+    let (name, active_parameter) = calls.calls.iter().rev().find_map(|(name, open)| {
+        active_parameter(tokens, *open, offset).map(|active| (*name, active))
+    })?;
+
+    let (signature, variadic) = match calls
+        .functions
+        .iter()
+        .find(|(_, f)| f.name.to_string() == name)
+    {
+        Some((stmt, function)) => (user_signature(source, stmt, function), function.variadic),
+        None => {
+            let function = builtins::find_function(name)?;
+            (builtin_signature(function), function.variadic())
+        }
+    };
+    let parameter_count = signature.parameters.as_ref().map_or(0, |p| p.len());
+    let active_parameter = if variadic && parameter_count > 0 {
+        active_parameter.min(parameter_count - 1)
+    } else {
+        active_parameter
+    };
+
+    Some(SignatureHelp {
+        signatures: vec![signature],
+        active_signature: Some(0),
+        active_parameter: Some(active_parameter as u32),
+    })
+}
+
+// Collects all calls (with the index of their opening parenthesis) and function definitions.
+struct Calls<'a> {
+    tokens: &'a [Token],
+    calls: Vec<(&'a str, usize)>,
+    functions: Vec<(&'a Stmt, &'a FunctionStatement)>,
+}
+
+impl<'a> Calls<'a> {
+    // Returns the index of the first `(` that starts at or after given byte offset.
+    fn parenthesis_after(&self, offset: usize) -> Option<usize> {
+        self.tokens.iter().position(|t| {
+            t.token_type == TokenType::LeftParenthesis && t.location.range.start >= offset
+        })
+    }
+}
+
+impl<'a> Visitor<'a> for Calls<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Call(s) => {
+                if let Some(open) = self.parenthesis_after(stmt.span.start.0 as usize) {
+                    self.calls.push((&s.name, open));
+                }
+            }
+            StmtKind::Function(f) => self.functions.push((stmt, f)),
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Function(e) = &expr.kind {
+            if let ExprKind::Identifier(callee) = &e.callee.kind {
+                if let Some(open) = self.parenthesis_after(e.callee.span.end.0 as usize) {
+                    self.calls.push((&callee.name, open));
+                }
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+// Returns the index of the argument at given offset, or None if the offset is not between the
+// parentheses of the call. Calls that are not terminated yet extend to the end of the statement.
+fn active_parameter(tokens: &[Token], open: usize, offset: usize) -> Option<usize> {
+    if offset < tokens[open].location.range.end {
+        return None;
+    }
+    let mut depth = 0;
+    let mut commas = 0;
+    for token in &tokens[open + 1..] {
+        let start = token.location.range.start;
+        match token.token_type {
+            TokenType::LeftParenthesis | TokenType::LeftBracket | TokenType::LeftCurlyBrace => {
+                depth += 1
+            }
+            TokenType::RightParenthesis if depth == 0 => {
+                return if offset <= start { Some(commas) } else { None };
+            }
+            TokenType::RightParenthesis | TokenType::RightBracket | TokenType::RightCurlyBrace => {
+                depth -= 1
+            }
+            t if Parser::end_of_statement_token(t) => {
+                return if offset <= start { Some(commas) } else { None };
+            }
+            TokenType::Comma if depth == 0 && start < offset => commas += 1,
+            _ => {}
+        }
+    }
+    Some(commas)
+}
+
+fn user_signature(source: &str, stmt: &Stmt, function: &FunctionStatement) -> SignatureInformation {
+    let mut arguments: Vec<String> = function
+        .arguments
+        .iter()
+        .map(|argument| match &argument.default_value {
+            Some(value) => format!(
+                "{} = {}",
+                argument.name,
+                &source[value.span.start.0 as usize..value.span.end.0 as usize]
+            ),
+            None => argument.name.clone(),
+        })
+        .collect();
+    if function.variadic {
+        arguments.push("...".to_string());
+    }
+
+    let mut label = format!("{}(", function.name);
+    let mut parameters = Vec::new();
+    for (i, argument) in arguments.iter().enumerate() {
+        if i > 0 {
+            label.push_str(", ");
+        }
+        let start = label.len();
+        label.push_str(argument);
+        parameters.push(parameter(&label, start, label.len()));
+    }
+    label.push(')');
+
+    let comments = comments_above(source, stmt.span.start.0 as usize);
+    SignatureInformation {
+        label: label,
+        documentation: if comments.is_empty() {
+            None
+        } else {
+            Some(Documentation::String(comments.join("\n")))
+        },
+        parameters: Some(parameters),
+        active_parameter: None,
+    }
+}
+
+fn builtin_signature(function: &builtins::BuiltinFunction) -> SignatureInformation {
+    let label = function.signature();
+    let mut parameters = Vec::new();
+    let mut start = function.name.len();
+    for name in function.parameters() {
+        if let Some(i) = label[start..].find(name) {
+            start += i;
+            parameters.push(parameter(&label, start, start + name.len()));
+            start += name.len();
+        }
+    }
+
+    SignatureInformation {
+        label: label,
+        documentation: Some(Documentation::String(function.help.to_string())),
+        parameters: Some(parameters),
+        active_parameter: None,
+    }
+}
+
+// Returns the parameter at given byte offsets of the label. Clients expect the offsets in UTF-16
+// code units.
+fn parameter(label: &str, start: usize, end: usize) -> ParameterInformation {
+    let offset = |end: usize| label[..end].encode_utf16().count() as u32;
+    ParameterInformation {
+        label: ParameterLabel::LabelOffsets([offset(start), offset(end)]),
+        documentation: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn help(source: &str, line: u32, character: u32) -> Option<(String, u32)> {
+        let help = signature_help(
+            source,
+            Position {
+                line: line,
+                character: character,
+            },
+        )?;
+        Some((
+            help.signatures[0].label.clone(),
+            help.active_parameter.unwrap(),
+        ))
+    }
+
+    #[test]
+    fn shows_builtin_signature_with_active_parameter() {
+        let source = "call get(l:list, 1)";
+        assert_eq!(
+            help(source, 0, 9),
+            Some(("get({list}, {idx} [, {default}])".to_string(), 0))
+        );
+        assert_eq!(
+            help(source, 0, 17),
+            Some(("get({list}, {idx} [, {default}])".to_string(), 1))
+        );
+        assert_eq!(help(source, 0, 19), None);
+    }
+
+    #[test]
+    fn returns_offsets_of_builtin_parameters() {
+        let help = signature_help(
+            "call get(",
+            Position {
+                line: 0,
+                character: 9,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            help.signatures[0].parameters.as_ref().unwrap()[1].label,
+            ParameterLabel::LabelOffsets([12, 17])
+        );
+    }
+
+    #[test]
+    fn shows_innermost_call_in_unterminated_expression() {
+        let source = "let x = get(l:list, len(l:other, ";
+        assert_eq!(help(source, 0, 33), Some(("len({expr})".to_string(), 1)));
+        assert_eq!(
+            help(source, 0, 15),
+            Some(("get({list}, {idx} [, {default}])".to_string(), 0))
+        );
+    }
+
+    #[test]
+    fn ignores_commas_in_nested_lists() {
+        assert_eq!(
+            help("call add([1, 2], 3)", 0, 18),
+            Some(("add({object}, {expr})".to_string(), 1))
+        );
+    }
+
+    #[test]
+    fn shows_user_function_with_optional_and_variadic_arguments() {
+        let source = "\" Joins the parts.
+function! s:Join(sep, first = '', ...) abort
+endfunction
+call s:Join(',', 'a', 'b', 'c')";
+        let help = signature_help(
+            source,
+            Position {
+                line: 3,
+                character: 29,
+            },
+        )
+        .unwrap();
+        assert_eq!(help.signatures[0].label, "s:Join(sep, first = '', ...)");
+        assert_eq!(
+            help.signatures[0].documentation,
+            Some(Documentation::String("Joins the parts.".to_string()))
+        );
+        assert_eq!(help.active_parameter, Some(2));
+    }
+
+    #[test]
+    fn returns_offsets_of_parameters_in_utf16_code_units() {
+        let source = "function! s:Wrap(left = '😀', right = '»') abort
+endfunction
+call s:Wrap('[', ";
+        let help = signature_help(
+            source,
+            Position {
+                line: 2,
+                character: 17,
+            },
+        )
+        .unwrap();
+        assert_eq!(help.active_parameter, Some(1));
+        assert_eq!(
+            help.signatures[0].parameters.as_ref().unwrap()[1].label,
+            ParameterLabel::LabelOffsets([20, 31])
+        );
+    }
+}
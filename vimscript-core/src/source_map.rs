@@ -14,9 +14,13 @@
 
 use lsp_types::Url;
 use std::collections::HashMap;
+use std::sync::Arc;
 
+/// Documents opened by the client, with the version of their last change.
+// Contents are shared between clones, so that snapshots of open documents are cheap.
+#[derive(Clone)]
 pub struct SourceMap {
-    files: HashMap<Url, String>,
+    files: HashMap<Url, (Arc<String>, i32)>,
 }
 
 impl SourceMap {
@@ -26,11 +30,23 @@ impl SourceMap {
         }
     }
 
-    pub fn add(&mut self, uri: &Url, content: String) {
-        self.files.insert(uri.clone(), content);
+    pub fn add(&mut self, uri: &Url, content: String, version: i32) {
+        self.files.insert(uri.clone(), (Arc::new(content), version));
+    }
+
+    pub fn remove(&mut self, uri: &Url) {
+        self.files.remove(uri);
+    }
+
+    pub fn contains(&self, uri: &Url) -> bool {
+        self.files.contains_key(uri)
     }
 
     pub fn get_content(&self, uri: &Url) -> Option<String> {
-        Some(self.files.get(uri)?.to_string())
+        Some(self.files.get(uri)?.0.to_string())
+    }
+
+    pub fn version(&self, uri: &Url) -> Option<i32> {
+        Some(self.files.get(uri)?.1)
     }
 }
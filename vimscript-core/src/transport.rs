@@ -0,0 +1,106 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Connections to clients over byte streams, e.g. stdio or sockets.
+use crate::protocol::MessageReader;
+use crate::protocol::MessageWriter;
+use crate::server::Read;
+use crate::server::Write;
+use std::io;
+use std::io::BufReader;
+use std::sync::Mutex;
+
+/// Reads messages from a stream, e.g. stdin or a socket.
+pub struct StreamReader<R: io::Read> {
+    input: MessageReader<BufReader<R>>,
+}
+
+impl<R: io::Read> StreamReader<R> {
+    pub fn new(input: R) -> StreamReader<R> {
+        StreamReader {
+            input: MessageReader::new(BufReader::new(input)),
+        }
+    }
+}
+
+impl<R: io::Read> Read for StreamReader<R> {
+    fn read_packet(&mut self) -> Result<String, io::Error> {
+        self.input.read_message()
+    }
+}
+
+/// Writes messages to a stream, e.g. stdout or a socket.
+pub struct StreamWriter<W: io::Write> {
+    output: Mutex<MessageWriter<W>>,
+}
+
+impl<W: io::Write> StreamWriter<W> {
+    pub fn new(output: W) -> StreamWriter<W> {
+        StreamWriter {
+            output: Mutex::new(MessageWriter::new(output)),
+        }
+    }
+}
+
+impl<W: io::Write> Write for StreamWriter<W> {
+    fn write_packet(&self, packet: String) -> Result<(), io::Error> {
+        self.output.lock().unwrap().write_message(&packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsp::run;
+    use crate::server::Server;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use std::net::TcpListener;
+    use std::net::TcpStream;
+    use std::thread;
+
+    #[test]
+    fn serves_client_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let t = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let reader = StreamReader::new(stream.try_clone().unwrap());
+            run(Server::new(reader, StreamWriter::new(stream)))
+        });
+
+        let stream = TcpStream::connect(address).unwrap();
+        let mut client = StreamReader::new(stream.try_clone().unwrap());
+        let output = StreamWriter::new(stream);
+        for message in &[
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "initialize",
+                "params": {"processId": null, "rootUri": null, "capabilities": {}},
+            }),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"}),
+        ] {
+            output.write_packet(message.to_string()).unwrap();
+            let response: serde_json::Value =
+                serde_json::from_str(&client.read_packet().unwrap()).unwrap();
+            assert_eq!(response["id"], message["id"]);
+        }
+        output
+            .write_packet(json!({"jsonrpc": "2.0", "method": "exit"}).to_string())
+            .unwrap();
+
+        assert_eq!(t.join().unwrap(), 0);
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ast::*;
+use crate::parser::Program;
+
+/// Visitor allows to traverse the AST.
+///
+/// Default implementations of all methods visit all children of the node, so implementations
+/// should call the corresponding `walk_*` function if they want to continue the traversal after
+/// overriding a method.
+pub trait Visitor<'a>: Sized {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        walk_expr(self, expr);
+    }
+}
+
+pub fn walk_program<'a, V: Visitor<'a>>(visitor: &mut V, program: &'a Program) {
+    walk_stmts(visitor, &program.statements);
+}
+
+pub fn walk_stmts<'a, V: Visitor<'a>>(visitor: &mut V, stmts: &'a [Stmt]) {
+    for stmt in stmts {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<'a, V: Visitor<'a>>(visitor: &mut V, stmt: &'a Stmt) {
+    match &stmt.kind {
+        StmtKind::Let(s) => {
+            visitor.visit_expr(&s.var);
+            visitor.visit_expr(&s.value);
+        }
+        StmtKind::Call(s) => {
+            for arg in &s.arguments {
+                visitor.visit_expr(arg);
+            }
+        }
+        StmtKind::Execute(s) => {
+            for arg in &s.arguments {
+                visitor.visit_expr(arg);
+            }
+        }
+        StmtKind::Return(s) => {
+            if let Some(value) = &s.value {
+                visitor.visit_expr(value);
+            }
+        }
+        StmtKind::If(s) => walk_if_statement(visitor, s),
+        StmtKind::While(s) => {
+            visitor.visit_expr(&s.condition);
+            walk_stmts(visitor, &s.body);
+        }
+        StmtKind::Function(s) => walk_stmts(visitor, &s.body),
+        StmtKind::For(s) => {
+            visitor.visit_expr(&s.range);
+            walk_stmts(visitor, &s.body);
+        }
+        StmtKind::Try(s) => {
+            walk_stmts(visitor, &s.body);
+            if let Some(finally) = &s.finally {
+                walk_stmts(visitor, finally);
+            }
+        }
+        StmtKind::Augroup(s) => walk_stmts(visitor, &s.body),
+        StmtKind::Set(_)
+        | StmtKind::Break(_)
+        | StmtKind::Finish(_)
+        | StmtKind::Autocmd(_)
+        | StmtKind::Command(_)
+        | StmtKind::Map(_)
+        | StmtKind::Empty() => {}
+    }
+}
+
+fn walk_if_statement<'a, V: Visitor<'a>>(visitor: &mut V, stmt: &'a IfStatement) {
+    visitor.visit_expr(&stmt.condition);
+    walk_stmts(visitor, &stmt.then);
+    match &stmt.else_cond {
+        ElseCond::None => {}
+        ElseCond::Else(stmts) => walk_stmts(visitor, stmts),
+        ElseCond::ElseIf(stmt) => walk_if_statement(visitor, stmt),
+    }
+}
+
+pub fn walk_expr<'a, V: Visitor<'a>>(visitor: &mut V, expr: &'a Expr) {
+    match &expr.kind {
+        ExprKind::Identifier(_) | ExprKind::Number(_) | ExprKind::StringLiteral(_) => {}
+        ExprKind::Infix(e) => {
+            visitor.visit_expr(&e.left);
+            visitor.visit_expr(&e.right);
+        }
+        ExprKind::Function(e) => {
+            visitor.visit_expr(&e.callee);
+            for arg in &e.arguments {
+                visitor.visit_expr(arg);
+            }
+        }
+        ExprKind::ArraySubscript(e) => {
+            visitor.visit_expr(&e.base);
+            match e.idx.as_ref() {
+                ArraySubscript::Index(idx) => visitor.visit_expr(idx),
+                ArraySubscript::Sublist(sublist) => {
+                    if let Some(left) = &sublist.left {
+                        visitor.visit_expr(left);
+                    }
+                    if let Some(right) = &sublist.right {
+                        visitor.visit_expr(right);
+                    }
+                }
+            }
+        }
+        ExprKind::Array(e) => {
+            for element in &e.elements {
+                visitor.visit_expr(element);
+            }
+        }
+        ExprKind::Unary(e) => visitor.visit_expr(&e.expr),
+        ExprKind::Paren(e) => visitor.visit_expr(&e.expr),
+        ExprKind::Member(e) => visitor.visit_expr(&e.base),
+        ExprKind::Choose(e) => {
+            visitor.visit_expr(&e.cond);
+            visitor.visit_expr(&e.lhs);
+            visitor.visit_expr(&e.rhs);
+        }
+        ExprKind::Dictionary(e) => {
+            for entry in &e.entries {
+                visitor.visit_expr(&entry.value);
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Fixed number of threads that run jobs in the order they were queued.
+///
+/// Dropping the pool waits until all queued jobs are finished.
+pub struct WorkerPool {
+    sender: Option<mpsc::Sender<Job>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    pub fn new(size: usize) -> WorkerPool {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..size.max(1))
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || loop {
+                    // The lock is released before the job runs, so other workers can take jobs.
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        // Jobs are expected to handle their panics, this only keeps the worker
+                        // alive for the following jobs.
+                        Ok(job) => {
+                            if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                                eprintln!("Worker job panicked");
+                            }
+                        }
+                        Err(_) => return,
+                    }
+                })
+            })
+            .collect();
+        WorkerPool {
+            sender: Some(sender),
+            workers: workers,
+        }
+    }
+
+    /// Creates a pool with one thread per available CPU.
+    pub fn with_available_parallelism() -> WorkerPool {
+        WorkerPool::new(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        self.sender.as_ref().unwrap().send(Box::new(job)).unwrap();
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // Closing the channel stops workers once the queue is empty.
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                eprintln!("Worker thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::sync::Barrier;
+
+    #[test]
+    fn finishes_queued_jobs_when_dropped() {
+        let results = Arc::new(Mutex::new(Vec::new()));
+        let pool = WorkerPool::new(1);
+        for i in 0..10 {
+            let results = Arc::clone(&results);
+            pool.execute(move || results.lock().unwrap().push(i));
+        }
+        drop(pool);
+        assert_eq!(*results.lock().unwrap(), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn keeps_running_jobs_after_panic() {
+        let results = Arc::new(Mutex::new(Vec::new()));
+        let pool = WorkerPool::new(1);
+        pool.execute(|| panic!("job failed"));
+        let job_results = Arc::clone(&results);
+        pool.execute(move || job_results.lock().unwrap().push(1));
+        drop(pool);
+        assert_eq!(*results.lock().unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn runs_jobs_concurrently() {
+        // Both jobs wait for each other, so this would deadlock on a single thread.
+        let barrier = Arc::new(Barrier::new(2));
+        let pool = WorkerPool::new(2);
+        for _ in 0..2 {
+            let barrier = Arc::clone(&barrier);
+            pool.execute(move || {
+                barrier.wait();
+            });
+        }
+    }
+}
@@ -0,0 +1,6 @@
+function! s:Add(a, b = 1, ...) abort
+  let l:sum = a:a
+  let l:sum += a:b
+  call add(l:list, [l:sum, 1])
+  return l:sum
+endfunction
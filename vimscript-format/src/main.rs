@@ -16,6 +16,10 @@ extern crate vimscript_core;
 
 use std::env;
 use std::fs;
+use std::path::Path;
+use std::process;
+use vimscript_core::config::config_for_file;
+use vimscript_core::config::Config;
 use vimscript_core::format;
 use vimscript_core::lexer::Lexer;
 use vimscript_core::parser::Parser;
@@ -23,7 +27,15 @@ use vimscript_core::parser::Parser;
 fn main() {
     for filename in env::args().skip(1) {
         println!("{}", filename);
-        let contents = fs::read_to_string(filename).expect("Something went wrong reading the file");
+        let contents =
+            fs::read_to_string(&filename).expect("Something went wrong reading the file");
+        let config = match config_for_file(Path::new(&filename), &Config::default()) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
         // TODO: read list of files from the command line
         let mut parser = Parser::new(Lexer::new(&contents));
         let program = parser.parse();
@@ -32,7 +44,7 @@ fn main() {
                 println!("{:?}", error);
             }
         } else {
-            println!("{}", format::format(&program));
+            println!("{}", format::format_with_options(&program, &config.format));
         }
     }
 }
@@ -14,31 +14,255 @@
 
 extern crate vimscript_core;
 
+use std::env;
+#[cfg(unix)]
+use std::fs;
 use std::io;
+use std::net::TcpListener;
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::process;
+use std::thread;
 use vimscript_core::lsp::run;
-use vimscript_core::protocol::read_message;
-use vimscript_core::protocol::write_message;
-use vimscript_core::server::Read;
 use vimscript_core::server::Server;
-use vimscript_core::server::Write;
+use vimscript_core::transport::StreamReader;
+use vimscript_core::transport::StreamWriter;
 
-struct Reader {}
+const USAGE: &str = "\
+Usage: vimscript-language-server [--listen <port> | --connect <host:port> | --pipe <path>]
 
-impl Read for Reader {
-    fn read_packet(&mut self) -> Result<String, io::Error> {
-        read_message(&mut std::io::stdin().lock())
+Communicates with the client over stdio by default.
+
+  --listen <port>        accept clients on a TCP port of localhost
+  --connect <host:port>  connect to a client listening on a TCP port
+  --pipe <path>          accept clients on a Unix socket";
+
+#[derive(PartialEq, Debug)]
+enum Transport {
+    Stdio,
+    Listen(u16),
+    Connect(String),
+    Pipe(String),
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Transport, String> {
+    let flag = match args.next() {
+        Some(flag) => flag,
+        None => return Ok(Transport::Stdio),
+    };
+    if !["--listen", "--connect", "--pipe"].contains(&flag.as_ref()) {
+        return Err(format!("unknown argument: {}", flag));
+    }
+    let value = args
+        .next()
+        .ok_or_else(|| format!("{} requires a value", flag))?;
+    if let Some(arg) = args.next() {
+        return Err(format!("unexpected argument: {}", arg));
     }
+    match flag.as_ref() {
+        "--listen" => match value.parse() {
+            Ok(port) => Ok(Transport::Listen(port)),
+            Err(_) => Err(format!("invalid port: {}", value)),
+        },
+        "--connect" => Ok(Transport::Connect(value)),
+        _ => Ok(Transport::Pipe(value)),
+    }
+}
+
+// Connection to a client, which is read and written from separate threads.
+trait Stream: io::Read + io::Write + Send + Sized + 'static {
+    fn try_clone(&self) -> io::Result<Self>;
 }
 
-struct Writer;
+impl Stream for TcpStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
+}
 
-impl Write for Writer {
-    fn write_packet(&self, packet: String) -> Result<(), io::Error> {
-        write_message(&packet)
+#[cfg(unix)]
+impl Stream for UnixStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        UnixStream::try_clone(self)
     }
 }
 
+fn serve<S: Stream>(stream: S) -> io::Result<i32> {
+    let reader = StreamReader::new(stream.try_clone()?);
+    Ok(run(Server::new(reader, StreamWriter::new(stream))))
+}
+
+// Serves each client on a separate thread with its own state, so that several editors can share
+// the process. Clients are accepted until the process is killed.
+fn listen<S: Stream>(connections: impl Iterator<Item = io::Result<S>>) -> io::Result<i32> {
+    for connection in connections {
+        match connection {
+            Ok(stream) => {
+                thread::spawn(move || {
+                    if let Err(e) = serve(stream) {
+                        eprintln!("Failed to serve client: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("Failed to accept client: {}", e),
+        }
+    }
+    Ok(0)
+}
+
+#[cfg(unix)]
+fn listen_on_pipe(path: &str) -> io::Result<i32> {
+    bind_pipe(path).and_then(|listener| listen(listener.incoming()))
+}
+
+// Binds the socket, replacing the one left behind by a previous process that was killed. Sockets
+// that still accept connections are kept, so that binding fails for them.
+#[cfg(unix)]
+fn bind_pipe(path: &str) -> io::Result<UnixListener> {
+    match UnixListener::bind(path) {
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            let is_socket = fs::symlink_metadata(path)?.file_type().is_socket();
+            if !is_socket || UnixStream::connect(path).is_ok() {
+                return Err(e);
+            }
+            fs::remove_file(path)?;
+            UnixListener::bind(path)
+        }
+        result => result,
+    }
+}
+
+#[cfg(not(unix))]
+fn listen_on_pipe(_path: &str) -> io::Result<i32> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "--pipe is only supported on Unix",
+    ))
+}
+
 fn main() {
-    let server = Server::new(Reader {}, Writer {});
-    run(server);
+    let transport = match parse_args(env::args().skip(1)) {
+        Ok(transport) => transport,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    let result = match transport {
+        Transport::Stdio => Ok(run(Server::new(
+            StreamReader::new(io::stdin()),
+            StreamWriter::new(io::stdout()),
+        ))),
+        Transport::Listen(port) => {
+            TcpListener::bind(("127.0.0.1", port)).and_then(|listener| listen(listener.incoming()))
+        }
+        Transport::Connect(address) => TcpStream::connect(&address).and_then(serve),
+        Transport::Pipe(path) => listen_on_pipe(&path),
+    };
+    match result {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Transport, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_transport_arguments() {
+        assert_eq!(parse(&[]), Ok(Transport::Stdio));
+        assert_eq!(parse(&["--listen", "8080"]), Ok(Transport::Listen(8080)));
+        assert_eq!(
+            parse(&["--connect", "localhost:8080"]),
+            Ok(Transport::Connect("localhost:8080".to_string()))
+        );
+        assert_eq!(
+            parse(&["--pipe", "/tmp/vim.sock"]),
+            Ok(Transport::Pipe("/tmp/vim.sock".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert_eq!(
+            parse(&["--stdio"]),
+            Err("unknown argument: --stdio".to_string())
+        );
+        assert_eq!(
+            parse(&["--listen"]),
+            Err("--listen requires a value".to_string())
+        );
+        assert_eq!(
+            parse(&["--listen", "port"]),
+            Err("invalid port: port".to_string())
+        );
+        assert_eq!(
+            parse(&["--pipe", "a", "b"]),
+            Err("unexpected argument: b".to_string())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serves_client_over_stale_unix_socket() {
+        use vimscript_core::server::Read;
+        use vimscript_core::server::Write;
+
+        let path = env::temp_dir().join(format!("vimscript-ls-{}.sock", process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let _ = fs::remove_file(&path);
+        // A socket of a process that did not clean up.
+        std::mem::drop(UnixListener::bind(&path).unwrap());
+
+        let listener = bind_pipe(&path).unwrap();
+        let t = thread::spawn(move || {
+            let code = serve(listener.accept().unwrap().0).unwrap();
+            (code, listener)
+        });
+
+        let stream = UnixStream::connect(&path).unwrap();
+        let mut client = StreamReader::new(stream.try_clone().unwrap());
+        let output = StreamWriter::new(stream);
+        for (id, message) in &[
+            (
+                1,
+                r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"processId":null,"rootUri":null,"capabilities":{}}}"#,
+            ),
+            (2, r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#),
+        ] {
+            output.write_packet(message.to_string()).unwrap();
+            let response = client.read_packet().unwrap();
+            assert!(
+                response.contains(&format!(r#""id":{}"#, id)),
+                "{}",
+                response
+            );
+        }
+        output
+            .write_packet(r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string())
+            .unwrap();
+
+        let (code, listener) = t.join().unwrap();
+        assert_eq!(code, 0);
+        // Sockets that are in use are not replaced.
+        assert_eq!(
+            bind_pipe(&path).unwrap_err().kind(),
+            io::ErrorKind::AddrInUse
+        );
+        std::mem::drop(listener);
+        let _ = fs::remove_file(&path);
+    }
 }
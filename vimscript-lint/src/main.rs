@@ -14,24 +14,58 @@
 
 extern crate vimscript_core;
 
+use lsp_types::DiagnosticSeverity;
 use std::env;
 use std::fs;
-use syntax::parse;
+use std::path::Path;
+use std::process;
+use vimscript_core::config::config_for_file;
+use vimscript_core::config::Config;
+use vimscript_core::diagnostics::diagnostics;
 
 fn main() {
     let mut total_errors = 0;
     for filename in env::args().skip(1) {
         println!("{}", filename);
-        let contents = fs::read_to_string(filename).expect("Something went wrong reading the file");
-        let mut parsed = parse(&contents);
-        for error in &parsed.errors {
-            println!("{:?}", error);
+        let contents =
+            fs::read_to_string(&filename).expect("Something went wrong reading the file");
+        let config = match config_for_file(Path::new(&filename), &Config::default()) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+        let mut errors = 0;
+        // Rules are configured the same way as in the language server, so that the same errors
+        // are reported in the editor and here.
+        for diagnostic in config.lint.apply(diagnostics(&contents, &config)) {
+            if diagnostic.severity == Some(DiagnosticSeverity::ERROR) {
+                errors += 1;
+            }
+            println!(
+                "{}:{}:{}: {}: {}",
+                filename,
+                diagnostic.range.start.line + 1,
+                diagnostic.range.start.character + 1,
+                severity_name(diagnostic.severity),
+                diagnostic.message
+            );
         }
-        if true {
-            println!("{:#?}", parsed.syntax())
-        }
-        total_errors += parsed.errors.len();
-        println!("\nError count: {}", parsed.errors.len());
+        total_errors += errors;
+        println!("\nError count: {}", errors);
     }
     println!("\n\nTotal error count: {}", total_errors);
+    if total_errors > 0 {
+        process::exit(1);
+    }
+}
+
+fn severity_name(severity: Option<DiagnosticSeverity>) -> &'static str {
+    match severity {
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        Some(DiagnosticSeverity::HINT) => "hint",
+        _ => "error",
+    }
 }